@@ -0,0 +1,38 @@
+#!/bin/bash
+
+# Create a local SROS2 keystore with one enclave per bridge node, to test
+# the bridge against DDS Security (see README "DDS Security (SROS2)").
+
+# Source common utilities
+source "$(dirname "$0")/common.sh"
+
+KEYSTORE="${1:-${ROS_SECURITY_KEYSTORE:-./sros2_keystore}}"
+shift || true
+ENCLAVES=("$@")
+if [ ${#ENCLAVES[@]} -eq 0 ]; then
+    ENCLAVES=("/dora")
+fi
+
+check_ros2
+
+if [ ! -d "$KEYSTORE" ]; then
+    log "Creating keystore in $KEYSTORE..."
+    ros2 security create_keystore "$KEYSTORE"
+else
+    info "Keystore $KEYSTORE already exists"
+fi
+
+for enclave in "${ENCLAVES[@]}"; do
+    if [ -d "$KEYSTORE/enclaves${enclave}" ]; then
+        info "Enclave $enclave already exists"
+        continue
+    fi
+    log "Creating enclave $enclave..."
+    ros2 security create_enclave "$KEYSTORE" "$enclave"
+done
+
+log "Keystore ready, use it with:"
+echo "  export ROS_SECURITY_KEYSTORE=$(realpath "$KEYSTORE")"
+echo "  export ROS_SECURITY_ENABLE=true"
+echo "  export ROS_SECURITY_STRATEGY=Enforce"
+echo "  export ROS_SECURITY_ENCLAVE_OVERRIDE=${ENCLAVES[0]}"
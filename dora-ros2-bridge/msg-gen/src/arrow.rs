@@ -0,0 +1,206 @@
+//! Generates Arrow conversions for message structs.
+//!
+//! The generated code relies on the runtime support in `crate::arrow_convert`
+//! of the crate that includes the generated messages.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+
+use crate::types::{
+    Member, MemberType, Message, NestableType,
+    primitives::{BasicType, GenericString},
+};
+
+/// Generates `ArrowStruct`, `IntoArrow` and `TryFrom<&ArrowData>` impls for `message`.
+pub fn message_arrow_impls(message: &Message, package: &str) -> TokenStream {
+    let struct_name = format_ident!("{}__{}", package, message.name);
+
+    let field_defs = message.members.iter().map(|member| {
+        let name = &member.name;
+        let converter = member_converter(member, package);
+        quote! {
+            arrow::datatypes::Field::new(#name, <#converter as crate::arrow_convert::ArrowConverter>::data_type(), false)
+        }
+    });
+
+    let idents: Vec<_> = message.members.iter().map(field_ident).collect();
+    let converters: Vec<_> = message
+        .members
+        .iter()
+        .map(|m| member_converter(m, package))
+        .collect();
+    let names = message.members.iter().map(|m| &m.name);
+
+    quote! {
+        impl crate::arrow_convert::ArrowStruct for ffi::#struct_name {
+            fn fields() -> arrow::datatypes::Fields {
+                arrow::datatypes::Fields::from(vec![#(#field_defs),*])
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn to_columns(values: Vec<Self>) -> Vec<arrow::array::ArrayRef> {
+                #(let mut #idents = Vec::with_capacity(values.len());)*
+                for value in values {
+                    #(#idents.push(value.#idents);)*
+                }
+                vec![#(<#converters as crate::arrow_convert::ArrowConverter>::to_array(#idents)),*]
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn from_columns(array: &arrow::array::StructArray) -> eyre::Result<Vec<Self>> {
+                #(
+                    let mut #idents = <#converters as crate::arrow_convert::ArrowConverter>::from_array(
+                        crate::arrow_convert::column(array, #names)?.as_ref(),
+                    )?
+                    .into_iter();
+                )*
+                (0..array.len())
+                    .map(|_| {
+                        Ok(Self {
+                            #(#idents: #idents.next().ok_or_else(|| eyre::eyre!("column too short"))?,)*
+                        })
+                    })
+                    .collect()
+            }
+        }
+
+        impl dora_node_api::IntoArrow for ffi::#struct_name {
+            type A = arrow::array::StructArray;
+
+            fn into_arrow(self) -> Self::A {
+                crate::arrow_convert::to_struct_array(vec![self])
+            }
+        }
+
+        impl TryFrom<&dora_node_api::ArrowData> for ffi::#struct_name {
+            type Error = eyre::Report;
+
+            fn try_from(value: &dora_node_api::ArrowData) -> eyre::Result<Self> {
+                crate::arrow_convert::from_single(value.0.as_ref())
+            }
+        }
+
+        impl TryFrom<dora_node_api::ArrowData> for ffi::#struct_name {
+            type Error = eyre::Report;
+
+            fn try_from(value: dora_node_api::ArrowData) -> eyre::Result<Self> {
+                Self::try_from(&value)
+            }
+        }
+    }
+}
+
+/// Converter for `wstring` fields, which are stored as `ffi::U16String`.
+pub fn u16_string_converter() -> TokenStream {
+    quote! {
+        pub struct U16StringConverter;
+
+        impl crate::arrow_convert::ArrowConverter for U16StringConverter {
+            type Item = ffi::U16String;
+
+            fn data_type() -> arrow::datatypes::DataType {
+                <crate::arrow_convert::Utf8 as crate::arrow_convert::ArrowConverter>::data_type()
+            }
+
+            fn to_array(values: Vec<Self::Item>) -> arrow::array::ArrayRef {
+                <crate::arrow_convert::Utf8 as crate::arrow_convert::ArrowConverter>::to_array(
+                    values.into_iter().map(|s| String::from_utf16_lossy(&s.chars)).collect(),
+                )
+            }
+
+            fn from_array(array: &dyn arrow::array::Array) -> eyre::Result<Vec<Self::Item>> {
+                Ok(<crate::arrow_convert::Utf8 as crate::arrow_convert::ArrowConverter>::from_array(array)?
+                    .iter()
+                    .map(|s| ffi::U16String::from_str(s))
+                    .collect())
+            }
+        }
+    }
+}
+
+fn member_converter(member: &Member, package: &str) -> TokenStream {
+    match &member.r#type {
+        MemberType::NestableType(t) => nestable_converter(t, package),
+        MemberType::Array(array) => {
+            let size = array.size;
+            if is_byte(&array.value_type) {
+                quote! { crate::arrow_convert::FixedBinary<#size> }
+            } else {
+                let inner = nestable_converter(&array.value_type, package);
+                quote! { crate::arrow_convert::FixedList<#inner, #size> }
+            }
+        }
+        MemberType::Sequence(sequence) => sequence_converter(&sequence.value_type, package),
+        MemberType::BoundedSequence(sequence) => sequence_converter(&sequence.value_type, package),
+    }
+}
+
+fn sequence_converter(value_type: &NestableType, package: &str) -> TokenStream {
+    if is_byte(value_type) {
+        quote! { crate::arrow_convert::Binary }
+    } else {
+        let inner = nestable_converter(value_type, package);
+        quote! { crate::arrow_convert::List<#inner> }
+    }
+}
+
+fn nestable_converter(t: &NestableType, package: &str) -> TokenStream {
+    match t {
+        NestableType::BasicType(t) => basic_converter(t),
+        NestableType::NamedType(t) => {
+            let name = format_ident!("{}__{}", package, t.0);
+            quote! { crate::arrow_convert::Struct<ffi::#name> }
+        }
+        NestableType::NamespacedType(t) => {
+            let name = format_ident!("{}__{}", t.package, t.name);
+            quote! { crate::arrow_convert::Struct<ffi::#name> }
+        }
+        NestableType::GenericString(s) => match s {
+            GenericString::String | GenericString::BoundedString(_) => {
+                quote! { crate::arrow_convert::Utf8 }
+            }
+            GenericString::WString | GenericString::BoundedWString(_) => {
+                quote! { U16StringConverter }
+            }
+        },
+    }
+}
+
+fn basic_converter(t: &BasicType) -> TokenStream {
+    let arrow_type = match t {
+        BasicType::Bool => return quote! { crate::arrow_convert::Bool },
+        BasicType::I8 => quote! { Int8Type },
+        BasicType::I16 => quote! { Int16Type },
+        BasicType::I32 => quote! { Int32Type },
+        BasicType::I64 => quote! { Int64Type },
+        BasicType::U8 | BasicType::Char | BasicType::Byte => quote! { UInt8Type },
+        BasicType::U16 => quote! { UInt16Type },
+        BasicType::U32 => quote! { UInt32Type },
+        BasicType::U64 => quote! { UInt64Type },
+        BasicType::F32 => quote! { Float32Type },
+        BasicType::F64 => quote! { Float64Type },
+    };
+    quote! { crate::arrow_convert::Primitive<arrow::datatypes::#arrow_type> }
+}
+
+fn is_byte(t: &NestableType) -> bool {
+    matches!(
+        t,
+        NestableType::BasicType(BasicType::U8 | BasicType::Byte)
+    )
+}
+
+fn field_ident(member: &Member) -> Ident {
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+        "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override",
+        "priv", "typeof", "unsized", "virtual", "yield", "try",
+    ];
+    if KEYWORDS.contains(&member.name.as_str()) {
+        Ident::new_raw(&member.name, Span::call_site())
+    } else {
+        format_ident!("{}", member.name)
+    }
+}
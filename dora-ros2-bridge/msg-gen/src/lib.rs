@@ -11,6 +11,7 @@ use std::path::Path;
 
 use quote::quote;
 
+pub mod arrow;
 pub mod parser;
 pub mod types;
 
@@ -26,6 +27,7 @@ where
     let mut message_struct_impls = Vec::new();
     let mut message_topic_defs = Vec::new();
     let mut message_topic_impls = Vec::new();
+    let mut message_arrow_impls = Vec::new();
     let mut service_defs = Vec::new();
     let mut service_impls = Vec::new();
     let mut service_creation_defs = Vec::new();
@@ -46,6 +48,8 @@ where
                 let (topic_def, topic_impl) = message.topic_def(&package.name);
                 message_topic_defs.push(topic_def);
                 message_topic_impls.push(topic_impl);
+            } else {
+                message_arrow_impls.push(arrow::message_arrow_impls(message, &package.name));
             }
         }
 
@@ -53,6 +57,11 @@ where
             let (def, imp) = service.struct_token_stream(&package.name, create_cxx_bridge);
             service_defs.push(def);
             service_impls.push(imp);
+            if !create_cxx_bridge {
+                for message in [&service.request, &service.response] {
+                    message_arrow_impls.push(arrow::message_arrow_impls(message, &package.name));
+                }
+            }
             if create_cxx_bridge {
                 let (service_creation_def, service_creation_impl) =
                     service.cxx_service_creation_functions(&package.name);
@@ -65,6 +74,11 @@ where
             let (def, imp) = action.struct_token_stream(&package.name, create_cxx_bridge);
             action_defs.push(def);
             action_impls.push(imp);
+            if !create_cxx_bridge {
+                for message in [&action.goal, &action.result, &action.feedback] {
+                    message_arrow_impls.push(arrow::message_arrow_impls(message, &package.name));
+                }
+            }
             if create_cxx_bridge {
                 let (action_creation_def, action_creation_impl) =
                     action.cxx_action_creation_functions(&package.name);
@@ -78,6 +92,17 @@ where
         aliases.push(package.aliases_token_stream());
     }
 
+    let arrow_support = if create_cxx_bridge {
+        quote! {}
+    } else {
+        let u16_string_converter = arrow::u16_string_converter();
+        quote! {
+            use ::dora_node_api::arrow;
+
+            #u16_string_converter
+        }
+    };
+
     let (attributes, imports_and_functions, cxx_bridge_impls) = if create_cxx_bridge {
         (
             quote! { #[cxx::bridge] },
@@ -344,6 +369,9 @@ where
 
         #(#message_struct_impls)*
 
+        #arrow_support
+        #(#message_arrow_impls)*
+
         #cxx_bridge_impls
         #(#message_topic_impls)*
         #(#service_creation_impls)*
@@ -0,0 +1,456 @@
+//! Drive ROS2 actions from a dora dataflow.
+//!
+//! A goal input sends a goal to the action server. Goal acceptance, status
+//! updates, feedback and the final result are sent on dora outputs, all tagged
+//! with the `goal_id` metadata parameter (the goal UUID). The `request_id` of the
+//! goal input is echoed on the `accepted`/`rejected` status so the sender can
+//! learn the goal id. Errors before the goal id is known, e.g. an invalid goal
+//! input, are tagged with the `request_id` instead. A cancel input cancels a
+//! goal by id.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use dora_node_api::{
+    ArrowData, DoraNode, IntoArrow, MetadataParameters, Parameter,
+    arrow::{
+        array::{ArrayRef, StringArray, StructArray},
+        datatypes::{DataType, Field},
+    },
+    dora_core::config::DataId,
+};
+use eyre::{Context as _, bail, eyre};
+use futures::{StreamExt, executor::ThreadPool, stream, task::SpawnExt};
+use ros2_client::{
+    ServiceMapping,
+    action::{ActionTypes, GoalId, GoalStatusEnum},
+    builtin_interfaces::Time,
+};
+use tracing::warn;
+
+use crate::{
+    BridgeNode,
+    qos::ActionQosConfig,
+    service_client::{REQUEST_ID_PARAM, ServiceCallError, with_timeout},
+};
+
+/// Metadata parameter holding the goal UUID.
+pub const GOAL_ID_PARAM: &str = "goal_id";
+
+#[derive(Debug, Clone)]
+pub struct ActionClientConfig {
+    /// Action name, resolved against the node namespace.
+    pub action_name: String,
+    /// Timeout for the goal and cancel service calls.
+    pub call_timeout: Duration,
+    /// Maximum time to wait for the result of an accepted goal, unlimited if `None`.
+    pub result_timeout: Option<Duration>,
+    pub qos: ActionQosConfig,
+    pub feedback_output: DataId,
+    pub status_output: DataId,
+    pub result_output: DataId,
+    pub error_output: DataId,
+}
+
+impl ActionClientConfig {
+    pub fn new(action_name: impl Into<String>) -> Self {
+        Self {
+            action_name: action_name.into(),
+            call_timeout: Duration::from_secs(5),
+            result_timeout: None,
+            qos: ActionQosConfig::default(),
+            feedback_output: DataId::from("feedback".to_owned()),
+            status_output: DataId::from("status".to_owned()),
+            result_output: DataId::from("result".to_owned()),
+            error_output: DataId::from("error".to_owned()),
+        }
+    }
+}
+
+/// Something that happened to a goal sent by an [`ActionClientBridge`].
+#[derive(Debug)]
+pub enum ActionClientEvent<F, R> {
+    /// The goal was accepted or rejected by the server.
+    Response {
+        request_id: String,
+        goal_id: String,
+        accepted: bool,
+    },
+    Status {
+        goal_id: String,
+        status: GoalStatusEnum,
+    },
+    Feedback {
+        goal_id: String,
+        feedback: F,
+    },
+    Result {
+        goal_id: String,
+        status: GoalStatusEnum,
+        result: R,
+    },
+    /// A cancel request was answered; `accepted` is `false` if the server refused it.
+    CancelResponse {
+        goal_id: String,
+        accepted: bool,
+    },
+    /// The goal input could not be converted or sending the goal failed.
+    RequestError {
+        request_id: String,
+        error: ServiceCallError,
+    },
+    /// Cancelling an accepted goal or retrieving its result failed.
+    Error {
+        goal_id: String,
+        error: ServiceCallError,
+    },
+}
+
+type ClientEvent<A> =
+    ActionClientEvent<<A as ActionTypes>::FeedbackType, <A as ActionTypes>::ResultType>;
+
+/// A ROS2 action client driven by dora inputs.
+pub struct ActionClientBridge<A: ActionTypes> {
+    client: Arc<ros2_client::action::ActionClient<A>>,
+    config: ActionClientConfig,
+    executor: Arc<ThreadPool>,
+    goals: Arc<Mutex<HashMap<String, GoalId>>>,
+    events_tx: flume::Sender<ClientEvent<A>>,
+    events_rx: flume::Receiver<ClientEvent<A>>,
+    next_request_id: AtomicU64,
+}
+
+impl<A> ActionClientBridge<A>
+where
+    A: ActionTypes + 'static,
+    A::GoalType: Send + 'static,
+    A::ResultType: Send + 'static,
+    A::FeedbackType: Send + 'static,
+{
+    pub fn new(
+        ros_node: &mut BridgeNode,
+        action_type: ros2_client::ActionTypeName,
+        config: ActionClientConfig,
+    ) -> eyre::Result<Self> {
+        let action_name = ros_node.resolve_name(&config.action_name)?;
+        let client = ros_node
+            .node_mut()
+            .create_action_client::<A>(
+                ServiceMapping::Enhanced,
+                &action_name,
+                &action_type,
+                config.qos.to_client_policies(),
+            )
+            .map_err(|e| {
+                eyre!("failed to create action client for `{}`: {e:?}", config.action_name)
+            })?;
+        let (events_tx, events_rx) = flume::unbounded();
+
+        Ok(Self {
+            client: Arc::new(client),
+            config,
+            executor: ros_node.executor().clone(),
+            goals: Default::default(),
+            events_tx,
+            events_rx,
+            next_request_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Convert a dora input into a goal and send it.
+    ///
+    /// Conversion errors are reported as [`ActionClientEvent::RequestError`]. Returns the
+    /// request id.
+    pub fn handle_goal_input(
+        &self,
+        parameters: &MetadataParameters,
+        data: &ArrowData,
+    ) -> eyre::Result<String>
+    where
+        A::GoalType: for<'a> TryFrom<&'a ArrowData, Error = eyre::Report>,
+    {
+        match A::GoalType::try_from(data) {
+            Ok(goal) => self.send_goal(parameters, goal),
+            Err(err) => {
+                let request_id = self.request_id(parameters);
+                let _ = self.events_tx.send(ActionClientEvent::RequestError {
+                    request_id: request_id.clone(),
+                    error: ServiceCallError::InvalidRequest(format!("{err:#}")),
+                });
+                Ok(request_id)
+            }
+        }
+    }
+
+    /// Send a goal and follow it until the result arrives, in the background.
+    ///
+    /// Returns the request id; the goal id is reported with [`ActionClientEvent::Response`].
+    pub fn send_goal(
+        &self,
+        parameters: &MetadataParameters,
+        goal: A::GoalType,
+    ) -> eyre::Result<String> {
+        let request_id = self.request_id(parameters);
+        let client = self.client.clone();
+        let goals = self.goals.clone();
+        let events_tx = self.events_tx.clone();
+        let call_timeout = self.config.call_timeout;
+        let result_timeout = self.config.result_timeout;
+        let id = request_id.clone();
+
+        self.executor
+            .spawn(async move {
+                let (goal_id, response) =
+                    match with_timeout(client.async_send_goal(goal), call_timeout).await {
+                        Ok(response) => response,
+                        Err(error) => {
+                            let _ = events_tx.send(ActionClientEvent::RequestError {
+                                request_id: id,
+                                error,
+                            });
+                            return;
+                        }
+                    };
+                let goal_key = goal_id_string(&goal_id);
+                let _ = events_tx.send(ActionClientEvent::Response {
+                    request_id: id,
+                    goal_id: goal_key.clone(),
+                    accepted: response.accepted,
+                });
+                if !response.accepted {
+                    return;
+                }
+                goals.lock().unwrap().insert(goal_key.clone(), goal_id);
+
+                let feedback = client.feedback_stream(goal_id).map(|feedback| match feedback {
+                    Ok(feedback) => Some(ActionClientEvent::Feedback {
+                        goal_id: goal_key.clone(),
+                        feedback,
+                    }),
+                    Err(err) => {
+                        warn!("failed to read feedback of goal `{goal_key}`: {err:?}");
+                        None
+                    }
+                });
+                let status = client.status_stream(goal_id).map(|status| match status {
+                    Ok(status) => Some(ActionClientEvent::Status {
+                        goal_id: goal_key.clone(),
+                        status: status.status,
+                    }),
+                    Err(err) => {
+                        warn!("failed to read status of goal `{goal_key}`: {err:?}");
+                        None
+                    }
+                });
+                let result = stream::once(async {
+                    let result = client.async_request_result(goal_id);
+                    let result = match result_timeout {
+                        Some(timeout) => with_timeout(result, timeout).await,
+                        None => result
+                            .await
+                            .map_err(|err| ServiceCallError::Failed(format!("{err:?}"))),
+                    };
+                    Some(match result {
+                        Ok((status, result)) => ActionClientEvent::Result {
+                            goal_id: goal_key.clone(),
+                            status,
+                            result,
+                        },
+                        Err(error) => ActionClientEvent::Error {
+                            goal_id: goal_key.clone(),
+                            error,
+                        },
+                    })
+                });
+
+                let mut events =
+                    stream::select(stream::select(feedback, status).boxed(), result.boxed());
+                while let Some(event) = events.next().await {
+                    let Some(event) = event else { continue };
+                    let done = matches!(
+                        event,
+                        ActionClientEvent::Result { .. } | ActionClientEvent::Error { .. }
+                    );
+                    if events_tx.send(event).is_err() || done {
+                        break;
+                    }
+                }
+                goals.lock().unwrap().remove(&goal_key);
+            })
+            .context("failed to spawn action goal")?;
+
+        Ok(request_id)
+    }
+
+    /// Cancel the goal whose id is given as `goal_id` metadata parameter or as
+    /// string input data.
+    pub fn handle_cancel_input(
+        &self,
+        parameters: &MetadataParameters,
+        data: &ArrowData,
+    ) -> eyre::Result<()> {
+        let goal_key = match parameters.get(GOAL_ID_PARAM) {
+            Some(Parameter::String(id)) => id.clone(),
+            _ => <&str>::try_from(data)
+                .context("expected goal id as metadata parameter or string data")?
+                .to_owned(),
+        };
+        self.cancel_goal(&goal_key)
+    }
+
+    /// Request cancellation of an active goal in the background.
+    pub fn cancel_goal(&self, goal_key: &str) -> eyre::Result<()> {
+        let Some(goal_id) = self.goals.lock().unwrap().get(goal_key).copied() else {
+            bail!("cannot cancel unknown or finished goal `{goal_key}`");
+        };
+        let client = self.client.clone();
+        let events_tx = self.events_tx.clone();
+        let call_timeout = self.config.call_timeout;
+        let goal_key = goal_key.to_owned();
+
+        self.executor
+            .spawn(async move {
+                let cancel = client.async_cancel_goal(goal_id, Time::ZERO);
+                let event = match with_timeout(cancel, call_timeout).await {
+                    Ok(response) => ActionClientEvent::CancelResponse {
+                        accepted: response.goals_canceling.iter().any(|g| g.goal_id == goal_id),
+                        goal_id: goal_key,
+                    },
+                    Err(error) => ActionClientEvent::Error {
+                        goal_id: goal_key,
+                        error,
+                    },
+                };
+                let _ = events_tx.send(event);
+            })
+            .context("failed to spawn action cancel")
+    }
+
+    /// Stream of goal updates, to be merged into the dora event loop.
+    pub fn events(&self) -> flume::r#async::RecvStream<'static, ClientEvent<A>> {
+        self.events_rx.clone().into_stream()
+    }
+
+    /// Send a goal update on the matching dora output.
+    pub fn send_output(&self, node: &mut DoraNode, event: ClientEvent<A>) -> eyre::Result<()>
+    where
+        A::FeedbackType: IntoArrow,
+        A::ResultType: IntoArrow,
+    {
+        let config = &self.config;
+        match event {
+            ActionClientEvent::Response {
+                request_id,
+                goal_id,
+                accepted,
+            } => {
+                let status = if accepted { "accepted" } else { "rejected" };
+                let mut params = goal_params(&goal_id);
+                params.insert(REQUEST_ID_PARAM.into(), Parameter::String(request_id));
+                node.send_output(
+                    config.status_output.clone(),
+                    params,
+                    status_to_arrow(&goal_id, status),
+                )
+            }
+            ActionClientEvent::Status { goal_id, status } => node.send_output(
+                config.status_output.clone(),
+                goal_params(&goal_id),
+                status_to_arrow(&goal_id, goal_status_name(status)),
+            ),
+            ActionClientEvent::Feedback { goal_id, feedback } => node.send_output(
+                config.feedback_output.clone(),
+                goal_params(&goal_id),
+                feedback.into_arrow(),
+            ),
+            ActionClientEvent::Result {
+                goal_id,
+                status,
+                result,
+            } => {
+                let mut params = goal_params(&goal_id);
+                params.insert(
+                    "status".into(),
+                    Parameter::String(goal_status_name(status).to_owned()),
+                );
+                node.send_output(config.result_output.clone(), params, result.into_arrow())
+            }
+            ActionClientEvent::CancelResponse { goal_id, accepted } => {
+                let status = if accepted { "cancel_accepted" } else { "cancel_rejected" };
+                node.send_output(
+                    config.status_output.clone(),
+                    goal_params(&goal_id),
+                    status_to_arrow(&goal_id, status),
+                )
+            }
+            ActionClientEvent::RequestError { request_id, error } => {
+                warn!("action `{}` request `{request_id}` failed: {error}", config.action_name);
+                let mut params = MetadataParameters::default();
+                params.insert(REQUEST_ID_PARAM.into(), Parameter::String(request_id.clone()));
+                node.send_output(
+                    config.error_output.clone(),
+                    params,
+                    error.to_arrow(&request_id),
+                )
+            }
+            ActionClientEvent::Error { goal_id, error } => {
+                warn!("action `{}` goal `{goal_id}` failed: {error}", config.action_name);
+                node.send_output(
+                    config.error_output.clone(),
+                    goal_params(&goal_id),
+                    error.to_arrow_with_id(GOAL_ID_PARAM, &goal_id),
+                )
+            }
+        }
+    }
+
+    fn request_id(&self, parameters: &MetadataParameters) -> String {
+        match parameters.get(REQUEST_ID_PARAM) {
+            Some(Parameter::String(id)) => id.clone(),
+            Some(Parameter::Integer(id)) => id.to_string(),
+            _ => self.next_request_id.fetch_add(1, Ordering::Relaxed).to_string(),
+        }
+    }
+}
+
+/// Lowercase name of a goal status, as used on the status output.
+pub fn goal_status_name(status: GoalStatusEnum) -> &'static str {
+    match status {
+        GoalStatusEnum::Unknown => "unknown",
+        GoalStatusEnum::Accepted => "accepted",
+        GoalStatusEnum::Executing => "executing",
+        GoalStatusEnum::Canceling => "canceling",
+        GoalStatusEnum::Succeeded => "succeeded",
+        GoalStatusEnum::Canceled => "canceled",
+        GoalStatusEnum::Aborted => "aborted",
+    }
+}
+
+pub(crate) fn goal_id_string(goal_id: &GoalId) -> String {
+    goal_id.uuid.hyphenated().to_string()
+}
+
+pub(crate) fn goal_params(goal_id: &str) -> MetadataParameters {
+    let mut params = MetadataParameters::default();
+    params.insert(GOAL_ID_PARAM.into(), Parameter::String(goal_id.to_owned()));
+    params
+}
+
+/// Arrow struct `{goal_id, status}` sent on the status output.
+pub(crate) fn status_to_arrow(goal_id: &str, status: &str) -> StructArray {
+    let fields = vec![
+        Field::new("goal_id", DataType::Utf8, false),
+        Field::new("status", DataType::Utf8, false),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(vec![goal_id.to_owned()])),
+        Arc::new(StringArray::from(vec![status.to_owned()])),
+    ];
+    StructArray::new(fields.into(), columns, None)
+}
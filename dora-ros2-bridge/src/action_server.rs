@@ -0,0 +1,383 @@
+//! Host a ROS2 action server inside a dora dataflow.
+//!
+//! Goals are accepted or rejected according to a [`GoalPolicy`]. Accepted goals
+//! are sent on a dora output with the `goal_id` metadata parameter. The dataflow
+//! reports progress by sending feedback and the final result to dora inputs with
+//! the same `goal_id`. Cancel requests for active goals are accepted and
+//! forwarded on a cancel output; the dataflow then finishes the goal by sending a
+//! result with the `status` parameter set to `canceled`.
+//!
+//! Goal states and the `action_msgs/GoalStatusArray` status topic are managed by
+//! `ros2_client::action::AsyncActionServer`.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use dora_node_api::{
+    ArrowData, DoraNode, IntoArrow, MetadataParameters, Parameter, dora_core::config::DataId,
+};
+use eyre::{Context as _, ContextCompat as _, bail, eyre};
+use ros2_client::{
+    ServiceMapping,
+    action::{ActionTypes, AsyncActionServer, ExecutingGoalHandle, GoalEndStatus},
+};
+use tracing::{info, warn};
+
+use crate::{
+    BridgeNode,
+    action_client::{GOAL_ID_PARAM, goal_id_string, goal_params, status_to_arrow},
+    backoff::ReceiveBackoff,
+    qos::ActionQosConfig,
+};
+
+/// Decides which goals an [`ActionServerBridge`] accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GoalPolicy {
+    /// Accept every goal, any number of goals may run at the same time.
+    #[default]
+    AcceptAll,
+    /// Reject new goals while another goal is active.
+    RejectWhenBusy,
+    /// Reject new goals while `n` goals are active.
+    MaxActive(usize),
+}
+
+impl GoalPolicy {
+    fn accepts(&self, active: usize) -> bool {
+        match self {
+            GoalPolicy::AcceptAll => true,
+            GoalPolicy::RejectWhenBusy => active == 0,
+            GoalPolicy::MaxActive(max) => active < *max,
+        }
+    }
+}
+
+impl FromStr for GoalPolicy {
+    type Err = eyre::Report;
+
+    /// Parse `accept_all`, `reject_when_busy` or `max_active=N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "accept_all" => Ok(GoalPolicy::AcceptAll),
+            "reject_when_busy" => Ok(GoalPolicy::RejectWhenBusy),
+            other => match other.split_once('=') {
+                Some(("max_active", n)) => {
+                    let n = n
+                        .trim()
+                        .parse()
+                        .map_err(|e| eyre!("invalid goal limit `{n}`: {e}"))?;
+                    Ok(GoalPolicy::MaxActive(n))
+                }
+                _ => bail!(
+                    "invalid goal policy `{other}`, expected `accept_all`, `reject_when_busy` or `max_active=N`"
+                ),
+            },
+        }
+    }
+}
+
+impl fmt::Display for GoalPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoalPolicy::AcceptAll => f.write_str("accept_all"),
+            GoalPolicy::RejectWhenBusy => f.write_str("reject_when_busy"),
+            GoalPolicy::MaxActive(n) => write!(f, "max_active={n}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActionServerConfig {
+    /// Action name, resolved against the node namespace.
+    pub action_name: String,
+    pub policy: GoalPolicy,
+    pub qos: ActionQosConfig,
+    pub goal_output: DataId,
+    pub cancel_output: DataId,
+}
+
+impl ActionServerConfig {
+    pub fn new(action_name: impl Into<String>) -> Self {
+        Self {
+            action_name: action_name.into(),
+            policy: GoalPolicy::default(),
+            qos: ActionQosConfig::default(),
+            goal_output: DataId::from("goal".to_owned()),
+            cancel_output: DataId::from("cancel".to_owned()),
+        }
+    }
+}
+
+/// A goal request or cancel request to forward to dora.
+#[derive(Debug)]
+pub enum ActionServerEvent<G> {
+    /// A goal was accepted and is now executing.
+    Goal { goal_id: String, goal: G },
+    /// Cancellation of an active goal was requested and accepted.
+    Cancel { goal_id: String },
+}
+
+type Goals<A> = Mutex<HashMap<String, ExecutingGoalHandle<<A as ActionTypes>::GoalType>>>;
+
+/// A ROS2 action server whose goals are executed by the dora dataflow.
+pub struct ActionServerBridge<A: ActionTypes> {
+    server: Arc<AsyncActionServer<A>>,
+    goals: Arc<Goals<A>>,
+    config: ActionServerConfig,
+    events_rx: flume::Receiver<ActionServerEvent<A::GoalType>>,
+}
+
+impl<A> ActionServerBridge<A>
+where
+    A: ActionTypes + 'static,
+    A::GoalType: Clone + Send + 'static,
+    A::ResultType: Send + 'static,
+    A::FeedbackType: Send + 'static,
+{
+    /// Create the action server and start receiving goal and cancel requests.
+    pub fn new(
+        ros_node: &mut BridgeNode,
+        action_type: ros2_client::ActionTypeName,
+        config: ActionServerConfig,
+    ) -> eyre::Result<Self> {
+        let action_name = ros_node.resolve_name(&config.action_name)?;
+        let server = ros_node
+            .node_mut()
+            .create_action_server::<A>(
+                ServiceMapping::Enhanced,
+                &action_name,
+                &action_type,
+                config.qos.to_server_policies(),
+            )
+            .map_err(|e| {
+                eyre!("failed to create action server for `{}`: {e:?}", config.action_name)
+            })?;
+        let server = Arc::new(AsyncActionServer::new(server));
+        let goals: Arc<Goals<A>> = Default::default();
+        let (events_tx, events_rx) = flume::unbounded();
+
+        ros_node.spawn(receive_goals(
+            server.clone(),
+            goals.clone(),
+            config.policy,
+            config.action_name.clone(),
+            events_tx.clone(),
+        ))?;
+        ros_node.spawn(receive_cancel_requests(
+            server.clone(),
+            goals.clone(),
+            config.action_name.clone(),
+            events_tx,
+        ))?;
+
+        Ok(Self {
+            server,
+            goals,
+            config,
+            events_rx,
+        })
+    }
+
+    /// Stream of accepted goals and cancel requests, to be merged into the dora event loop.
+    pub fn events(&self) -> flume::r#async::RecvStream<'static, ActionServerEvent<A::GoalType>> {
+        self.events_rx.clone().into_stream()
+    }
+
+    /// Send an accepted goal or a cancel request on the matching dora output.
+    pub fn send_output(
+        &self,
+        node: &mut DoraNode,
+        event: ActionServerEvent<A::GoalType>,
+    ) -> eyre::Result<()>
+    where
+        A::GoalType: IntoArrow,
+    {
+        match event {
+            ActionServerEvent::Goal { goal_id, goal } => node.send_output(
+                self.config.goal_output.clone(),
+                goal_params(&goal_id),
+                goal.into_arrow(),
+            ),
+            ActionServerEvent::Cancel { goal_id } => node.send_output(
+                self.config.cancel_output.clone(),
+                goal_params(&goal_id),
+                status_to_arrow(&goal_id, "cancel_requested"),
+            ),
+        }
+    }
+
+    /// Publish feedback received as dora input for the goal given in the `goal_id` parameter.
+    pub fn handle_feedback_input(
+        &self,
+        parameters: &MetadataParameters,
+        data: &ArrowData,
+    ) -> eyre::Result<()>
+    where
+        A::FeedbackType: for<'a> TryFrom<&'a ArrowData, Error = eyre::Report>,
+    {
+        let goal_id = goal_id(parameters)?;
+        let feedback = A::FeedbackType::try_from(data).context("invalid action feedback")?;
+        self.publish_feedback(&goal_id, feedback)
+    }
+
+    /// Finish the goal given in the `goal_id` parameter with the result received as dora input.
+    ///
+    /// The optional `status` parameter (`succeeded`, `aborted` or `canceled`)
+    /// defaults to `succeeded`.
+    pub fn handle_result_input(
+        &self,
+        parameters: &MetadataParameters,
+        data: &ArrowData,
+    ) -> eyre::Result<()>
+    where
+        A::ResultType: for<'a> TryFrom<&'a ArrowData, Error = eyre::Report>,
+    {
+        let goal_id = goal_id(parameters)?;
+        let status = match parameters.get("status") {
+            None => GoalEndStatus::Succeeded,
+            Some(Parameter::String(status)) => match status.as_str() {
+                "succeeded" => GoalEndStatus::Succeeded,
+                "aborted" => GoalEndStatus::Aborted,
+                "canceled" => GoalEndStatus::Canceled,
+                other => bail!("invalid goal status `{other}`"),
+            },
+            Some(other) => bail!("invalid goal status parameter {other:?}"),
+        };
+        let result = A::ResultType::try_from(data).context("invalid action result")?;
+        self.finish_goal(&goal_id, status, result)
+    }
+
+    pub fn publish_feedback(&self, goal_id: &str, feedback: A::FeedbackType) -> eyre::Result<()> {
+        let handle = self
+            .goals
+            .lock()
+            .unwrap()
+            .get(goal_id)
+            .cloned()
+            .with_context(|| format!("no active goal `{goal_id}`"))?;
+        futures::executor::block_on(self.server.publish_feedback(handle, feedback))
+            .map_err(|e| eyre!("failed to publish feedback for goal `{goal_id}`: {e:?}"))
+    }
+
+    pub fn finish_goal(
+        &self,
+        goal_id: &str,
+        status: GoalEndStatus,
+        result: A::ResultType,
+    ) -> eyre::Result<()> {
+        let handle = self
+            .goals
+            .lock()
+            .unwrap()
+            .remove(goal_id)
+            .with_context(|| format!("no active goal `{goal_id}`"))?;
+        futures::executor::block_on(self.server.send_result_response(handle, status, result))
+            .map_err(|e| eyre!("failed to send result for goal `{goal_id}`: {e:?}"))
+    }
+}
+
+async fn receive_goals<A>(
+    server: Arc<AsyncActionServer<A>>,
+    goals: Arc<Goals<A>>,
+    policy: GoalPolicy,
+    action_name: String,
+    events_tx: flume::Sender<ActionServerEvent<A::GoalType>>,
+) where
+    A: ActionTypes,
+    A::GoalType: Clone,
+{
+    let mut backoff = ReceiveBackoff::new(format!("goals on `{action_name}`"));
+    loop {
+        let new_goal = match server.receive_new_goal().await {
+            Ok(new_goal) => new_goal,
+            Err(err) => {
+                backoff.failed(err).await;
+                continue;
+            }
+        };
+        backoff.succeeded();
+        let goal_id = goal_id_string(&new_goal.goal_id());
+        let Some(goal) = server.get_new_goal(new_goal).cloned() else {
+            warn!("goal `{goal_id}` on `{action_name}` disappeared");
+            continue;
+        };
+
+        let active = goals.lock().unwrap().len();
+        if !policy.accepts(active) {
+            info!("rejecting goal `{goal_id}` on `{action_name}` ({policy}, {active} active)");
+            if let Err(err) = server.reject_goal(new_goal).await {
+                warn!("failed to reject goal `{goal_id}`: {err:?}");
+            }
+            continue;
+        }
+
+        let executing = match server.accept_goal(new_goal).await {
+            Ok(accepted) => server.start_executing_goal(accepted).await,
+            Err(err) => Err(err),
+        };
+        match executing {
+            Ok(handle) => {
+                goals.lock().unwrap().insert(goal_id.clone(), handle);
+                if events_tx
+                    .send(ActionServerEvent::Goal { goal_id, goal })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            Err(err) => warn!("failed to accept goal `{goal_id}`: {err:?}"),
+        }
+    }
+}
+
+async fn receive_cancel_requests<A>(
+    server: Arc<AsyncActionServer<A>>,
+    goals: Arc<Goals<A>>,
+    action_name: String,
+    events_tx: flume::Sender<ActionServerEvent<A::GoalType>>,
+) where
+    A: ActionTypes,
+{
+    let mut backoff = ReceiveBackoff::new(format!("cancel requests on `{action_name}`"));
+    loop {
+        let cancel = match server.receive_cancel_request().await {
+            Ok(cancel) => cancel,
+            Err(err) => {
+                backoff.failed(err).await;
+                continue;
+            }
+        };
+        backoff.succeeded();
+        let canceling: Vec<_> = {
+            let goals = goals.lock().unwrap();
+            cancel
+                .goals()
+                .filter(|goal_id| goals.contains_key(&goal_id_string(goal_id)))
+                .collect()
+        };
+        if let Err(err) = server
+            .respond_to_cancel_requests(&cancel, canceling.iter().copied())
+            .await
+        {
+            warn!("failed to respond to cancel request on `{action_name}`: {err:?}");
+            continue;
+        }
+        for goal_id in canceling {
+            let goal_id = goal_id_string(&goal_id);
+            if events_tx.send(ActionServerEvent::Cancel { goal_id }).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn goal_id(parameters: &MetadataParameters) -> eyre::Result<String> {
+    match parameters.get(GOAL_ID_PARAM) {
+        Some(Parameter::String(id)) => Ok(id.clone()),
+        _ => bail!("missing `{GOAL_ID_PARAM}` metadata parameter"),
+    }
+}
@@ -0,0 +1,444 @@
+//! Runtime support for the Arrow conversions generated by `dora_ros2_bridge_msg_gen`.
+//!
+//! Every generated message struct implements [`ArrowStruct`], `dora_node_api::IntoArrow`
+//! and `TryFrom<&ArrowData>`. The mapping is:
+//!
+//! - messages -> `Struct`
+//! - `uint8[]`/`byte[]` -> `Binary`, `uint8[N]`/`byte[N]` -> `FixedSizeBinary(N)`
+//! - other sequences -> `List`, other fixed size arrays -> `FixedSizeList`
+//! - `string`/`wstring` -> `Utf8`, numbers and `bool` -> the matching primitive type
+
+use std::{marker::PhantomData, sync::Arc};
+
+use dora_node_api::arrow::{
+    array::{
+        Array, ArrayRef, AsArray, BinaryArray, BooleanArray, FixedSizeBinaryArray,
+        FixedSizeListArray, ListArray, PrimitiveArray, StringArray, StructArray,
+    },
+    buffer::{Buffer, OffsetBuffer},
+    datatypes::{ArrowPrimitiveType, DataType, Field, Fields},
+};
+use eyre::{ContextCompat, bail, eyre};
+
+/// Conversion between a column of Rust values and an Arrow array.
+pub trait ArrowConverter {
+    type Item;
+
+    fn data_type() -> DataType;
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef;
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>>;
+}
+
+/// Implemented by all generated message structs.
+pub trait ArrowStruct: Sized {
+    fn fields() -> Fields;
+    /// Split `values` into one Arrow column per field, in [`fields`](Self::fields) order.
+    fn to_columns(values: Vec<Self>) -> Vec<ArrayRef>;
+    fn from_columns(array: &StructArray) -> eyre::Result<Vec<Self>>;
+}
+
+pub fn to_struct_array<T: ArrowStruct>(values: Vec<T>) -> StructArray {
+    let len = values.len();
+    let fields = T::fields();
+    if fields.is_empty() {
+        return StructArray::new_empty_fields(len, None);
+    }
+    StructArray::new(fields, T::to_columns(values), None)
+}
+
+/// Convert an Arrow struct array of length 1 into a single message.
+pub fn from_single<T: ArrowStruct>(array: &dyn Array) -> eyre::Result<T> {
+    let array = array
+        .as_struct_opt()
+        .with_context(|| format!("expected struct array, got {}", array.data_type()))?;
+    if array.len() != 1 {
+        bail!("expected struct array of length 1, got length {}", array.len());
+    }
+    T::from_columns(array)?
+        .pop()
+        .context("struct conversion returned no value")
+}
+
+/// Look up the column for `name` in a struct array.
+pub fn column<'a>(array: &'a StructArray, name: &str) -> eyre::Result<&'a ArrayRef> {
+    array
+        .column_by_name(name)
+        .with_context(|| format!("missing field `{name}` in struct array"))
+}
+
+fn field<C: ArrowConverter>() -> Arc<Field> {
+    Arc::new(Field::new("item", C::data_type(), false))
+}
+
+pub struct Primitive<T>(PhantomData<T>);
+
+impl<T: ArrowPrimitiveType> ArrowConverter for Primitive<T> {
+    type Item = T::Native;
+
+    fn data_type() -> DataType {
+        T::DATA_TYPE
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        Arc::new(PrimitiveArray::<T>::from_iter_values(values))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array.as_primitive_opt::<T>().with_context(|| {
+            format!("expected {} array, got {}", T::DATA_TYPE, array.data_type())
+        })?;
+        Ok(array.values().to_vec())
+    }
+}
+
+pub struct Bool;
+
+impl ArrowConverter for Bool {
+    type Item = bool;
+
+    fn data_type() -> DataType {
+        DataType::Boolean
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        Arc::new(BooleanArray::from(values))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array
+            .as_boolean_opt()
+            .with_context(|| format!("expected boolean array, got {}", array.data_type()))?;
+        Ok(array.iter().map(Option::unwrap_or_default).collect())
+    }
+}
+
+pub struct Utf8;
+
+impl ArrowConverter for Utf8 {
+    type Item = String;
+
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        Arc::new(StringArray::from(values))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array
+            .as_string_opt::<i32>()
+            .with_context(|| format!("expected utf8 array, got {}", array.data_type()))?;
+        Ok(array
+            .iter()
+            .map(|s| s.unwrap_or_default().to_owned())
+            .collect())
+    }
+}
+
+pub struct Binary;
+
+impl ArrowConverter for Binary {
+    type Item = Vec<u8>;
+
+    fn data_type() -> DataType {
+        DataType::Binary
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        Arc::new(BinaryArray::from_iter_values(values))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array
+            .as_binary_opt::<i32>()
+            .with_context(|| format!("expected binary array, got {}", array.data_type()))?;
+        Ok(array
+            .iter()
+            .map(|b| b.unwrap_or_default().to_vec())
+            .collect())
+    }
+}
+
+pub struct FixedBinary<const N: usize>;
+
+impl<const N: usize> ArrowConverter for FixedBinary<N> {
+    type Item = [u8; N];
+
+    fn data_type() -> DataType {
+        DataType::FixedSizeBinary(N as i32)
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        let len = values.len();
+        let flat: Vec<u8> = values.into_iter().flatten().collect();
+        let array = FixedSizeBinaryArray::new(N as i32, Buffer::from_vec(flat), None);
+        debug_assert_eq!(array.len(), len);
+        Arc::new(array)
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array.as_fixed_size_binary_opt().with_context(|| {
+            format!("expected fixed size binary array, got {}", array.data_type())
+        })?;
+        (0..array.len())
+            .map(|i| {
+                array.value(i).try_into().map_err(|_| {
+                    eyre!("expected {N} bytes, got {}", array.value_length())
+                })
+            })
+            .collect()
+    }
+}
+
+pub struct List<C>(PhantomData<C>);
+
+impl<C: ArrowConverter> ArrowConverter for List<C> {
+    type Item = Vec<C::Item>;
+
+    fn data_type() -> DataType {
+        DataType::List(field::<C>())
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        let offsets = OffsetBuffer::from_lengths(values.iter().map(Vec::len));
+        let values = C::to_array(values.into_iter().flatten().collect());
+        Arc::new(ListArray::new(field::<C>(), offsets, values, None))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array
+            .as_list_opt::<i32>()
+            .with_context(|| format!("expected list array, got {}", array.data_type()))?;
+        (0..array.len())
+            .map(|i| C::from_array(&array.value(i)))
+            .collect()
+    }
+}
+
+pub struct FixedList<C, const N: usize>(PhantomData<C>);
+
+impl<C: ArrowConverter, const N: usize> ArrowConverter for FixedList<C, N> {
+    type Item = [C::Item; N];
+
+    fn data_type() -> DataType {
+        DataType::FixedSizeList(field::<C>(), N as i32)
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        let values = C::to_array(values.into_iter().flatten().collect());
+        Arc::new(FixedSizeListArray::new(field::<C>(), N as i32, values, None))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array.as_fixed_size_list_opt().with_context(|| {
+            format!("expected fixed size list array, got {}", array.data_type())
+        })?;
+        (0..array.len())
+            .map(|i| {
+                let items = C::from_array(&array.value(i))?;
+                let len = items.len();
+                items
+                    .try_into()
+                    .map_err(|_| eyre!("expected {N} list items, got {len}"))
+            })
+            .collect()
+    }
+}
+
+pub struct Struct<T>(PhantomData<T>);
+
+impl<T: ArrowStruct> ArrowConverter for Struct<T> {
+    type Item = T;
+
+    fn data_type() -> DataType {
+        DataType::Struct(T::fields())
+    }
+
+    fn to_array(values: Vec<Self::Item>) -> ArrayRef {
+        Arc::new(to_struct_array(values))
+    }
+
+    fn from_array(array: &dyn Array) -> eyre::Result<Vec<Self::Item>> {
+        let array = array
+            .as_struct_opt()
+            .with_context(|| format!("expected struct array, got {}", array.data_type()))?;
+        T::from_columns(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dora_node_api::{ArrowData, IntoArrow};
+
+    use super::*;
+    use crate::messages::{
+        builtin_interfaces::msg::Time,
+        diagnostic_msgs::msg::{DiagnosticArray, DiagnosticStatus, KeyValue},
+        geometry_msgs::msg::{Quaternion, Transform, TransformStamped, Vector3},
+        sensor_msgs::msg::{CameraInfo, Image, RegionOfInterest},
+        std_msgs::msg::Header,
+        tf2_msgs::msg::TFMessage,
+    };
+
+    fn round_trip<T>(value: T) -> T
+    where
+        T: IntoArrow<A = StructArray> + for<'a> TryFrom<&'a ArrowData, Error = eyre::Report>,
+    {
+        let data = ArrowData(Arc::new(value.into_arrow()));
+        T::try_from(&data).unwrap()
+    }
+
+    fn header(frame_id: &str) -> Header {
+        Header {
+            stamp: Time {
+                sec: 1_700_000_000,
+                nanosec: 123_456_789,
+            },
+            frame_id: frame_id.to_owned(),
+        }
+    }
+
+    fn transform(child: &str) -> TransformStamped {
+        TransformStamped {
+            header: header("base_link"),
+            child_frame_id: child.to_owned(),
+            transform: Transform {
+                translation: Vector3 {
+                    x: 0.1,
+                    y: -2.0,
+                    z: 1.2,
+                },
+                rotation: Quaternion {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.7071,
+                    w: 0.7071,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = header("camera_frame");
+        assert_eq!(round_trip(header.clone()), header);
+
+        let fields = Header::fields();
+        assert_eq!(fields[0].name(), "stamp");
+        assert_eq!(fields[0].data_type(), &DataType::Struct(Time::fields()));
+        assert_eq!(fields[1].data_type(), &DataType::Utf8);
+    }
+
+    #[test]
+    fn binary_sequence_round_trip() {
+        let image = Image {
+            header: header("camera_frame"),
+            height: 2,
+            width: 3,
+            encoding: "mono8".to_owned(),
+            is_bigendian: 0,
+            step: 3,
+            data: vec![0, 1, 2, 253, 254, 255],
+        };
+        assert_eq!(round_trip(image.clone()), image);
+
+        let data = Image::fields().find("data").unwrap().1.clone();
+        assert_eq!(data.data_type(), &DataType::Binary);
+    }
+
+    #[test]
+    fn fixed_arrays_and_lists_round_trip() {
+        let info = CameraInfo {
+            header: header("camera_frame"),
+            height: 480,
+            width: 640,
+            distortion_model: "plumb_bob".to_owned(),
+            d: vec![0.1, -0.2, 0.0, 0.0, 0.05],
+            k: [500.0, 0.0, 320.0, 0.0, 500.0, 240.0, 0.0, 0.0, 1.0],
+            r: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            p: [500.0, 0.0, 320.0, 0.0, 0.0, 500.0, 240.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            binning_x: 1,
+            binning_y: 2,
+            roi: RegionOfInterest {
+                x_offset: 10,
+                y_offset: 20,
+                height: 100,
+                width: 200,
+                do_rectify: true,
+            },
+        };
+        assert_eq!(round_trip(info.clone()), info);
+
+        let fields = CameraInfo::fields();
+        let (_, d) = fields.find("d").unwrap();
+        assert!(
+            matches!(d.data_type(), DataType::List(item) if item.data_type() == &DataType::Float64)
+        );
+        let (_, k) = fields.find("k").unwrap();
+        assert!(matches!(k.data_type(), DataType::FixedSizeList(_, 9)));
+    }
+
+    #[test]
+    fn nested_struct_sequences_round_trip() {
+        let empty = TFMessage { transforms: vec![] };
+        assert_eq!(round_trip(empty.clone()), empty);
+
+        let tf = TFMessage {
+            transforms: vec![transform("camera_frame"), transform("lidar_frame")],
+        };
+        assert_eq!(round_trip(tf.clone()), tf);
+
+        let diagnostics = DiagnosticArray {
+            header: header(""),
+            status: vec![
+                DiagnosticStatus {
+                    level: 0,
+                    name: "bridge: /camera".to_owned(),
+                    message: "OK".to_owned(),
+                    hardware_id: String::new(),
+                    values: vec![],
+                },
+                DiagnosticStatus {
+                    level: 2,
+                    name: "bridge: /audio".to_owned(),
+                    message: "stale".to_owned(),
+                    hardware_id: "mic0".to_owned(),
+                    values: vec![
+                        KeyValue {
+                            key: "rate".to_owned(),
+                            value: "0.0".to_owned(),
+                        },
+                        KeyValue {
+                            key: "dropped".to_owned(),
+                            value: "12".to_owned(),
+                        },
+                    ],
+                },
+            ],
+        };
+        assert_eq!(round_trip(diagnostics.clone()), diagnostics);
+    }
+
+    #[test]
+    fn multiple_rows_round_trip() {
+        let transforms = vec![transform("a"), transform("b"), transform("c")];
+        let array = to_struct_array(transforms.clone());
+        assert_eq!(array.len(), 3);
+        assert_eq!(TransformStamped::from_columns(&array).unwrap(), transforms);
+    }
+
+    #[test]
+    fn reject_mismatching_arrays() {
+        // not a struct
+        let data = ArrowData(Arc::new(StringArray::from(vec!["frame"])));
+        assert!(Header::try_from(&data).is_err());
+        // more than one row
+        let data = ArrowData(Arc::new(to_struct_array(vec![header("a"), header("b")])));
+        assert!(Header::try_from(&data).is_err());
+        // missing field
+        let time = to_struct_array(vec![Time { sec: 1, nanosec: 2 }]);
+        let data = ArrowData(Arc::new(time));
+        assert!(Header::try_from(&data).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+//! Retry delays for receive loops of long-running ROS2 endpoints.
+
+use std::{fmt::Debug, time::Duration};
+
+use tracing::{info, warn};
+
+/// Delays between retries after failing to receive.
+const RECEIVE_RETRY_MIN: Duration = Duration::from_millis(100);
+const RECEIVE_RETRY_MAX: Duration = Duration::from_secs(5);
+
+/// Exponential backoff for a loop that receives from a DDS reader.
+///
+/// Errors are logged once per error streak and each retry waits twice as long
+/// as the previous one, so a persistent DDS error doesn't turn into a busy loop
+/// that floods the log.
+pub(crate) struct ReceiveBackoff {
+    /// What is received, e.g. "requests on `/add_two_ints`", used in the logs.
+    what: String,
+    delay: Option<Duration>,
+}
+
+impl ReceiveBackoff {
+    pub fn new(what: impl Into<String>) -> Self {
+        Self {
+            what: what.into(),
+            delay: None,
+        }
+    }
+
+    /// Log `err` if it starts an error streak, returns the delay to await before the next retry.
+    pub fn failed(&mut self, err: impl Debug) -> futures_timer::Delay {
+        let delay = match self.delay {
+            None => {
+                warn!("failed to receive {}, retrying: {err:?}", self.what);
+                RECEIVE_RETRY_MIN
+            }
+            Some(delay) => (delay * 2).min(RECEIVE_RETRY_MAX),
+        };
+        self.delay = Some(delay);
+        futures_timer::Delay::new(delay)
+    }
+
+    /// End the error streak after a successful receive.
+    pub fn succeeded(&mut self) {
+        if self.delay.take().is_some() {
+            info!("receiving {} again", self.what);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Clocks for the `builtin_interfaces/Time` stamps produced by bridge nodes.
+//!
+//! Like in rclcpp there are three clock types: system time, steady (monotonic)
+//! time and ROS time. ROS time follows the system time unless the node's
+//! `use_sim_time` parameter is set, in which case it follows the latest time
+//! published on `/clock`, e.g. by Gazebo or `ros2 bag play --clock`. Until the
+//! first `/clock` message arrives, ROS time is zero.
+
+use std::{
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{Context as _, bail};
+use futures::StreamExt;
+use serde::Deserialize;
+use tracing::warn;
+
+#[cfg(feature = "generate-messages")]
+use crate::messages::builtin_interfaces::msg::Time;
+use crate::{QosConfig, qos::Reliability};
+
+/// Name of the parameter enabling simulation time.
+pub const USE_SIM_TIME_PARAM: &str = "use_sim_time";
+/// Environment variable setting the default of the `use_sim_time` parameter.
+pub const USE_SIM_TIME_ENV: &str = "ROS2_USE_SIM_TIME";
+/// Topic the simulation time is read from.
+pub const CLOCK_TOPIC: &str = "/clock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockType {
+    /// Wall-clock time, may jump when the system time is adjusted.
+    System,
+    /// Monotonic time, starts at the system time of the first use.
+    Steady,
+    /// Simulation time from `/clock` if `use_sim_time` is set, system time otherwise.
+    Ros,
+}
+
+/// A clock producing `builtin_interfaces/Time` stamps.
+///
+/// Cheap to clone; clones of a ROS clock all follow the same `/clock` subscription.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    clock_type: ClockType,
+    sim_time: Option<Arc<RwLock<Option<Duration>>>>,
+}
+
+impl Clock {
+    pub fn system() -> Self {
+        Self {
+            clock_type: ClockType::System,
+            sim_time: None,
+        }
+    }
+
+    pub fn steady() -> Self {
+        Self {
+            clock_type: ClockType::Steady,
+            sim_time: None,
+        }
+    }
+
+    /// A ROS clock that is not driven by `/clock`, i.e. equal to system time.
+    pub fn ros() -> Self {
+        Self {
+            clock_type: ClockType::Ros,
+            sim_time: None,
+        }
+    }
+
+    /// A ROS clock following the latest time published on `clock_topic`.
+    ///
+    /// The subscription runs on the node's executor for the lifetime of the node.
+    pub(crate) fn sim_time(
+        node: &mut ros2_client::Node,
+        executor: &futures::executor::ThreadPool,
+        clock_topic: &ros2_client::Name,
+    ) -> eyre::Result<Self> {
+        // `rosgraph_msgs/Clock`
+        #[derive(Deserialize)]
+        struct ClockMessage {
+            clock: ClockTime,
+        }
+        #[derive(Deserialize)]
+        struct ClockTime {
+            sec: i32,
+            nanosec: u32,
+        }
+
+        // rclcpp `ClockQoS`: best effort, keep last 1
+        let qos = QosConfig::default()
+            .reliability(Reliability::BestEffort)
+            .keep_last(1)
+            .to_policies();
+        let topic = node
+            .create_topic(
+                clock_topic,
+                ros2_client::MessageTypeName::new("rosgraph_msgs", "Clock"),
+                &qos,
+            )
+            .context("failed to create /clock topic")?;
+        let subscription = node
+            .create_subscription::<ClockMessage>(&topic, None)
+            .context("failed to subscribe to /clock")?;
+
+        let sim_time = Arc::new(RwLock::new(None));
+        let latest = sim_time.clone();
+        futures::task::SpawnExt::spawn(executor, async move {
+            let stream = subscription.async_stream();
+            futures::pin_mut!(stream);
+            while let Some(message) = stream.next().await {
+                match message {
+                    Ok((ClockMessage { clock }, _info)) => {
+                        let time = Duration::new(clock.sec.max(0) as u64, clock.nanosec);
+                        *latest.write().unwrap() = Some(time);
+                    }
+                    Err(err) => warn!("failed to read /clock message: {err:?}"),
+                }
+            }
+        })
+        .context("failed to spawn /clock subscription")?;
+
+        Ok(Self {
+            clock_type: ClockType::Ros,
+            sim_time: Some(sim_time),
+        })
+    }
+
+    pub fn clock_type(&self) -> ClockType {
+        self.clock_type
+    }
+
+    /// Whether the clock follows `/clock` instead of the system time.
+    pub fn is_sim_time(&self) -> bool {
+        self.sim_time.is_some()
+    }
+
+    /// The current time of this clock as a stamp for message headers.
+    #[cfg(feature = "generate-messages")]
+    pub fn now(&self) -> Time {
+        to_time(self.since_epoch())
+    }
+
+    /// The current time of this clock as duration since the Unix epoch.
+    pub fn since_epoch(&self) -> Duration {
+        match (self.clock_type, &self.sim_time) {
+            (ClockType::Ros, Some(sim_time)) => sim_time.read().unwrap().unwrap_or_default(),
+            (ClockType::Steady, _) => steady_now(),
+            _ => system_now(),
+        }
+    }
+}
+
+/// Read the default of the `use_sim_time` parameter from `ROS2_USE_SIM_TIME`.
+pub fn use_sim_time_from_env() -> eyre::Result<bool> {
+    match std::env::var(USE_SIM_TIME_ENV) {
+        Ok(value) => match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" | "" => Ok(false),
+            other => bail!("invalid {USE_SIM_TIME_ENV} `{other}`, expected true or false"),
+        },
+        Err(_) => Ok(false),
+    }
+}
+
+/// Convert a duration since the epoch into a `builtin_interfaces/Time`.
+#[cfg(feature = "generate-messages")]
+pub fn to_time(since_epoch: Duration) -> Time {
+    Time {
+        sec: since_epoch.as_secs() as i32,
+        nanosec: since_epoch.subsec_nanos(),
+    }
+}
+
+fn system_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn steady_now() -> Duration {
+    static START: OnceLock<(Instant, Duration)> = OnceLock::new();
+    let (instant, since_epoch) = START.get_or_init(|| (Instant::now(), system_now()));
+    *since_epoch + instant.elapsed()
+}
@@ -0,0 +1,88 @@
+//! YAML node configs, given as a file or inline in an environment variable.
+//!
+//! Bridge nodes take their config from a pair of variables, e.g.
+//! `RELAY_CONFIG` with the path of a YAML file or `RELAY` with the YAML
+//! itself, so short configs can be written directly into the dataflow.
+//!
+//! Nodes handling arbitrary topics also load the message definitions of the
+//! sourced ROS2 installation and pick a QoS matching the discovered writers.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use dora_ros2_bridge_msg_gen::types::Message;
+use eyre::{Context as _, bail, eyre};
+use serde::de::DeserializeOwned;
+
+use crate::{GraphMonitor, QosConfig};
+
+/// Parsed message definitions by package and message name.
+pub type Messages = HashMap<String, HashMap<String, Message>>;
+
+/// Load a YAML config from the file named by `file_env` or inline from `inline_env`.
+///
+/// Exactly one of the two variables must be set. `what` names the config in
+/// errors, e.g. `relay` for "failed to parse relay config".
+pub fn load_yaml<T: DeserializeOwned>(
+    file_env: &str,
+    inline_env: &str,
+    what: &str,
+) -> eyre::Result<T> {
+    let yaml = match (std::env::var_os(file_env), std::env::var(inline_env).ok()) {
+        (Some(path), None) => {
+            let path = PathBuf::from(path);
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {what} config `{}`", path.display()))?
+        }
+        (None, Some(inline)) => inline,
+        (Some(_), Some(_)) => bail!("only one of {file_env} and {inline_env} may be set"),
+        (None, None) => bail!("no {what} config given, set {file_env} or {inline_env}"),
+    };
+    serde_yaml::from_str(&yaml).with_context(|| format!("failed to parse {what} config"))
+}
+
+/// Parse all message definitions found in `AMENT_PREFIX_PATH`.
+pub fn load_messages() -> eyre::Result<Messages> {
+    let ament_prefix_path = match std::env::var("AMENT_PREFIX_PATH") {
+        Ok(path) => path,
+        Err(std::env::VarError::NotPresent) => String::new(),
+        Err(std::env::VarError::NotUnicode(s)) => {
+            bail!(
+                "AMENT_PREFIX_PATH is not valid unicode: `{}`",
+                s.to_string_lossy()
+            );
+        }
+    };
+    let paths: Vec<_> = ament_prefix_path.split(':').map(Path::new).collect();
+
+    let packages = dora_ros2_bridge_msg_gen::get_packages(&paths)
+        .map_err(|err| eyre!(err))
+        .context("failed to parse ROS2 message types")?;
+
+    let mut messages = Messages::new();
+    for message in packages.into_iter().flat_map(|p| p.messages.into_iter()) {
+        messages
+            .entry(message.package.clone())
+            .or_default()
+            .insert(message.name.clone(), message);
+    }
+    Ok(messages)
+}
+
+/// A reader QoS compatible with the discovered writers of `topic`, see
+/// [`QosConfig::compatible_with`].
+///
+/// Looked up when subscribing rather than taken from a [`GraphEvent`], as the
+/// writer QoS might only be known once the topic has been fully discovered.
+/// The default QoS is used if it is still unknown.
+///
+/// [`GraphEvent`]: crate::GraphEvent
+pub fn writer_compatible_qos(monitor: &GraphMonitor, topic: &str) -> QosConfig {
+    monitor
+        .topic(topic)
+        .and_then(|t| t.qos)
+        .map(|qos| QosConfig::compatible_with(&qos))
+        .unwrap_or_default()
+}
@@ -0,0 +1,426 @@
+//! Validated configuration for the ROS2 [`ros2_client::Context`] used by bridge nodes.
+//!
+//! The configuration can be built programmatically, deserialized from a config
+//! struct (e.g. a YAML block in the dataflow), or read from the standard ROS2
+//! environment variables. Invalid values are reported as errors instead of
+//! silently falling back to defaults.
+
+use eyre::{Context as _, bail, eyre};
+use serde::Deserialize;
+
+use crate::security::SecurityConfig;
+
+/// Highest domain id that maps to valid RTPS ports (see the ROS2 domain id docs).
+pub const MAX_DOMAIN_ID: u16 = 232;
+
+/// Environment variable holding a comma separated list of network interfaces to use.
+pub const NETWORK_INTERFACES_ENV: &str = "ROS2_NETWORK_INTERFACES";
+/// Environment variable holding the DDS participant name.
+pub const PARTICIPANT_NAME_ENV: &str = "ROS2_PARTICIPANT_NAME";
+
+/// Mirrors `ROS_AUTOMATIC_DISCOVERY_RANGE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DiscoveryRange {
+    /// No automatic discovery, only static peers are contacted.
+    Off,
+    /// Only discover participants on the same host.
+    Localhost,
+    /// Discover participants reachable by multicast on the local subnet.
+    Subnet,
+    /// Use the DDS implementation default (subnet for rustdds).
+    #[default]
+    SystemDefault,
+}
+
+impl std::str::FromStr for DiscoveryRange {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "OFF" => Ok(Self::Off),
+            "LOCALHOST" => Ok(Self::Localhost),
+            "SUBNET" => Ok(Self::Subnet),
+            "SYSTEM_DEFAULT" | "" => Ok(Self::SystemDefault),
+            other => Err(eyre!(
+                "invalid discovery range `{other}`, expected one of OFF, LOCALHOST, SUBNET, SYSTEM_DEFAULT"
+            )),
+        }
+    }
+}
+
+/// Builder for a ROS2 context.
+///
+/// ```no_run
+/// let context = dora_ros2_bridge::Ros2ContextConfig::from_env()?
+///     .participant_name("dora_bridge")
+///     .build()?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ros2ContextConfig {
+    pub domain_id: u16,
+    pub localhost_only: bool,
+    pub discovery_range: DiscoveryRange,
+    pub static_peers: Vec<String>,
+    pub interfaces: Vec<String>,
+    pub participant_name: Option<String>,
+    /// SROS2 enclave, DDS security is disabled if unset.
+    pub security: Option<SecurityConfig>,
+}
+
+impl Ros2ContextConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the configuration from the ROS2 environment variables.
+    ///
+    /// - `ROS_DOMAIN_ID`: integer in `0..=232`
+    /// - `ROS_LOCALHOST_ONLY`: `0` or `1`
+    /// - `ROS_AUTOMATIC_DISCOVERY_RANGE`: `OFF`, `LOCALHOST`, `SUBNET` or `SYSTEM_DEFAULT`
+    /// - `ROS_STATIC_PEERS`: semicolon separated list of hosts
+    /// - `ROS2_NETWORK_INTERFACES`: comma separated list of interface names or addresses
+    /// - `ROS2_PARTICIPANT_NAME`: DDS participant name
+    /// - `ROS_SECURITY_*`: SROS2 enclave, see [`SecurityConfig::from_env`]
+    ///
+    /// Unset variables keep their defaults, set but invalid variables are an error.
+    pub fn from_env() -> eyre::Result<Self> {
+        Self::from_vars(&env_var)
+    }
+
+    /// [`Self::from_env`] with the variables looked up by `var`.
+    pub(crate) fn from_vars(
+        var: &dyn Fn(&str) -> eyre::Result<Option<String>>,
+    ) -> eyre::Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(value) = var("ROS_DOMAIN_ID")? {
+            config.domain_id = value
+                .trim()
+                .parse()
+                .map_err(|e| eyre!("invalid ROS_DOMAIN_ID `{value}`: {e}"))?;
+        }
+        if let Some(value) = var("ROS_LOCALHOST_ONLY")? {
+            config.localhost_only = match value.trim() {
+                "1" => true,
+                "0" | "" => false,
+                other => bail!("invalid ROS_LOCALHOST_ONLY `{other}`, expected `0` or `1`"),
+            };
+        }
+        if let Some(value) = var("ROS_AUTOMATIC_DISCOVERY_RANGE")? {
+            config.discovery_range = value
+                .parse()
+                .context("invalid ROS_AUTOMATIC_DISCOVERY_RANGE")?;
+        }
+        if let Some(value) = var("ROS_STATIC_PEERS")? {
+            config.static_peers = split_list(&value, ';');
+        }
+        if let Some(value) = var(NETWORK_INTERFACES_ENV)? {
+            config.interfaces = split_list(&value, ',');
+        }
+        config.participant_name = var(PARTICIPANT_NAME_ENV)?;
+        config.security = SecurityConfig::from_vars(var)?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn domain_id(mut self, domain_id: u16) -> Self {
+        self.domain_id = domain_id;
+        self
+    }
+
+    pub fn localhost_only(mut self, localhost_only: bool) -> Self {
+        self.localhost_only = localhost_only;
+        self
+    }
+
+    pub fn discovery_range(mut self, range: DiscoveryRange) -> Self {
+        self.discovery_range = range;
+        self
+    }
+
+    pub fn static_peer(mut self, peer: impl Into<String>) -> Self {
+        self.static_peers.push(peer.into());
+        self
+    }
+
+    pub fn interface(mut self, interface: impl Into<String>) -> Self {
+        self.interfaces.push(interface.into());
+        self
+    }
+
+    pub fn participant_name(mut self, name: impl Into<String>) -> Self {
+        self.participant_name = Some(name.into());
+        self
+    }
+
+    pub fn security(mut self, security: SecurityConfig) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    /// The discovery range after applying the legacy `ROS_LOCALHOST_ONLY` flag.
+    pub fn effective_discovery_range(&self) -> DiscoveryRange {
+        if self.localhost_only {
+            DiscoveryRange::Localhost
+        } else {
+            self.discovery_range
+        }
+    }
+
+    /// Check the configuration for invalid or contradicting values.
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.domain_id > MAX_DOMAIN_ID {
+            bail!(
+                "ROS domain id {} is out of range, must be in 0..={MAX_DOMAIN_ID}",
+                self.domain_id
+            );
+        }
+        if self.localhost_only
+            && !matches!(
+                self.discovery_range,
+                DiscoveryRange::Localhost | DiscoveryRange::SystemDefault
+            )
+        {
+            bail!(
+                "ROS_LOCALHOST_ONLY=1 contradicts discovery range {:?}",
+                self.discovery_range
+            );
+        }
+        if self.effective_discovery_range() == DiscoveryRange::Off && self.static_peers.is_empty()
+        {
+            bail!("discovery range OFF requires at least one static peer");
+        }
+        if let Some(peer) = self
+            .static_peers
+            .iter()
+            .find(|p| p.is_empty() || p.contains(char::is_whitespace))
+        {
+            bail!("invalid static peer `{peer}`");
+        }
+        if let Some(interface) = self.interfaces.iter().find(|i| i.is_empty()) {
+            bail!("invalid network interface `{interface}`");
+        }
+        if self.effective_discovery_range() == DiscoveryRange::Localhost {
+            if let Some(interface) = self.interfaces.iter().find(|i| !is_loopback(i)) {
+                bail!(
+                    "network interface `{interface}` contradicts localhost-only discovery \
+                     (ROS_LOCALHOST_ONLY=1 or discovery range LOCALHOST)"
+                );
+            }
+        }
+        if let Some(name) = &self.participant_name {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("invalid participant name `{name}`, only [A-Za-z0-9_] are allowed");
+            }
+        }
+        if let Some(security) = &self.security {
+            security.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Validate the configuration and create the ROS2 context.
+    pub fn build(&self) -> eyre::Result<ros2_client::Context> {
+        self.validate()?;
+
+        let mut participant = rustdds::DomainParticipantBuilder::new(self.domain_id);
+        match self.effective_discovery_range() {
+            DiscoveryRange::Off => participant = participant.multicast_discovery(false),
+            // `validate` only allows loopback interfaces here
+            DiscoveryRange::Localhost => {
+                participant = participant.only_networks(vec!["lo".to_string()])
+            }
+            DiscoveryRange::Subnet | DiscoveryRange::SystemDefault => {}
+        }
+        if !self.interfaces.is_empty()
+            && self.effective_discovery_range() != DiscoveryRange::Localhost
+        {
+            participant = participant.only_networks(self.interfaces.clone());
+        }
+        if !self.static_peers.is_empty() {
+            participant = participant.initial_peers(self.static_peers.clone());
+        }
+        if let Some(name) = &self.participant_name {
+            participant = participant.participant_name(name.clone());
+        }
+        if let Some(security) = &self.security {
+            participant = security.configure(participant)?;
+        }
+        let participant = participant
+            .build()
+            .map_err(|e| eyre!("failed to create DDS domain participant: {e:?}"))?;
+
+        ros2_client::Context::from_domain_participant(participant)
+            .map_err(|e| eyre!("failed to create ROS2 context on domain {}: {e:?}", self.domain_id))
+    }
+}
+
+pub(crate) fn env_var(name: &str) -> eyre::Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(std::env::VarError::NotUnicode(s)) => {
+            bail!("{name} is not valid unicode: `{}`", s.to_string_lossy())
+        }
+    }
+}
+
+/// Whether `interface` names the loopback interface or one of its addresses.
+fn is_loopback(interface: &str) -> bool {
+    interface == "lo"
+        || interface
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|addr| addr.is_loopback())
+}
+
+fn split_list(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> eyre::Result<Ros2ContextConfig> {
+        Ros2ContextConfig::from_vars(&|name| {
+            Ok(vars
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string()))
+        })
+    }
+
+    #[test]
+    fn domain_id_from_env() {
+        assert_eq!(from_vars(&[]).unwrap().domain_id, 0);
+        assert_eq!(from_vars(&[("ROS_DOMAIN_ID", "42")]).unwrap().domain_id, 42);
+        assert_eq!(from_vars(&[("ROS_DOMAIN_ID", " 7 ")]).unwrap().domain_id, 7);
+        assert_eq!(
+            from_vars(&[("ROS_DOMAIN_ID", "232")]).unwrap().domain_id,
+            MAX_DOMAIN_ID
+        );
+        for value in ["", "abc", "-1", "1.5", "233", "65536"] {
+            assert!(
+                from_vars(&[("ROS_DOMAIN_ID", value)]).is_err(),
+                "ROS_DOMAIN_ID=`{value}` should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn out_of_range_domain() {
+        assert!(
+            Ros2ContextConfig::new()
+                .domain_id(MAX_DOMAIN_ID)
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            Ros2ContextConfig::new()
+                .domain_id(MAX_DOMAIN_ID + 1)
+                .validate()
+                .is_err()
+        );
+        assert!(
+            Ros2ContextConfig::new()
+                .domain_id(u16::MAX)
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn discovery_from_env() {
+        let cases = [
+            (vec![], DiscoveryRange::SystemDefault),
+            (
+                vec![("ROS_LOCALHOST_ONLY", "0")],
+                DiscoveryRange::SystemDefault,
+            ),
+            (vec![("ROS_LOCALHOST_ONLY", "1")], DiscoveryRange::Localhost),
+            (
+                vec![("ROS_AUTOMATIC_DISCOVERY_RANGE", "subnet")],
+                DiscoveryRange::Subnet,
+            ),
+            (
+                vec![
+                    ("ROS_AUTOMATIC_DISCOVERY_RANGE", "OFF"),
+                    ("ROS_STATIC_PEERS", "10.0.0.1; 10.0.0.2;"),
+                ],
+                DiscoveryRange::Off,
+            ),
+        ];
+        for (vars, expected) in cases {
+            let config = from_vars(&vars).unwrap();
+            assert_eq!(config.effective_discovery_range(), expected, "{vars:?}");
+        }
+        let config = from_vars(&[
+            ("ROS_AUTOMATIC_DISCOVERY_RANGE", "OFF"),
+            ("ROS_STATIC_PEERS", "10.0.0.1; 10.0.0.2;"),
+        ])
+        .unwrap();
+        assert_eq!(config.static_peers, ["10.0.0.1", "10.0.0.2"]);
+
+        for vars in [
+            vec![("ROS_LOCALHOST_ONLY", "yes")],
+            vec![("ROS_AUTOMATIC_DISCOVERY_RANGE", "everywhere")],
+            vec![("ROS_AUTOMATIC_DISCOVERY_RANGE", "OFF")],
+            vec![
+                ("ROS_LOCALHOST_ONLY", "1"),
+                ("ROS_AUTOMATIC_DISCOVERY_RANGE", "SUBNET"),
+            ],
+            vec![(PARTICIPANT_NAME_ENV, "dora-bridge")],
+        ] {
+            assert!(from_vars(&vars).is_err(), "{vars:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn interfaces_and_localhost() {
+        let localhost = Ros2ContextConfig::new().localhost_only(true);
+        for interface in ["lo", "127.0.0.1", "::1"] {
+            assert!(
+                localhost.clone().interface(interface).validate().is_ok(),
+                "`{interface}`"
+            );
+        }
+        for interface in ["eth0", "192.168.1.10", ""] {
+            assert!(
+                localhost.clone().interface(interface).validate().is_err(),
+                "`{interface}` should be rejected"
+            );
+        }
+        assert!(
+            Ros2ContextConfig::new()
+                .discovery_range(DiscoveryRange::Localhost)
+                .interface("eth0")
+                .validate()
+                .is_err()
+        );
+        assert!(
+            Ros2ContextConfig::new()
+                .interface("eth0")
+                .validate()
+                .is_ok()
+        );
+
+        let config = from_vars(&[(NETWORK_INTERFACES_ENV, "eth0, wlan0,")]).unwrap();
+        assert_eq!(config.interfaces, ["eth0", "wlan0"]);
+        assert!(
+            from_vars(&[
+                ("ROS_LOCALHOST_ONLY", "1"),
+                (NETWORK_INTERFACES_ENV, "eth0"),
+            ])
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,678 @@
+//! Node health on the standard ROS2 `/diagnostics` pipeline.
+//!
+//! Nodes record what happens on their channels (an input, output or topic)
+//! through a [`Channel`] handle: received or published messages, dropped
+//! frames, errors, queue depths and arbitrary key/values. Once per period a
+//! `diagnostic_msgs/DiagnosticArray` with one status per channel is published
+//! on `/diagnostics`, with levels from the configured [`Thresholds`].
+//!
+//! The configuration is given as a string, e.g. in `ROS2_DIAGNOSTICS`:
+//!
+//! ```text
+//! period=1s,rate_warn=10,rate_error=1,age_warn=500ms,age_error=2s
+//! drop_warn=0.01,drop_error=0.1,queue_warn=0.8,queue_error=1,errors_warn=1,errors_error=10
+//! off
+//! ```
+//!
+//! Rates are in Hz, drop thresholds are the fraction of dropped messages and
+//! queue thresholds the fill ratio of the queue within one period.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eyre::{Context as _, bail, eyre};
+use tracing::warn;
+
+use crate::{
+    BridgeNode, QosConfig,
+    messages::{
+        diagnostic_msgs::msg::{DiagnosticArray, DiagnosticStatus, KeyValue},
+        std_msgs::msg::Header,
+    },
+    qos::QosDuration,
+};
+
+/// Topic the diagnostics are published on.
+pub const DIAGNOSTICS_TOPIC: &str = "/diagnostics";
+/// Environment variable with the diagnostics configuration.
+pub const DIAGNOSTICS_ENV: &str = "ROS2_DIAGNOSTICS";
+
+/// `diagnostic_msgs/DiagnosticStatus` levels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    #[default]
+    Ok = 0,
+    Warn = 1,
+    Error = 2,
+    Stale = 3,
+}
+
+/// Limits at which a channel is reported as WARN or ERROR; unset limits are not checked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thresholds {
+    /// Minimum message rate in Hz.
+    pub rate_warn: Option<f64>,
+    pub rate_error: Option<f64>,
+    /// Maximum time since the last message.
+    pub age_warn: Option<Duration>,
+    pub age_error: Option<Duration>,
+    /// Maximum fraction of dropped messages within a period.
+    pub drop_warn: Option<f64>,
+    pub drop_error: Option<f64>,
+    /// Maximum queue fill ratio.
+    pub queue_warn: Option<f64>,
+    pub queue_error: Option<f64>,
+    /// Maximum number of errors within a period.
+    pub errors_warn: Option<u64>,
+    pub errors_error: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticsConfig {
+    /// Publish on `/diagnostics`; counters are still kept if disabled.
+    pub enabled: bool,
+    pub period: Duration,
+    /// Default thresholds of all channels.
+    pub thresholds: Thresholds,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            period: Duration::from_secs(1),
+            thresholds: Thresholds {
+                queue_warn: Some(0.9),
+                errors_warn: Some(1),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl DiagnosticsConfig {
+    /// Read the configuration from `ROS2_DIAGNOSTICS`, see the [module docs](self).
+    pub fn from_env() -> eyre::Result<Self> {
+        match std::env::var(DIAGNOSTICS_ENV) {
+            Ok(value) => value
+                .parse()
+                .with_context(|| format!("invalid {DIAGNOSTICS_ENV}")),
+            Err(std::env::VarError::NotPresent) => Ok(Self::default()),
+            Err(std::env::VarError::NotUnicode(s)) => {
+                bail!("{DIAGNOSTICS_ENV} is not valid unicode: `{}`", s.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl FromStr for DiagnosticsConfig {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = DiagnosticsConfig::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = match part.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if matches!(part, "off" | "false") => {
+                    config.enabled = false;
+                    continue;
+                }
+                None if matches!(part, "on" | "true") => continue,
+                None => bail!("invalid diagnostics config `{s}`: unexpected `{part}`"),
+            };
+            let t = &mut config.thresholds;
+            match key {
+                "enabled" => config.enabled = parse(key, value)?,
+                "period" => {
+                    config.period = duration(value)?
+                        .filter(|period| !period.is_zero())
+                        .ok_or_else(|| eyre!("period must be finite and positive"))?
+                }
+                "rate_warn" => t.rate_warn = Some(parse(key, value)?),
+                "rate_error" => t.rate_error = Some(parse(key, value)?),
+                "age_warn" => t.age_warn = duration(value)?,
+                "age_error" => t.age_error = duration(value)?,
+                "drop_warn" => t.drop_warn = Some(parse(key, value)?),
+                "drop_error" => t.drop_error = Some(parse(key, value)?),
+                "queue_warn" => t.queue_warn = Some(parse(key, value)?),
+                "queue_error" => t.queue_error = Some(parse(key, value)?),
+                "errors_warn" => t.errors_warn = Some(parse(key, value)?),
+                "errors_error" => t.errors_error = Some(parse(key, value)?),
+                other => bail!("unknown diagnostics setting `{other}`"),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> eyre::Result<T>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| eyre!("invalid {key} `{value}`: {e}"))
+}
+
+/// `inf` means no limit.
+fn duration(value: &str) -> eyre::Result<Option<Duration>> {
+    Ok(match value.parse()? {
+        QosDuration::Infinite => None,
+        QosDuration::Finite(d) => Some(d),
+    })
+}
+
+/// Collects the channel statistics of a node and publishes them on `/diagnostics`.
+///
+/// Cheap to clone; all clones share the same channels.
+#[derive(Clone)]
+pub struct Diagnostics {
+    channels: Arc<Mutex<BTreeMap<String, Arc<Mutex<ChannelState>>>>>,
+    thresholds: Thresholds,
+}
+
+impl Diagnostics {
+    /// Start publishing the diagnostics of `ros_node` in the background.
+    pub fn new(ros_node: &mut BridgeNode, config: DiagnosticsConfig) -> eyre::Result<Self> {
+        let diagnostics = Self {
+            channels: Default::default(),
+            thresholds: config.thresholds.clone(),
+        };
+        if !config.enabled {
+            return Ok(diagnostics);
+        }
+
+        let topic_name = ros_node.resolve_name(DIAGNOSTICS_TOPIC)?;
+        let qos = QosConfig::default().keep_last(10);
+        let topic = ros_node
+            .node_mut()
+            .create_topic(
+                &topic_name,
+                ros2_client::MessageTypeName::new("diagnostic_msgs", "DiagnosticArray"),
+                &qos.to_policies(),
+            )
+            .context("failed to create /diagnostics topic")?;
+        let publisher = ros_node
+            .node_mut()
+            .create_publisher::<DiagnosticArray>(&topic, None)
+            .context("failed to create /diagnostics publisher")?;
+
+        let node_name = ros_node.resolver().fully_qualified_node_name();
+        let clock = ros_node.clock().clone();
+        let channels = diagnostics.channels.clone();
+        ros_node.spawn(async move {
+            let mut last = Instant::now();
+            loop {
+                futures_timer::Delay::new(config.period).await;
+                let elapsed = last.elapsed();
+                last = Instant::now();
+                let status = channels
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(name, state)| {
+                        state.lock().unwrap().report(&node_name, name, elapsed)
+                    })
+                    .collect();
+                let message = DiagnosticArray {
+                    header: Header {
+                        stamp: clock.now(),
+                        frame_id: String::new(),
+                    },
+                    status,
+                };
+                if let Err(err) = publisher.publish(message) {
+                    warn!("failed to publish diagnostics: {err:?}");
+                }
+            }
+        })?;
+        Ok(diagnostics)
+    }
+
+    /// The channel `name`, created with the default thresholds on first use.
+    pub fn channel(&self, name: &str) -> Channel {
+        self.channel_with_thresholds(name, self.thresholds.clone())
+    }
+
+    /// The channel `name`, created with `thresholds` on first use.
+    pub fn channel_with_thresholds(&self, name: &str, thresholds: Thresholds) -> Channel {
+        let state = self
+            .channels
+            .lock()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_insert_with(|| Arc::new(Mutex::new(ChannelState::new(thresholds))))
+            .clone();
+        Channel { state }
+    }
+}
+
+/// Statistics of one input, output or topic, see [`Diagnostics::channel`].
+#[derive(Clone)]
+pub struct Channel {
+    state: Arc<Mutex<ChannelState>>,
+}
+
+impl Channel {
+    /// A message of `bytes` bytes was received or published.
+    pub fn message(&self, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.messages += 1;
+        state.bytes += bytes as u64;
+        state.period_messages += 1;
+        state.last_message = Some(Instant::now());
+    }
+
+    /// `count` messages were dropped, e.g. because a queue was full.
+    pub fn dropped(&self, count: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.dropped += count;
+        state.period_dropped += count;
+    }
+
+    pub fn error(&self, error: impl fmt::Display) {
+        let mut state = self.state.lock().unwrap();
+        state.errors += 1;
+        state.period_errors += 1;
+        state.last_error = Some(error.to_string());
+    }
+
+    /// Current depth and capacity of the channel's queue.
+    pub fn queue(&self, depth: usize, capacity: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.queue = Some((depth, capacity));
+        state.max_queue_depth = state.max_queue_depth.max(depth);
+    }
+
+    /// An additional key/value reported with the channel status.
+    pub fn value(&self, key: &str, value: impl ToString) {
+        self.state
+            .lock()
+            .unwrap()
+            .values
+            .insert(key.to_owned(), value.to_string());
+    }
+}
+
+struct ChannelState {
+    thresholds: Thresholds,
+    messages: u64,
+    bytes: u64,
+    dropped: u64,
+    errors: u64,
+    period_messages: u64,
+    period_dropped: u64,
+    period_errors: u64,
+    last_message: Option<Instant>,
+    last_error: Option<String>,
+    queue: Option<(usize, usize)>,
+    /// Highest queue depth within the current period.
+    max_queue_depth: usize,
+    values: BTreeMap<String, String>,
+}
+
+impl ChannelState {
+    fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            messages: 0,
+            bytes: 0,
+            dropped: 0,
+            errors: 0,
+            period_messages: 0,
+            period_dropped: 0,
+            period_errors: 0,
+            last_message: None,
+            last_error: None,
+            queue: None,
+            max_queue_depth: 0,
+            values: BTreeMap::new(),
+        }
+    }
+
+    /// Build the status for the period of length `elapsed` and start a new period.
+    fn report(&mut self, node_name: &str, channel: &str, elapsed: Duration) -> DiagnosticStatus {
+        let t = &self.thresholds;
+        let rate = self.period_messages as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let age = self.last_message.map(|last| last.elapsed());
+        let total = self.period_messages + self.period_dropped;
+        let drop_ratio = if total == 0 {
+            0.0
+        } else {
+            self.period_dropped as f64 / total as f64
+        };
+        let queue_fill = self
+            .queue
+            .filter(|(_, capacity)| *capacity > 0)
+            .map(|(_, capacity)| self.max_queue_depth as f64 / capacity as f64);
+
+        let mut checks = Checks::default();
+        checks.below("rate", rate, t.rate_warn, t.rate_error, "Hz");
+        match age {
+            Some(age) => checks.above(
+                "last message age",
+                age.as_secs_f64(),
+                t.age_warn.map(|d| d.as_secs_f64()),
+                t.age_error.map(|d| d.as_secs_f64()),
+                "s",
+            ),
+            None if t.age_error.is_some() => {
+                checks.add(Level::Error, "no message received".to_owned())
+            }
+            None if t.age_warn.is_some() => {
+                checks.add(Level::Warn, "no message received".to_owned())
+            }
+            None => {}
+        }
+        checks.above("dropped", drop_ratio, t.drop_warn, t.drop_error, "");
+        if let Some(fill) = queue_fill {
+            checks.above("queue fill", fill, t.queue_warn, t.queue_error, "");
+        }
+        checks.above(
+            "errors",
+            self.period_errors as f64,
+            t.errors_warn.map(|n| n as f64),
+            t.errors_error.map(|n| n as f64),
+            "",
+        );
+
+        let mut values = vec![
+            key_value("rate_hz", format!("{rate:.2}")),
+            key_value("messages", self.messages),
+            key_value("bytes", self.bytes),
+            key_value("dropped", self.dropped),
+            key_value("dropped_in_period", self.period_dropped),
+            key_value("errors", self.errors),
+            key_value("errors_in_period", self.period_errors),
+        ];
+        if let Some(age) = age {
+            values.push(key_value("last_message_age_s", format!("{:.3}", age.as_secs_f64())));
+        }
+        if let Some((depth, capacity)) = self.queue {
+            values.push(key_value("queue_depth", depth));
+            values.push(key_value("queue_max_depth", self.max_queue_depth));
+            values.push(key_value("queue_capacity", capacity));
+        }
+        if let Some(error) = &self.last_error {
+            values.push(key_value("last_error", error));
+        }
+        values.extend(self.values.iter().map(|(k, v)| key_value(k, v)));
+
+        self.period_messages = 0;
+        self.period_dropped = 0;
+        self.period_errors = 0;
+        self.max_queue_depth = self.queue.map(|(depth, _)| depth).unwrap_or(0);
+
+        DiagnosticStatus {
+            level: checks.level as u8,
+            name: format!("{node_name}: {channel}"),
+            message: checks.message(),
+            hardware_id: node_name.to_owned(),
+            values,
+        }
+    }
+}
+
+/// Levels and reasons of the threshold checks of one status.
+#[derive(Default)]
+struct Checks {
+    level: Level,
+    reasons: Vec<String>,
+}
+
+impl Checks {
+    fn add(&mut self, level: Level, reason: String) {
+        self.level = self.level.max(level);
+        self.reasons.push(reason);
+    }
+
+    fn below(&mut self, name: &str, value: f64, warn: Option<f64>, error: Option<f64>, unit: &str) {
+        if error.is_some_and(|limit| value < limit) {
+            self.add(Level::Error, format!("{name} {value:.2}{unit} < {}{unit}", error.unwrap()));
+        } else if warn.is_some_and(|limit| value < limit) {
+            self.add(Level::Warn, format!("{name} {value:.2}{unit} < {}{unit}", warn.unwrap()));
+        }
+    }
+
+    fn above(&mut self, name: &str, value: f64, warn: Option<f64>, error: Option<f64>, unit: &str) {
+        if error.is_some_and(|limit| value >= limit) {
+            self.add(Level::Error, format!("{name} {value:.2}{unit} >= {}{unit}", error.unwrap()));
+        } else if warn.is_some_and(|limit| value >= limit) {
+            self.add(Level::Warn, format!("{name} {value:.2}{unit} >= {}{unit}", warn.unwrap()));
+        }
+    }
+
+    fn message(&self) -> String {
+        if self.reasons.is_empty() {
+            "OK".to_owned()
+        } else {
+            self.reasons.join(", ")
+        }
+    }
+}
+
+fn key_value(key: &str, value: impl ToString) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let defaults = DiagnosticsConfig::default();
+        let cases = [
+            ("", defaults.clone()),
+            ("on", defaults.clone()),
+            (
+                "off",
+                DiagnosticsConfig {
+                    enabled: false,
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "enabled=false,period=250ms",
+                DiagnosticsConfig {
+                    enabled: false,
+                    period: Duration::from_millis(250),
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "rate_warn=10, rate_error=1, age_warn=500ms, age_error=inf",
+                DiagnosticsConfig {
+                    thresholds: Thresholds {
+                        rate_warn: Some(10.0),
+                        rate_error: Some(1.0),
+                        age_warn: Some(Duration::from_millis(500)),
+                        age_error: None,
+                        ..defaults.thresholds.clone()
+                    },
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "drop_warn=0.01,drop_error=0.1,queue_warn=0.8,queue_error=1,errors_warn=2",
+                DiagnosticsConfig {
+                    thresholds: Thresholds {
+                        drop_warn: Some(0.01),
+                        drop_error: Some(0.1),
+                        queue_warn: Some(0.8),
+                        queue_error: Some(1.0),
+                        errors_warn: Some(2),
+                        ..Default::default()
+                    },
+                    ..defaults.clone()
+                },
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                input.parse::<DiagnosticsConfig>().ok(),
+                Some(expected),
+                "`{input}`"
+            );
+        }
+    }
+
+    #[test]
+    fn reject_invalid_config() {
+        for input in [
+            "period=0s",
+            "period=0",
+            "period=inf",
+            "period=-1s",
+            "sometimes",
+            "rate_warn=fast",
+            "errors_warn=-1",
+            "enabled=maybe",
+            "colour=blue",
+        ] {
+            assert!(
+                input.parse::<DiagnosticsConfig>().is_err(),
+                "`{input}` should be rejected"
+            );
+        }
+    }
+
+    fn report(thresholds: &str, record: impl Fn(&Channel)) -> DiagnosticStatus {
+        let thresholds = thresholds.parse::<DiagnosticsConfig>().unwrap().thresholds;
+        let channel = Channel {
+            state: Arc::new(Mutex::new(ChannelState::new(thresholds))),
+        };
+        record(&channel);
+        let mut state = channel.state.lock().unwrap();
+        state.report("/node", "input", Duration::from_secs(1))
+    }
+
+    /// Records activity on a channel before the report.
+    type Record = Box<dyn Fn(&Channel)>;
+
+    fn messages(count: usize) -> impl Fn(&Channel) {
+        move |channel| (0..count).for_each(|_| channel.message(8))
+    }
+
+    #[test]
+    fn threshold_levels() {
+        let cases: [(&str, Record, Level); 14] = [
+            ("", Box::new(|_: &Channel| {}), Level::Ok),
+            (
+                "rate_warn=10,rate_error=1",
+                Box::new(messages(20)),
+                Level::Ok,
+            ),
+            (
+                "rate_warn=10,rate_error=1",
+                Box::new(messages(5)),
+                Level::Warn,
+            ),
+            (
+                "rate_warn=10,rate_error=1",
+                Box::new(messages(0)),
+                Level::Error,
+            ),
+            ("age_warn=1s", Box::new(messages(0)), Level::Warn),
+            (
+                "age_warn=1s,age_error=2s",
+                Box::new(messages(0)),
+                Level::Error,
+            ),
+            ("age_warn=1s,age_error=2s", Box::new(messages(1)), Level::Ok),
+            (
+                "drop_warn=0.05,drop_error=0.2",
+                Box::new(|c: &Channel| {
+                    messages(9)(c);
+                    c.dropped(1);
+                }),
+                Level::Warn,
+            ),
+            (
+                "drop_warn=0.05,drop_error=0.2",
+                Box::new(|c: &Channel| {
+                    messages(3)(c);
+                    c.dropped(1);
+                }),
+                Level::Error,
+            ),
+            ("", Box::new(|c: &Channel| c.queue(8, 10)), Level::Ok),
+            ("", Box::new(|c: &Channel| c.queue(9, 10)), Level::Warn),
+            (
+                "queue_error=1",
+                Box::new(|c: &Channel| {
+                    c.queue(10, 10);
+                    c.queue(2, 10);
+                }),
+                Level::Error,
+            ),
+            ("", Box::new(|c: &Channel| c.error("failed")), Level::Warn),
+            (
+                "errors_error=3",
+                Box::new(|c: &Channel| (0..3).for_each(|_| c.error("failed"))),
+                Level::Error,
+            ),
+        ];
+        for (i, (thresholds, record, expected)) in cases.into_iter().enumerate() {
+            let status = report(thresholds, record);
+            assert_eq!(
+                status.level, expected as u8,
+                "case {i} `{thresholds}`: {}",
+                status.message
+            );
+            if expected == Level::Ok {
+                assert_eq!(status.message, "OK");
+            }
+        }
+    }
+
+    #[test]
+    fn report_starts_new_period() {
+        let thresholds = "rate_warn=1"
+            .parse::<DiagnosticsConfig>()
+            .unwrap()
+            .thresholds;
+        let channel = Channel {
+            state: Arc::new(Mutex::new(ChannelState::new(thresholds))),
+        };
+        channel.message(100);
+        channel.error("failed");
+        channel.value("mode", "auto");
+
+        let mut state = channel.state.lock().unwrap();
+        let first = state.report("/node", "input", Duration::from_secs(1));
+        assert_eq!(first.name, "/node: input");
+        assert_eq!(first.hardware_id, "/node");
+        assert_eq!(first.level, Level::Warn as u8, "{}", first.message);
+        let value = |status: &DiagnosticStatus, key: &str| {
+            status
+                .values
+                .iter()
+                .find(|kv| kv.key == key)
+                .map(|kv| kv.value.clone())
+        };
+        assert_eq!(value(&first, "errors_in_period").as_deref(), Some("1"));
+        assert_eq!(value(&first, "last_error").as_deref(), Some("failed"));
+        assert_eq!(value(&first, "mode").as_deref(), Some("auto"));
+
+        // no messages and no errors in the second period
+        let second = state.report("/node", "input", Duration::from_secs(1));
+        assert_eq!(second.level, Level::Warn as u8, "{}", second.message);
+        assert!(
+            second.message.starts_with("rate 0.00Hz < 1Hz"),
+            "{}",
+            second.message
+        );
+        assert_eq!(value(&second, "errors_in_period").as_deref(), Some("0"));
+        assert_eq!(value(&second, "messages").as_deref(), Some("1"));
+        assert_eq!(value(&second, "bytes").as_deref(), Some("100"));
+    }
+}
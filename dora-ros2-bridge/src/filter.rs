@@ -0,0 +1,1038 @@
+//! Content filters for bridged messages, using the SQL subset of DDS
+//! content-filtered topics.
+//!
+//! A filter is evaluated against the typed fields of a message, given as an
+//! Arrow struct array (see [`arrow_convert`](crate::arrow_convert)):
+//!
+//! ```text
+//! battery_level < 20
+//! header.frame_id = 'camera_frame' AND width >= 640
+//! NOT (mode = 'idle' OR mode LIKE 'test_%')
+//! linear.x BETWEEN -0.5 AND 0.5
+//! status.level IN (1, 2)
+//! ranges[0] > %0
+//! ```
+//!
+//! Fields are addressed by name, nested fields with `.` and elements of
+//! sequences and arrays with `[index]`. Operands are compared with `=`, `<>`
+//! (or `!=`), `<`, `<=`, `>`, `>=`, `BETWEEN`, `IN` and `LIKE`, where `%`
+//! matches any number of characters and `_` a single one. Keywords are case
+//! insensitive, strings are quoted with `'`, and `%0`, `%1`, ... refer to the
+//! filter parameters.
+
+use std::{cmp::Ordering, fmt, str::FromStr, sync::Arc};
+
+use dora_node_api::arrow::{
+    array::{Array, ArrayRef, AsArray, StructArray},
+    datatypes::{
+        DataType, Float32Type, Float64Type, Int8Type, Int16Type, Int32Type, Int64Type,
+        UInt8Type, UInt16Type, UInt32Type, UInt64Type,
+    },
+};
+use eyre::{ContextCompat, bail, eyre};
+use serde::Deserialize;
+
+/// A parsed filter expression, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentFilter {
+    expression: String,
+    parameters: Vec<String>,
+    expr: Expr,
+}
+
+impl ContentFilter {
+    pub fn new(expression: &str) -> eyre::Result<Self> {
+        Self::with_parameters(expression, Vec::<String>::new())
+    }
+
+    /// A filter whose `%0`, `%1`, ... placeholders are replaced by `parameters`.
+    ///
+    /// Parameters are literals like in the expression (`20`, `'camera_frame'`,
+    /// `TRUE`); values that are no valid literal are taken as strings.
+    pub fn with_parameters(
+        expression: &str,
+        parameters: impl IntoIterator<Item = impl Into<String>>,
+    ) -> eyre::Result<Self> {
+        let parameters: Vec<String> = parameters.into_iter().map(Into::into).collect();
+        let values = parameters
+            .iter()
+            .map(|p| parse_literal(p).unwrap_or_else(|_| Value::String(p.clone())))
+            .collect::<Vec<_>>();
+        let expr = Parser::new(expression, &values)
+            .and_then(Parser::parse)
+            .map_err(|e| eyre!("invalid content filter `{expression}`: {e}"))?;
+        Ok(Self {
+            expression: expression.to_owned(),
+            parameters,
+            expr,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    /// Evaluate the filter on a message given as a struct array of length 1.
+    pub fn matches(&self, message: &dyn Array) -> eyre::Result<bool> {
+        let message = message
+            .as_struct_opt()
+            .with_context(|| format!("expected struct array, got {}", message.data_type()))?;
+        if message.len() != 1 {
+            bail!("expected struct array of length 1, got length {}", message.len());
+        }
+        self.matches_row(message, 0)
+    }
+
+    /// Evaluate the filter on row `row` of a struct array of messages.
+    pub fn matches_row(&self, messages: &StructArray, row: usize) -> eyre::Result<bool> {
+        let root: ArrayRef = Arc::new(messages.clone());
+        self.expr
+            .eval(&root, row)
+            .map_err(|e| eyre!("failed to evaluate content filter `{}`: {e}", self.expression))
+    }
+}
+
+impl fmt::Display for ContentFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl FromStr for ContentFilter {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+/// Either just the expression, or the expression with its parameters:
+///
+/// ```yaml
+/// filter: battery_level < 20
+/// filter:
+///   expression: header.frame_id = %0
+///   parameters: [camera_frame]
+/// ```
+impl<'de> Deserialize<'de> for ContentFilter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Parameter {
+            Bool(bool),
+            Integer(i64),
+            Float(f64),
+            Text(String),
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Full {
+                expression: String,
+                #[serde(default)]
+                parameters: Vec<Parameter>,
+            },
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(expression) => Self::new(&expression),
+            Raw::Full {
+                expression,
+                parameters,
+            } => Self::with_parameters(
+                &expression,
+                parameters.into_iter().map(|p| match p {
+                    Parameter::Bool(b) => b.to_string(),
+                    Parameter::Integer(i) => i.to_string(),
+                    Parameter::Float(f) => f.to_string(),
+                    Parameter::Text(s) => s,
+                }),
+            ),
+        }
+        .map_err(|e| serde::de::Error::custom(format!("{e:#}")))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Integer(i128),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Value::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn apply(self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FieldPath {
+    name: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Field(FieldPath),
+    Literal(Value),
+}
+
+impl Operand {
+    fn eval(&self, root: &ArrayRef, row: usize) -> Result<Value, String> {
+        match self {
+            Operand::Literal(value) => Ok(value.clone()),
+            Operand::Field(path) => path.eval(root, row),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+    Between {
+        operand: Operand,
+        low: Operand,
+        high: Operand,
+    },
+    In {
+        operand: Operand,
+        values: Vec<Operand>,
+    },
+    Like {
+        operand: Operand,
+        pattern: Vec<char>,
+    },
+}
+
+impl Expr {
+    fn eval(&self, root: &ArrayRef, row: usize) -> Result<bool, String> {
+        match self {
+            Expr::And(a, b) => Ok(a.eval(root, row)? && b.eval(root, row)?),
+            Expr::Or(a, b) => Ok(a.eval(root, row)? || b.eval(root, row)?),
+            Expr::Not(e) => Ok(!e.eval(root, row)?),
+            Expr::Compare(a, op, b) => {
+                let (a, b) = (a.eval(root, row)?, b.eval(root, row)?);
+                Ok(compare(&a, &b)?.is_some_and(|o| op.apply(o)))
+            }
+            Expr::Between { operand, low, high } => {
+                let value = operand.eval(root, row)?;
+                let low = compare(&value, &low.eval(root, row)?)?;
+                let high = compare(&value, &high.eval(root, row)?)?;
+                Ok(low.is_some_and(|o| o != Ordering::Less)
+                    && high.is_some_and(|o| o != Ordering::Greater))
+            }
+            Expr::In { operand, values } => {
+                let value = operand.eval(root, row)?;
+                for candidate in values {
+                    if compare(&value, &candidate.eval(root, row)?)? == Some(Ordering::Equal) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Expr::Like { operand, pattern } => match operand.eval(root, row)? {
+                Value::String(s) => Ok(like(&s.chars().collect::<Vec<_>>(), pattern)),
+                other => Err(format!("LIKE needs a string, got {other}")),
+            },
+        }
+    }
+}
+
+/// Ordering of two values, `None` if one of them is NaN.
+fn compare(a: &Value, b: &Value) -> Result<Option<Ordering>, String> {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(Some(a.cmp(b))),
+        (Value::Integer(a), Value::Float(b)) => Ok((*a as f64).partial_cmp(b)),
+        (Value::Float(a), Value::Integer(b)) => Ok(a.partial_cmp(&(*b as f64))),
+        (Value::Float(a), Value::Float(b)) => Ok(a.partial_cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Ok(Some(a.cmp(b))),
+        (Value::String(a), Value::String(b)) => Ok(Some(a.cmp(b))),
+        _ => Err(format!("cannot compare {a} with {b}")),
+    }
+}
+
+/// SQL `LIKE`: `%` matches any sequence of characters, `_` a single one.
+///
+/// On a mismatch only the last `%` is retried, one character further into
+/// the text, so matching takes at most `text.len() * pattern.len()` steps.
+fn like(text: &[char], pattern: &[char]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    // pattern position after the last `%`, and the text position it matched up to
+    let mut last_wildcard = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                last_wildcard = Some((p, t));
+            }
+            Some(c) if *c == '_' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match last_wildcard {
+                Some((after_wildcard, matched)) => {
+                    p = after_wildcard;
+                    t = matched + 1;
+                    last_wildcard = Some((after_wildcard, t));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+impl FieldPath {
+    fn eval(&self, root: &ArrayRef, row: usize) -> Result<Value, String> {
+        let mut array = root.clone();
+        let mut row = row;
+        for (i, segment) in self.segments.iter().enumerate() {
+            match (segment, array.data_type()) {
+                (Segment::Field(name), DataType::Struct(_)) => {
+                    let column = array.as_struct().column_by_name(name).cloned();
+                    array = column.ok_or_else(|| format!("no field `{name}` in `{}`", self.name))?;
+                }
+                (Segment::Index(index), DataType::List(_)) => {
+                    let items = array.as_list::<i32>().value(row);
+                    (array, row) = (items, *index);
+                }
+                (Segment::Index(index), DataType::LargeList(_)) => {
+                    let items = array.as_list::<i64>().value(row);
+                    (array, row) = (items, *index);
+                }
+                (Segment::Index(index), DataType::FixedSizeList(_, _)) => {
+                    let items = array.as_fixed_size_list().value(row);
+                    (array, row) = (items, *index);
+                }
+                (Segment::Index(index), DataType::Binary | DataType::FixedSizeBinary(_))
+                    if i + 1 == self.segments.len() =>
+                {
+                    let bytes = match array.data_type() {
+                        DataType::Binary => array.as_binary::<i32>().value(row),
+                        _ => array.as_fixed_size_binary().value(row),
+                    };
+                    return bytes
+                        .get(*index)
+                        .map(|b| Value::Integer((*b).into()))
+                        .ok_or_else(|| format!("index out of bounds in `{}`", self.name));
+                }
+                (segment, data_type) => {
+                    return Err(format!(
+                        "cannot access {} of `{}`, which has type {data_type}",
+                        match segment {
+                            Segment::Field(name) => format!("field `{name}`"),
+                            Segment::Index(index) => format!("index {index}"),
+                        },
+                        self.name
+                    ));
+                }
+            }
+            if row >= array.len() {
+                return Err(format!("index out of bounds in `{}`", self.name));
+            }
+        }
+        scalar(&array, row).map_err(|e| format!("field `{}`: {e}", self.name))
+    }
+}
+
+fn scalar(array: &ArrayRef, row: usize) -> Result<Value, String> {
+    if array.is_null(row) {
+        return Err("value is null".to_owned());
+    }
+    macro_rules! integer {
+        ($t:ty) => {
+            Value::Integer(array.as_primitive::<$t>().value(row).into())
+        };
+    }
+    Ok(match array.data_type() {
+        DataType::Int8 => integer!(Int8Type),
+        DataType::Int16 => integer!(Int16Type),
+        DataType::Int32 => integer!(Int32Type),
+        DataType::Int64 => integer!(Int64Type),
+        DataType::UInt8 => integer!(UInt8Type),
+        DataType::UInt16 => integer!(UInt16Type),
+        DataType::UInt32 => integer!(UInt32Type),
+        DataType::UInt64 => integer!(UInt64Type),
+        DataType::Float32 => Value::Float(array.as_primitive::<Float32Type>().value(row).into()),
+        DataType::Float64 => Value::Float(array.as_primitive::<Float64Type>().value(row)),
+        DataType::Boolean => Value::Bool(array.as_boolean().value(row)),
+        DataType::Utf8 => Value::String(array.as_string::<i32>().value(row).to_owned()),
+        DataType::LargeUtf8 => Value::String(array.as_string::<i64>().value(row).to_owned()),
+        other => return Err(format!("values of type {other} cannot be compared")),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Integer(i128),
+    Float(f64),
+    String(String),
+    Parameter(usize),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "`{s}`"),
+            Token::Integer(i) => write!(f, "`{i}`"),
+            Token::Float(x) => write!(f, "`{x}`"),
+            Token::String(s) => write!(f, "'{s}'"),
+            Token::Parameter(i) => write!(f, "`%{i}`"),
+            Token::Op(op) => write!(f, "`{op}`"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    const OPS: [&str; 13] = [
+        "<>", "!=", "<=", ">=", "=", "<", ">", "(", ")", ".", "[", "]", ",",
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let position = input.len() - rest.len();
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_owned()));
+            len
+        } else if c.is_ascii_digit()
+            || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let (token, len) = number(rest)?;
+            tokens.push(token);
+            len
+        } else if c == '\'' {
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let len = loop {
+                match chars.next() {
+                    Some((i, '\'')) if rest[i + 1..].starts_with('\'') => {
+                        value.push('\'');
+                        chars.next();
+                    }
+                    Some((i, '\'')) => break i + 1,
+                    Some((_, c)) => value.push(c),
+                    None => return Err(format!("unterminated string at position {position}")),
+                }
+            };
+            tokens.push(Token::String(value));
+            len
+        } else if c == '%' {
+            let len = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            let index = rest[1..=len]
+                .parse()
+                .map_err(|_| format!("expected parameter index after `%` at position {position}"))?;
+            tokens.push(Token::Parameter(index));
+            len + 1
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(format!("unexpected character `{c}` at position {position}"));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Lex a (possibly negative) integer or float at the start of `s`.
+fn number(s: &str) -> Result<(Token, usize), String> {
+    let mut len = usize::from(s.starts_with('-'));
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    len += digits(&s[len..]);
+    let mut float = false;
+    if s[len..].starts_with('.') && s[len + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+        float = true;
+        len += 1 + digits(&s[len + 1..]);
+    }
+    if s[len..].starts_with(['e', 'E']) {
+        let sign = usize::from(s[len + 1..].starts_with(['+', '-']));
+        let exponent = digits(&s[len + 1 + sign..]);
+        if exponent > 0 {
+            float = true;
+            len += 1 + sign + exponent;
+        }
+    }
+    let text = &s[..len];
+    let token = if float {
+        Token::Float(text.parse().map_err(|e| format!("invalid number `{text}`: {e}"))?)
+    } else {
+        Token::Integer(text.parse().map_err(|e| format!("invalid number `{text}`: {e}"))?)
+    };
+    Ok((token, len))
+}
+
+/// Parse a single literal, as used for filter parameters.
+fn parse_literal(text: &str) -> Result<Value, String> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        parameters: &[],
+    };
+    match parser.operand()? {
+        Operand::Literal(value) if parser.position == parser.tokens.len() => Ok(value),
+        _ => Err(format!("`{text}` is not a literal")),
+    }
+}
+
+/// Recursive descent parser for
+///
+/// ```text
+/// expr    = and ("OR" and)*
+/// and     = not ("AND" not)*
+/// not     = "NOT" not | "(" expr ")" | operand predicate
+/// predicate = cmp_op operand | ["NOT"] "BETWEEN" operand "AND" operand
+///           | ["NOT"] "IN" "(" operand ("," operand)* ")" | ["NOT"] "LIKE" string
+/// operand = field | number | string | "TRUE" | "FALSE" | %n
+/// field   = ident ("." ident | "[" integer "]")*
+/// ```
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    parameters: &'a [Value],
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &str, parameters: &'a [Value]) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(input)?,
+            position: 0,
+            parameters,
+        })
+    }
+
+    fn parse(mut self) -> Result<Expr, String> {
+        if self.tokens.is_empty() {
+            return Err("empty expression".to_owned());
+        }
+        let expr = self.or()?;
+        match self.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {token}")),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_owned())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn op(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        if self.op(op) {
+            Ok(())
+        } else {
+            Err(match self.peek() {
+                Some(token) => format!("expected `{op}`, found {token}"),
+                None => format!("expected `{op}` at end of expression"),
+            })
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.op("(") {
+            let expr = self.or()?;
+            self.expect_op(")")?;
+            return Ok(expr);
+        }
+        let operand = self.operand()?;
+        self.predicate(operand)
+    }
+
+    fn predicate(&mut self, operand: Operand) -> Result<Expr, String> {
+        let negated = self.keyword("NOT");
+        let expr = if self.keyword("BETWEEN") {
+            let low = self.operand()?;
+            if !self.keyword("AND") {
+                return Err("expected `AND` in BETWEEN".to_owned());
+            }
+            let high = self.operand()?;
+            Expr::Between { operand, low, high }
+        } else if self.keyword("IN") {
+            self.expect_op("(")?;
+            let mut values = vec![self.operand()?];
+            while self.op(",") {
+                values.push(self.operand()?);
+            }
+            self.expect_op(")")?;
+            Expr::In { operand, values }
+        } else if self.keyword("LIKE") {
+            match self.operand()? {
+                Operand::Literal(Value::String(pattern)) => Expr::Like {
+                    operand,
+                    pattern: pattern.chars().collect(),
+                },
+                _ => return Err("LIKE needs a string pattern".to_owned()),
+            }
+        } else if negated {
+            return Err("expected `BETWEEN`, `IN` or `LIKE` after `NOT`".to_owned());
+        } else {
+            let op = match self.next()? {
+                Token::Op("=") => CompareOp::Eq,
+                Token::Op("<>" | "!=") => CompareOp::Ne,
+                Token::Op("<") => CompareOp::Lt,
+                Token::Op("<=") => CompareOp::Le,
+                Token::Op(">") => CompareOp::Gt,
+                Token::Op(">=") => CompareOp::Ge,
+                token => return Err(format!("expected comparison operator, found {token}")),
+            };
+            Expr::Compare(operand, op, self.operand()?)
+        };
+        Ok(if negated { Expr::Not(Box::new(expr)) } else { expr })
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        Ok(match self.next()? {
+            Token::Integer(i) => Operand::Literal(Value::Integer(i)),
+            Token::Float(x) => Operand::Literal(Value::Float(x)),
+            Token::String(s) => Operand::Literal(Value::String(s)),
+            Token::Parameter(index) => Operand::Literal(
+                self.parameters
+                    .get(index)
+                    .cloned()
+                    .ok_or_else(|| format!("missing filter parameter %{index}"))?,
+            ),
+            Token::Ident(s) if s.eq_ignore_ascii_case("TRUE") => {
+                Operand::Literal(Value::Bool(true))
+            }
+            Token::Ident(s) if s.eq_ignore_ascii_case("FALSE") => {
+                Operand::Literal(Value::Bool(false))
+            }
+            Token::Ident(s) if is_keyword(&s) => return Err(format!("unexpected keyword `{s}`")),
+            Token::Ident(first) => {
+                let mut name = first.clone();
+                let mut segments = vec![Segment::Field(first)];
+                loop {
+                    if self.op(".") {
+                        match self.next()? {
+                            Token::Ident(field) => {
+                                name.push('.');
+                                name.push_str(&field);
+                                segments.push(Segment::Field(field));
+                            }
+                            token => return Err(format!("expected field name, found {token}")),
+                        }
+                    } else if self.op("[") {
+                        match self.next()? {
+                            Token::Integer(index) if index >= 0 => {
+                                name.push_str(&format!("[{index}]"));
+                                segments.push(Segment::Index(index as usize));
+                            }
+                            token => return Err(format!("expected index, found {token}")),
+                        }
+                        self.expect_op("]")?;
+                    } else {
+                        break;
+                    }
+                }
+                Operand::Field(FieldPath { name, segments })
+            }
+            token => return Err(format!("expected field or value, found {token}")),
+        })
+    }
+}
+
+fn is_keyword(ident: &str) -> bool {
+    ["AND", "OR", "NOT", "BETWEEN", "IN", "LIKE"]
+        .iter()
+        .any(|k| ident.eq_ignore_ascii_case(k))
+}
+
+#[cfg(test)]
+mod tests {
+    use dora_node_api::arrow::{
+        array::{
+            BinaryArray, BooleanArray, Float64Array, Int32Array, ListArray, StringArray,
+            UInt8Array,
+        },
+        datatypes::Field,
+    };
+
+    use super::*;
+
+    fn column(name: &str, array: ArrayRef) -> (Arc<Field>, ArrayRef) {
+        (Arc::new(Field::new(name, array.data_type().clone(), true)), array)
+    }
+
+    fn structure(columns: Vec<(Arc<Field>, ArrayRef)>) -> ArrayRef {
+        Arc::new(StructArray::from(columns))
+    }
+
+    /// A status message with nested structs, a sequence and a byte array.
+    fn message(battery_level: u8, mode: &str, frame_id: &str) -> StructArray {
+        let stamp = structure(vec![column("sec", Arc::new(Int32Array::from(vec![42])))]);
+        let header = structure(vec![
+            column("frame_id", Arc::new(StringArray::from(vec![frame_id]))),
+            column("stamp", stamp),
+        ]);
+        let linear = structure(vec![column("x", Arc::new(Float64Array::from(vec![0.25])))]);
+        let ranges = ListArray::from_iter_primitive::<Float32Type, _, _>([Some([
+            Some(1.5),
+            Some(2.5),
+            Some(f32::NAN),
+        ])]);
+        let data: &[u8] = &[7, 255];
+        StructArray::from(vec![
+            column("header", header),
+            column("battery_level", Arc::new(UInt8Array::from(vec![battery_level]))),
+            column("mode", Arc::new(StringArray::from(vec![mode]))),
+            column("charging", Arc::new(BooleanArray::from(vec![true]))),
+            column("linear", linear),
+            column("ranges", Arc::new(ranges)),
+            column("data", Arc::new(BinaryArray::from(vec![data]))),
+        ])
+    }
+
+    fn expr(expression: &str) -> Expr {
+        ContentFilter::new(expression).unwrap().expr
+    }
+
+    fn check(message: &StructArray, cases: &[(&str, bool)]) {
+        for (expression, expected) in cases {
+            let filter = ContentFilter::new(expression).unwrap();
+            assert_eq!(
+                filter.matches(message).unwrap(),
+                *expected,
+                "`{expression}`"
+            );
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            expr("a = 1 OR b = 2 AND c = 3"),
+            expr("a = 1 OR (b = 2 AND c = 3)")
+        );
+        assert_ne!(
+            expr("a = 1 OR b = 2 AND c = 3"),
+            expr("(a = 1 OR b = 2) AND c = 3")
+        );
+        assert_eq!(expr("NOT a = 1 AND b = 2"), expr("(NOT a = 1) AND b = 2"));
+        assert_eq!(expr("a = 1 OR b = 2 OR c = 3"), expr("(a = 1 OR b = 2) OR c = 3"));
+        assert_eq!(expr("a = 1 or NOT b = 2"), expr("a = 1 OR not b = 2"));
+
+        check(
+            &message(10, "idle", "base"),
+            &[
+                ("battery_level < 20 OR mode = 'run' AND battery_level > 50", true),
+                ("(battery_level < 20 OR mode = 'run') AND battery_level > 50", false),
+                ("NOT mode = 'run' AND battery_level = 10", true),
+                ("NOT (mode = 'idle' AND battery_level = 10)", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        check(
+            &message(10, "idle", "base"),
+            &[
+                ("battery_level = 10", true),
+                ("battery_level <> 10", false),
+                ("battery_level != 11", true),
+                ("battery_level < 10", false),
+                ("battery_level <= 10", true),
+                ("battery_level > 9.5", true),
+                ("battery_level >= 1e1", true),
+                ("10 = battery_level", true),
+                ("mode < 'run'", true),
+                ("charging = TRUE", true),
+                ("charging = false", false),
+                ("linear.x = 0.25", true),
+                ("linear.x > -1", true),
+                // comparisons with NaN are false
+                ("ranges[2] = ranges[2]", false),
+                ("ranges[2] <> 0", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn string_escapes() {
+        check(
+            &message(10, "it's", "base"),
+            &[
+                ("mode = 'it''s'", true),
+                ("mode = 'it'", false),
+                ("mode LIKE '%''s'", true),
+                ("mode <> ''", true),
+            ],
+        );
+        check(&message(10, "", "base"), &[("mode = ''", true)]);
+        let parameter = ContentFilter::with_parameters("mode = %0", ["'a ''quoted'' value'"]);
+        assert_eq!(parameter.unwrap().expr, expr("mode = 'a ''quoted'' value'"));
+        assert_eq!(
+            parse_literal("'a ''quoted'' value'"),
+            Ok(Value::String("a 'quoted' value".to_owned()))
+        );
+        assert_eq!(Value::String("it's".to_owned()).to_string(), "'it''s'");
+    }
+
+    #[test]
+    fn between_in_like() {
+        check(
+            &message(10, "test_drive", "camera_frame"),
+            &[
+                ("battery_level BETWEEN 10 AND 20", true),
+                ("battery_level BETWEEN 11 AND 20", false),
+                ("battery_level BETWEEN 0 AND 10", true),
+                ("battery_level NOT BETWEEN 11 AND 20", true),
+                ("linear.x BETWEEN -0.5 AND 0.5", true),
+                ("ranges[2] BETWEEN 0 AND 10", false),
+                ("battery_level IN (5, 10, 15)", true),
+                ("battery_level IN (5, 15)", false),
+                ("battery_level NOT IN (5, 15)", true),
+                ("battery_level IN (10.0)", true),
+                ("mode IN ('idle', 'test_drive')", true),
+                ("header.frame_id IN (mode, 'base')", false),
+                ("mode LIKE 'test_%'", true),
+                ("mode LIKE 'test'", false),
+                ("mode LIKE '%drive'", true),
+                ("mode LIKE '%_%'", true),
+                ("mode LIKE 'test_driv_'", true),
+                ("mode LIKE '____________'", false),
+                ("mode NOT LIKE 'idle%'", true),
+                ("header.frame_id LIKE '%frame'", true),
+            ],
+        );
+    }
+
+    #[test]
+    fn like_patterns() {
+        let cases = [
+            ("", "", true),
+            ("", "%", true),
+            ("", "_", false),
+            ("abc", "abc", true),
+            ("abc", "ab", false),
+            ("abc", "a%", true),
+            ("abc", "%c", true),
+            ("abc", "%b%", true),
+            ("abc", "a_c", true),
+            ("abc", "a_", false),
+            ("abc", "%%%", true),
+            ("abcbc", "a%bc", true),
+            ("abcbd", "a%bc", false),
+            ("mississippi", "m%iss%ppi", true),
+            ("mississippi", "m%iss%ppx", false),
+            ("a%b", "a%b", true),
+        ];
+        for (text, pattern, expected) in cases {
+            let (text, pattern): (Vec<char>, Vec<char>) =
+                (text.chars().collect(), pattern.chars().collect());
+            assert_eq!(like(&text, &pattern), expected, "{text:?} LIKE {pattern:?}");
+        }
+
+        // exponential with backtracking over every `%`
+        let text: Vec<char> = "a".repeat(10_000).chars().collect();
+        let pattern: Vec<char> = "%a".repeat(20).chars().chain(['b']).collect();
+        assert!(!like(&text, &pattern));
+    }
+
+    #[test]
+    fn nested_field_paths() {
+        let message = message(10, "idle", "camera_frame");
+        check(
+            &message,
+            &[
+                ("header.frame_id = 'camera_frame'", true),
+                ("header.stamp.sec = 42", true),
+                ("ranges[0] = 1.5", true),
+                ("ranges[1] > ranges[0]", true),
+                ("data[0] = 7", true),
+                ("data[1] = 255", true),
+            ],
+        );
+
+        let errors = [
+            ("header.missing = 1", "no field `missing` in `header.missing`"),
+            ("ranges[3] = 1", "index out of bounds in `ranges[3]`"),
+            ("data[2] = 1", "index out of bounds in `data[2]`"),
+            ("battery_level.x = 1", "cannot access field `x` of `battery_level.x`"),
+            ("header[0] = 1", "cannot access index 0 of `header[0]`"),
+            ("header = 1", "values of type Struct"),
+            ("mode = 1", "cannot compare 'idle' with 1"),
+            ("battery_level LIKE '1%'", "LIKE needs a string, got 10"),
+        ];
+        for (expression, expected) in errors {
+            let err = ContentFilter::new(expression)
+                .unwrap()
+                .matches(&message)
+                .unwrap_err();
+            assert!(
+                format!("{err}").contains(expected),
+                "`{expression}`: {err}"
+            );
+        }
+
+        let filter = ContentFilter::new("battery_level = 10").unwrap();
+        let int: ArrayRef = Arc::new(Int32Array::from(vec![1]));
+        assert!(filter.matches(&int).is_err());
+    }
+
+    #[test]
+    fn parameters() {
+        let message = message(10, "idle", "camera_frame");
+        let cases = [
+            ("battery_level < %0", vec!["20"], true),
+            ("battery_level < %0", vec!["5"], false),
+            ("linear.x = %0", vec!["0.25"], true),
+            ("header.frame_id = %0", vec!["camera_frame"], true),
+            ("header.frame_id = %0", vec!["'camera_frame'"], true),
+            ("mode = %1 AND charging = %0", vec!["TRUE", "idle"], true),
+            ("battery_level BETWEEN %0 AND %1", vec!["0", "9"], false),
+            ("mode IN (%0, %1)", vec!["run", "idle"], true),
+            ("mode LIKE 'id%'", vec![], true),
+            ("mode = %0", vec!["10 OR TRUE"], false),
+        ];
+        for (expression, parameters, expected) in cases {
+            let filter = ContentFilter::with_parameters(expression, parameters.clone()).unwrap();
+            assert_eq!(filter.parameters(), parameters.as_slice());
+            assert_eq!(
+                filter.matches(&message).unwrap(),
+                expected,
+                "`{expression}` with {parameters:?}"
+            );
+        }
+
+        let err = ContentFilter::with_parameters("mode = %1", ["idle"]).unwrap_err();
+        assert!(format!("{err}").contains("missing filter parameter %1"), "{err}");
+
+        let filter: ContentFilter =
+            serde_yaml::from_str("{expression: 'battery_level < %0', parameters: [20]}").unwrap();
+        assert!(filter.matches(&message).unwrap());
+        let filter: ContentFilter = serde_yaml::from_str("battery_level > 20").unwrap();
+        assert!(!filter.matches(&message).unwrap());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("", "empty expression"),
+            ("   ", "empty expression"),
+            ("battery_level", "unexpected end of expression"),
+            ("battery_level <", "unexpected end of expression"),
+            ("battery_level < 20 AND", "unexpected end of expression"),
+            ("(battery_level < 20", "expected `)` at end of expression"),
+            ("battery_level < 20)", "unexpected `)`"),
+            ("battery_level < 20 mode", "unexpected `mode`"),
+            ("battery_level ~ 20", "unexpected character `~` at position 14"),
+            ("mode = 'idle", "unterminated string at position 7"),
+            ("battery_level = %x", "expected parameter index after `%` at position 16"),
+            ("battery_level NOT = 20", "expected `BETWEEN`, `IN` or `LIKE` after `NOT`"),
+            ("battery_level BETWEEN 1 OR 2", "expected `AND` in BETWEEN"),
+            ("battery_level IN 1", "expected `(`, found `1`"),
+            ("battery_level IN (1, 2", "expected `)` at end of expression"),
+            ("battery_level IN ()", "expected field or value, found `)`"),
+            ("mode LIKE mode", "LIKE needs a string pattern"),
+            ("header. = 1", "expected field name, found `=`"),
+            ("ranges[-1] = 1", "expected index, found `-1`"),
+            ("ranges[0 = 1", "expected `]`, found `=`"),
+            ("AND = 1", "unexpected keyword `AND`"),
+            ("battery_level = = 1", "expected field or value, found `=`"),
+            ("battery_level = 99999999999999999999999999999999999999999", "invalid number"),
+        ];
+        for (expression, expected) in cases {
+            let err = ContentFilter::new(expression).unwrap_err();
+            let message = format!("{err}");
+            assert!(message.starts_with("invalid content filter"), "{message}");
+            assert!(message.contains(expected), "`{expression}`: {message}");
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! Introspection of the ROS2 graph on the DDS domain.
+//!
+//! [`GraphMonitor`] follows the rustdds discovery events and the ROS2
+//! `ros_discovery_info` topic of a [`BridgeNode`] to track participants, ROS2
+//! nodes and topics with their types, QoS and publisher/subscriber counts.
+//! Changes are reported as [`GraphEvent`]s, which can be sent to dora as Arrow
+//! records with [`GraphEvent::to_arrow`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+};
+
+use dora_node_api::arrow::{
+    array::{ArrayRef, StringArray, StructArray, UInt32Array},
+    datatypes::{DataType, Field},
+};
+use futures::StreamExt;
+use ros2_client::{NodeEvent, ParticipantEntitiesInfo};
+use rustdds::{
+    GUID, GuidPrefix, HasQoSPolicy, QosPolicies,
+    dds::statusevents::DomainParticipantStatusEvent,
+    structure::guid::EntityId,
+};
+
+use crate::BridgeNode;
+
+/// A DDS participant, usually one per ROS2 process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParticipantInfo {
+    pub guid: String,
+    pub nodes: Vec<NodeInfo>,
+}
+
+/// A ROS2 node announced on `ros_discovery_info`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeInfo {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl NodeInfo {
+    pub fn fully_qualified_name(&self) -> String {
+        match self.namespace.as_str() {
+            "/" | "" => format!("/{}", self.name),
+            namespace => format!("{namespace}/{}", self.name),
+        }
+    }
+}
+
+/// A ROS2 topic with at least one publisher or subscriber.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicInfo {
+    /// ROS2 topic name, e.g. `/camera/image_raw`.
+    pub name: String,
+    /// ROS2 type names, e.g. `sensor_msgs/msg/Image`. More than one means a type mismatch.
+    pub type_names: Vec<String>,
+    pub publishers: usize,
+    pub subscribers: usize,
+    /// QoS of the topic as announced by discovery, if known.
+    pub qos: Option<QosPolicies>,
+}
+
+/// A change of the ROS2 graph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphEvent {
+    ParticipantAppeared { guid: String },
+    ParticipantDisappeared { guid: String },
+    NodeAppeared(NodeInfo),
+    NodeDisappeared(NodeInfo),
+    TopicAppeared(TopicInfo),
+    /// Type, QoS or publisher/subscriber counts of a topic changed.
+    TopicChanged(TopicInfo),
+    TopicDisappeared { name: String },
+}
+
+impl GraphEvent {
+    /// Arrow struct with one `{event, kind, name, type_name, publishers, subscribers}` row.
+    pub fn to_arrow(&self) -> StructArray {
+        let record = match self {
+            GraphEvent::ParticipantAppeared { guid } => Record::new("appeared", "participant", guid),
+            GraphEvent::ParticipantDisappeared { guid } => {
+                Record::new("disappeared", "participant", guid)
+            }
+            GraphEvent::NodeAppeared(node) => {
+                Record::new("appeared", "node", &node.fully_qualified_name())
+            }
+            GraphEvent::NodeDisappeared(node) => {
+                Record::new("disappeared", "node", &node.fully_qualified_name())
+            }
+            GraphEvent::TopicAppeared(topic) => Record::topic("appeared", topic),
+            GraphEvent::TopicChanged(topic) => Record::topic("changed", topic),
+            GraphEvent::TopicDisappeared { name } => Record::new("disappeared", "topic", name),
+        };
+        records_to_arrow(vec![record])
+    }
+}
+
+/// Arrow struct with one row per topic, in the format of [`GraphEvent::to_arrow`].
+pub fn topics_to_arrow(topics: &[TopicInfo]) -> StructArray {
+    records_to_arrow(topics.iter().map(|t| Record::topic("present", t)).collect())
+}
+
+#[derive(Debug, Clone)]
+struct Endpoint {
+    topic: String,
+    type_name: String,
+    writer: bool,
+}
+
+#[derive(Debug, Default)]
+struct GraphState {
+    participants: BTreeMap<String, Vec<NodeInfo>>,
+    endpoints: HashMap<GUID, Endpoint>,
+}
+
+impl GraphState {
+    fn apply_dds(&mut self, event: DomainParticipantStatusEvent) {
+        match event {
+            DomainParticipantStatusEvent::ParticipantDiscovered { participant } => {
+                self.participants
+                    .entry(guid_prefix_string(participant.guid.prefix))
+                    .or_default();
+            }
+            DomainParticipantStatusEvent::ParticipantLost { id, .. } => {
+                self.participants.remove(&guid_prefix_string(id));
+                self.endpoints.retain(|guid, _| guid.prefix != id);
+            }
+            DomainParticipantStatusEvent::WriterDetected { writer } => {
+                self.insert_endpoint(writer.guid, &writer.topic_name, &writer.type_name, true);
+            }
+            DomainParticipantStatusEvent::ReaderDetected { reader } => {
+                self.insert_endpoint(reader.guid, &reader.topic_name, &reader.type_name, false);
+            }
+            DomainParticipantStatusEvent::WriterLost { guid, .. }
+            | DomainParticipantStatusEvent::ReaderLost { guid, .. } => {
+                self.endpoints.remove(&guid);
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_ros(&mut self, info: ParticipantEntitiesInfo) {
+        let guid = GUID::from(info.gid);
+        let mut nodes: Vec<_> = info
+            .node_entities_info_seq
+            .into_iter()
+            .map(|node| NodeInfo {
+                namespace: node.node_namespace,
+                name: node.node_name,
+            })
+            .collect();
+        nodes.sort();
+        self.participants.insert(guid_prefix_string(guid.prefix), nodes);
+    }
+
+    fn insert_endpoint(&mut self, guid: GUID, topic: &str, type_name: &str, writer: bool) {
+        // only ROS2 topics, not services or DDS-only topics
+        let Some(topic) = ros_topic_name(topic) else {
+            return;
+        };
+        self.participants
+            .entry(guid_prefix_string(guid.prefix))
+            .or_default();
+        self.endpoints.insert(
+            guid,
+            Endpoint {
+                topic,
+                type_name: ros_type_name(type_name),
+                writer,
+            },
+        );
+    }
+
+    fn nodes(&self) -> BTreeSet<NodeInfo> {
+        self.participants.values().flatten().cloned().collect()
+    }
+
+    fn topics(&self) -> BTreeMap<String, TopicInfo> {
+        let mut topics: BTreeMap<String, TopicInfo> = BTreeMap::new();
+        for endpoint in self.endpoints.values() {
+            let topic = topics
+                .entry(endpoint.topic.clone())
+                .or_insert_with(|| TopicInfo {
+                    name: endpoint.topic.clone(),
+                    type_names: Vec::new(),
+                    publishers: 0,
+                    subscribers: 0,
+                    qos: None,
+                });
+            if !topic.type_names.contains(&endpoint.type_name) {
+                topic.type_names.push(endpoint.type_name.clone());
+                topic.type_names.sort();
+            }
+            if endpoint.writer {
+                topic.publishers += 1;
+            } else {
+                topic.subscribers += 1;
+            }
+        }
+        topics
+    }
+}
+
+/// Tracks the ROS2 graph seen by a bridge node's DDS participant.
+pub struct GraphMonitor {
+    state: Arc<RwLock<GraphState>>,
+    participant: rustdds::DomainParticipant,
+    events_rx: flume::Receiver<GraphEvent>,
+}
+
+impl GraphMonitor {
+    /// Start following discovery events of `ros_node`.
+    pub fn new(ros_node: &BridgeNode) -> eyre::Result<Self> {
+        let state: Arc<RwLock<GraphState>> = Default::default();
+        let (events_tx, events_rx) = flume::unbounded();
+        let participant = ros_node.context().domain_participant().clone();
+        let qos_participant = participant.clone();
+        let mut statuses = ros_node.status_events()?.into_stream();
+
+        let tracked = state.clone();
+        ros_node.spawn(async move {
+            while let Some(status) = statuses.next().await {
+                let mut state = tracked.write().unwrap();
+                let (old_participants, old_nodes, old_topics) =
+                    (state.participants.clone(), state.nodes(), state.topics());
+                match status {
+                    NodeEvent::DDS(event) => state.apply_dds(event),
+                    NodeEvent::ROS(info) => state.apply_ros(info),
+                }
+                let mut events = Vec::new();
+                for guid in state.participants.keys() {
+                    if !old_participants.contains_key(guid) {
+                        events.push(GraphEvent::ParticipantAppeared { guid: guid.clone() });
+                    }
+                }
+                for guid in old_participants.keys() {
+                    if !state.participants.contains_key(guid) {
+                        events.push(GraphEvent::ParticipantDisappeared { guid: guid.clone() });
+                    }
+                }
+                let nodes = state.nodes();
+                events.extend(nodes.difference(&old_nodes).cloned().map(GraphEvent::NodeAppeared));
+                events.extend(
+                    old_nodes
+                        .difference(&nodes)
+                        .cloned()
+                        .map(GraphEvent::NodeDisappeared),
+                );
+                let topics = state.topics();
+                for (name, topic) in &topics {
+                    match old_topics.get(name) {
+                        None => events.push(GraphEvent::TopicAppeared(
+                            with_qos(&qos_participant, topic.clone()),
+                        )),
+                        Some(old) if old != topic => events.push(GraphEvent::TopicChanged(
+                            with_qos(&qos_participant, topic.clone()),
+                        )),
+                        Some(_) => {}
+                    }
+                }
+                for name in old_topics.keys() {
+                    if !topics.contains_key(name) {
+                        events.push(GraphEvent::TopicDisappeared { name: name.clone() });
+                    }
+                }
+                drop(state);
+
+                for event in events {
+                    if events_tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self {
+            state,
+            participant,
+            events_rx,
+        })
+    }
+
+    /// Stream of graph changes, to be merged into the dora event loop.
+    pub fn events(&self) -> flume::r#async::RecvStream<'static, GraphEvent> {
+        self.events_rx.clone().into_stream()
+    }
+
+    pub fn participants(&self) -> Vec<ParticipantInfo> {
+        self.state
+            .read()
+            .unwrap()
+            .participants
+            .iter()
+            .map(|(guid, nodes)| ParticipantInfo {
+                guid: guid.clone(),
+                nodes: nodes.clone(),
+            })
+            .collect()
+    }
+
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        self.state.read().unwrap().nodes().into_iter().collect()
+    }
+
+    /// All known topics, sorted by name.
+    pub fn topics(&self) -> Vec<TopicInfo> {
+        let topics = self.state.read().unwrap().topics();
+        topics
+            .into_values()
+            .map(|topic| with_qos(&self.participant, topic))
+            .collect()
+    }
+
+    pub fn topic(&self, name: &str) -> Option<TopicInfo> {
+        let topic = self.state.read().unwrap().topics().remove(name)?;
+        Some(with_qos(&self.participant, topic))
+    }
+}
+
+/// Convert a DDS topic name (`rt/camera/image_raw`) to a ROS2 topic name.
+///
+/// Returns `None` for service topics (`rq/`, `rr/`) and plain DDS topics.
+pub fn ros_topic_name(dds_name: &str) -> Option<String> {
+    dds_name.strip_prefix("rt/").map(|name| format!("/{name}"))
+}
+
+/// Convert a DDS type name (`sensor_msgs::msg::dds_::Image_`) to a ROS2 type name
+/// (`sensor_msgs/msg/Image`). Other names are returned unchanged.
+pub fn ros_type_name(dds_type: &str) -> String {
+    let parts: Vec<_> = dds_type.split("::").collect();
+    match parts.as_slice() {
+        [package, kind, "dds_", name] => {
+            format!("{package}/{kind}/{}", name.strip_suffix('_').unwrap_or(name))
+        }
+        _ => dds_type.to_owned(),
+    }
+}
+
+fn with_qos(participant: &rustdds::DomainParticipant, mut topic: TopicInfo) -> TopicInfo {
+    let dds_name = format!("rt{}", topic.name);
+    topic.qos = participant
+        .discovered_topics()
+        .into_iter()
+        .find(|t| t.topic_data.name == dds_name)
+        .map(|t| t.topic_data.qos());
+    topic
+}
+
+fn guid_prefix_string(prefix: GuidPrefix) -> String {
+    GUID::new(prefix, EntityId::PARTICIPANT).to_string()
+}
+
+struct Record {
+    event: &'static str,
+    kind: &'static str,
+    name: String,
+    type_name: String,
+    publishers: u32,
+    subscribers: u32,
+}
+
+impl Record {
+    fn new(event: &'static str, kind: &'static str, name: &str) -> Self {
+        Self {
+            event,
+            kind,
+            name: name.to_owned(),
+            type_name: String::new(),
+            publishers: 0,
+            subscribers: 0,
+        }
+    }
+
+    fn topic(event: &'static str, topic: &TopicInfo) -> Self {
+        Self {
+            type_name: topic.type_names.join(","),
+            publishers: topic.publishers as u32,
+            subscribers: topic.subscribers as u32,
+            ..Self::new(event, "topic", &topic.name)
+        }
+    }
+}
+
+fn records_to_arrow(records: Vec<Record>) -> StructArray {
+    let fields = vec![
+        Field::new("event", DataType::Utf8, false),
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("type_name", DataType::Utf8, false),
+        Field::new("publishers", DataType::UInt32, false),
+        Field::new("subscribers", DataType::UInt32, false),
+    ];
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.event))),
+        Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.kind))),
+        Arc::new(StringArray::from_iter_values(records.iter().map(|r| &r.name))),
+        Arc::new(StringArray::from_iter_values(records.iter().map(|r| &r.type_name))),
+        Arc::new(UInt32Array::from_iter_values(records.iter().map(|r| r.publishers))),
+        Arc::new(UInt32Array::from_iter_values(records.iter().map(|r| r.subscribers))),
+    ];
+    StructArray::new(fields.into(), columns, None)
+}
@@ -0,0 +1,254 @@
+//! Stamp and `frame_id` policy for the `std_msgs/Header` of bridged messages.
+//!
+//! Bridge sources pass the header of the original message through dora
+//! metadata (`stamp_sec`, `stamp_nanosec`, `frame_id`), together with the time
+//! the message was received (`receive_stamp_sec`, `receive_stamp_nanosec`).
+//! Sinks then build the header of the published message according to a
+//! [`HeaderPolicy`], given as a string, e.g. from an env variable:
+//!
+//! ```text
+//! stamp=source
+//! stamp=receive,frame_prefix=robot1/
+//! stamp=publish,frame_id=camera_frame
+//! ```
+//!
+//! or as a YAML/serde struct:
+//!
+//! ```yaml
+//! header:
+//!   stamp: source
+//!   frame_prefix: robot1/
+//! ```
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use dora_node_api::{
+    MetadataParameters, Parameter,
+    arrow::array::{Array, ArrayRef, AsArray, StructArray},
+};
+use eyre::{Context as _, bail};
+use serde::Deserialize;
+
+use crate::{
+    Clock,
+    arrow_convert::{ArrowStruct, to_struct_array},
+    messages::{builtin_interfaces::msg::Time, std_msgs::msg::Header},
+};
+
+/// Metadata parameter holding `header.stamp.sec` of the original message.
+pub const STAMP_SEC_PARAM: &str = "stamp_sec";
+/// Metadata parameter holding `header.stamp.nanosec` of the original message.
+pub const STAMP_NANOSEC_PARAM: &str = "stamp_nanosec";
+/// Metadata parameter holding `header.frame_id` of the original message.
+pub const FRAME_ID_PARAM: &str = "frame_id";
+/// Metadata parameter holding the seconds of the time the message was received.
+pub const RECEIVE_STAMP_SEC_PARAM: &str = "receive_stamp_sec";
+/// Metadata parameter holding the nanoseconds of the time the message was received.
+pub const RECEIVE_STAMP_NANOSEC_PARAM: &str = "receive_stamp_nanosec";
+
+/// Which time is used as `header.stamp` of published messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StampPolicy {
+    /// Keep the stamp of the original message, so TF lookups and message
+    /// synchronisation keep working. Falls back to `receive` if there is none.
+    #[default]
+    Source,
+    /// The time the bridge received the original message, falls back to `publish`.
+    Receive,
+    /// The time the message is published on ROS2.
+    Publish,
+}
+
+impl FromStr for StampPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "source" => Ok(Self::Source),
+            "receive" => Ok(Self::Receive),
+            "publish" => Ok(Self::Publish),
+            other => bail!("unknown stamp policy `{other}`, expected source, receive or publish"),
+        }
+    }
+}
+
+impl fmt::Display for StampPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StampPolicy::Source => "source",
+            StampPolicy::Receive => "receive",
+            StampPolicy::Publish => "publish",
+        })
+    }
+}
+
+/// How the header of a published message is derived from the original one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeaderPolicy {
+    pub stamp: StampPolicy,
+    /// Replaces the `frame_id` of the original message.
+    pub frame_id: Option<String>,
+    /// Prepended to the `frame_id`, e.g. `robot1/` for multi-robot TF trees.
+    pub frame_prefix: Option<String>,
+}
+
+impl HeaderPolicy {
+    /// Read the policy from the env variable `name`, or use the default if it is unset.
+    ///
+    /// The variable uses the string syntax described in the [module docs](self).
+    pub fn from_env(name: &str) -> eyre::Result<Self> {
+        match std::env::var(name) {
+            Ok(value) => value.parse().with_context(|| format!("invalid {name}")),
+            Err(std::env::VarError::NotPresent) => Ok(Self::default()),
+            Err(std::env::VarError::NotUnicode(s)) => {
+                bail!("{name} is not valid unicode: `{}`", s.to_string_lossy())
+            }
+        }
+    }
+
+    /// Build the header of a message that is published now.
+    ///
+    /// `source` is the header of the original message, `received` the time the
+    /// bridge received it. `default_frame_id` is used if the original message
+    /// has no `frame_id` and the policy doesn't override it.
+    pub fn apply(
+        &self,
+        source: Option<&Header>,
+        received: Option<Time>,
+        clock: &Clock,
+        default_frame_id: &str,
+    ) -> Header {
+        let stamp = match self.stamp {
+            StampPolicy::Source => source.map(|h| h.stamp.clone()).or(received),
+            StampPolicy::Receive => received,
+            StampPolicy::Publish => None,
+        }
+        .unwrap_or_else(|| clock.now());
+
+        let frame_id = match (&self.frame_id, source) {
+            (Some(frame_id), _) => frame_id.as_str(),
+            (None, Some(source)) if !source.frame_id.is_empty() => source.frame_id.as_str(),
+            (None, _) => default_frame_id,
+        };
+        let frame_id = match &self.frame_prefix {
+            Some(prefix) if !frame_id.starts_with(prefix.as_str()) => format!("{prefix}{frame_id}"),
+            _ => frame_id.to_owned(),
+        };
+
+        Header { stamp, frame_id }
+    }
+
+    /// Build the header from the metadata of a dora input, see [`HeaderPolicy::apply`].
+    pub fn apply_metadata(
+        &self,
+        metadata: &MetadataParameters,
+        clock: &Clock,
+        default_frame_id: &str,
+    ) -> Header {
+        self.apply(
+            header_from_metadata(metadata).as_ref(),
+            receive_time_from_metadata(metadata),
+            clock,
+            default_frame_id,
+        )
+    }
+}
+
+impl FromStr for HeaderPolicy {
+    type Err = eyre::Report;
+
+    /// Parse `key=value[,key=value]*`, see the [module docs](self).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = HeaderPolicy::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let Some((key, value)) = part.split_once('=') else {
+                bail!("invalid header policy `{s}`: expected `key=value`, got `{part}`");
+            };
+            let value = value.trim();
+            match key.trim() {
+                "stamp" => policy.stamp = value.parse()?,
+                "frame_id" => policy.frame_id = Some(value.to_owned()),
+                "frame_prefix" => policy.frame_prefix = Some(value.to_owned()),
+                other => bail!("unknown header policy setting `{other}`"),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// Add the header of the original message to the metadata of a dora output.
+pub fn header_to_metadata(params: &mut MetadataParameters, header: &Header) {
+    params.insert(
+        STAMP_SEC_PARAM.into(),
+        Parameter::Integer(header.stamp.sec.into()),
+    );
+    params.insert(
+        STAMP_NANOSEC_PARAM.into(),
+        Parameter::Integer(header.stamp.nanosec.into()),
+    );
+    params.insert(
+        FRAME_ID_PARAM.into(),
+        Parameter::String(header.frame_id.clone()),
+    );
+}
+
+/// Add the time a message was received to the metadata of a dora output.
+pub fn receive_time_to_metadata(params: &mut MetadataParameters, received: &Time) {
+    params.insert(
+        RECEIVE_STAMP_SEC_PARAM.into(),
+        Parameter::Integer(received.sec.into()),
+    );
+    params.insert(
+        RECEIVE_STAMP_NANOSEC_PARAM.into(),
+        Parameter::Integer(received.nanosec.into()),
+    );
+}
+
+/// The header of the original message, if the metadata carries a stamp.
+pub fn header_from_metadata(metadata: &MetadataParameters) -> Option<Header> {
+    let stamp = time_from_metadata(metadata, STAMP_SEC_PARAM, STAMP_NANOSEC_PARAM)?;
+    let frame_id = match metadata.get(FRAME_ID_PARAM) {
+        Some(Parameter::String(frame_id)) => frame_id.clone(),
+        _ => String::new(),
+    };
+    Some(Header { stamp, frame_id })
+}
+
+/// The time the original message was received, if the metadata carries it.
+pub fn receive_time_from_metadata(metadata: &MetadataParameters) -> Option<Time> {
+    time_from_metadata(metadata, RECEIVE_STAMP_SEC_PARAM, RECEIVE_STAMP_NANOSEC_PARAM)
+}
+
+fn time_from_metadata(metadata: &MetadataParameters, sec: &str, nanosec: &str) -> Option<Time> {
+    let integer = |name| match metadata.get(name) {
+        Some(Parameter::Integer(value)) => Some(*value),
+        _ => None,
+    };
+    Some(Time {
+        sec: integer(sec)?.try_into().ok()?,
+        nanosec: integer(nanosec).unwrap_or(0).try_into().ok()?,
+    })
+}
+
+/// The `header` field of a message struct array of length 1, if it has one.
+pub fn header_from_arrow(array: &dyn Array) -> Option<Header> {
+    let header = array.as_struct_opt()?.column_by_name("header")?;
+    Header::from_columns(header.as_struct_opt()?).ok()?.pop()
+}
+
+/// Replace the `header` field of a message struct array of length 1.
+pub fn replace_header(array: &dyn Array, header: Header) -> eyre::Result<ArrayRef> {
+    let Some(message) = array.as_struct_opt() else {
+        bail!("expected struct array, got {}", array.data_type());
+    };
+    let Some(index) = message.column_names().iter().position(|name| *name == "header") else {
+        bail!("message has no `header` field");
+    };
+    let mut columns = message.columns().to_vec();
+    columns[index] = Arc::new(to_struct_array(vec![header]));
+    let message = StructArray::try_new(message.fields().clone(), columns, message.nulls().cloned())
+        .context("failed to replace message header")?;
+    Ok(Arc::new(message))
+}
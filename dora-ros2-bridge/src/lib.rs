@@ -14,18 +14,66 @@ pub mod messages {
 }
 
 pub mod _core;
+pub mod action_client;
+pub mod action_server;
+pub mod arrow_convert;
+mod backoff;
+pub mod clock;
+pub mod config;
+pub mod context;
+#[cfg(feature = "generate-messages")]
+pub mod diagnostics;
+pub mod filter;
+pub mod graph;
+#[cfg(feature = "generate-messages")]
+pub mod header;
+pub mod lifecycle;
+pub mod names;
+pub mod node;
+pub mod params;
+pub mod pattern;
+pub mod qos;
+pub mod qos_events;
+pub mod raw;
+pub mod relay;
+pub mod rosout;
+pub mod security;
+pub mod service_client;
+pub mod service_server;
+#[cfg(feature = "generate-messages")]
+pub mod tf;
+pub mod throttle;
+
+pub use action_client::{ActionClientBridge, ActionClientConfig};
+pub use action_server::{ActionServerBridge, ActionServerConfig, GoalPolicy};
+pub use clock::{Clock, ClockType};
+pub use context::{DiscoveryRange, Ros2ContextConfig};
+#[cfg(feature = "generate-messages")]
+pub use diagnostics::{Diagnostics, DiagnosticsConfig};
+pub use filter::ContentFilter;
+pub use graph::{GraphEvent, GraphMonitor};
+#[cfg(feature = "generate-messages")]
+pub use header::{HeaderPolicy, StampPolicy};
+pub use lifecycle::{Lifecycle, LifecyclePublisher, LifecycleState, LifecycleTransition};
+pub use names::{NameResolver, Remappings};
+pub use node::{BridgeEvent, BridgeNode, BridgeNodeBuilder};
+pub use params::{ParameterChange, ParameterValue, Parameters};
+pub use pattern::TopicPattern;
+pub use qos::{ActionQosConfig, QosConfig, QosProfile};
+pub use qos_events::{QosEvent, QosEventKind, QosEventMonitor};
+pub use relay::{DomainRelay, RelayConfig};
+pub use rosout::RosoutLayer;
+pub use security::{SecurityConfig, SecurityStrategy};
+pub use service_client::{ServiceClientBridge, ServiceClientConfig};
+pub use service_server::{ServiceServerBridge, ServiceServerConfig};
+#[cfg(feature = "generate-messages")]
+pub use tf::{StaticTransform, TfPublisher};
+pub use throttle::{Throttle, ThrottleConfig};
 
-/// Create a ROS2 context with proper domain ID support.
-/// 
-/// This function reads the ROS_DOMAIN_ID environment variable and creates
-/// a context with the specified domain ID. If ROS_DOMAIN_ID is not set,
-/// it defaults to domain 0.
+/// Create a ROS2 context configured from the ROS2 environment variables.
+///
+/// See [`Ros2ContextConfig::from_env`] for the supported variables. Invalid
+/// values (e.g. a non-numeric `ROS_DOMAIN_ID`) are reported as errors.
 pub fn create_ros2_context() -> eyre::Result<ros2_client::Context> {
-    let domain_id = std::env::var("ROS_DOMAIN_ID")
-        .unwrap_or_else(|_| "0".to_string())
-        .parse::<u16>()
-        .unwrap_or(0);
-    
-    let context_options = ros2_client::ContextOptions::new().domain_id(domain_id);
-    Ok(ros2_client::Context::with_options(context_options)?)
+    Ros2ContextConfig::from_env()?.build()
 }
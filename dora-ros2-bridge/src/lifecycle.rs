@@ -0,0 +1,537 @@
+//! ROS2 managed (lifecycle) nodes.
+//!
+//! A managed bridge node serves the `lifecycle_msgs` services (`~/change_state`,
+//! `~/get_state`, `~/get_available_states`, `~/get_available_transitions` and
+//! `~/get_transition_graph`) and publishes every state change on
+//! `~/transition_event`, so `ros2 lifecycle` and launch files can drive it like
+//! an rclcpp `LifecycleNode`. The node starts `unconfigured`; transitions are
+//! applied immediately and passed to the event loop as
+//! [`BridgeEvent::Lifecycle`](crate::BridgeEvent::Lifecycle).
+//!
+//! [`LifecyclePublisher`]s only publish while the node is `active`, so a bridge
+//! can be paused with `deactivate` without stopping the dataflow. Unmanaged
+//! nodes are always `active`.
+
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use eyre::{Context as _, bail, eyre};
+use futures::task::SpawnExt;
+use ros2_client::{AService, ServiceMapping};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{Clock, QosConfig, QosProfile, backoff::ReceiveBackoff, names::NameResolver};
+
+/// Environment variable enabling the lifecycle state machine for bridge nodes.
+pub const LIFECYCLE_ENV: &str = "ROS2_LIFECYCLE";
+
+/// `lifecycle_msgs/State` ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LifecycleState {
+    Unknown = 0,
+    Unconfigured = 1,
+    Inactive = 2,
+    Active = 3,
+    Finalized = 4,
+    Configuring = 10,
+    CleaningUp = 11,
+    ShuttingDown = 12,
+    Activating = 13,
+    Deactivating = 14,
+    ErrorProcessing = 15,
+}
+
+impl LifecycleState {
+    /// The states a node can rest in.
+    pub const PRIMARY: [LifecycleState; 4] = [
+        LifecycleState::Unconfigured,
+        LifecycleState::Inactive,
+        LifecycleState::Active,
+        LifecycleState::Finalized,
+    ];
+    /// The intermediate states while a transition is running.
+    pub const TRANSITIONAL: [LifecycleState; 6] = [
+        LifecycleState::Configuring,
+        LifecycleState::CleaningUp,
+        LifecycleState::ShuttingDown,
+        LifecycleState::Activating,
+        LifecycleState::Deactivating,
+        LifecycleState::ErrorProcessing,
+    ];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Label used by rclcpp, e.g. `unconfigured`.
+    pub fn label(self) -> &'static str {
+        match self {
+            LifecycleState::Unknown => "unknown",
+            LifecycleState::Unconfigured => "unconfigured",
+            LifecycleState::Inactive => "inactive",
+            LifecycleState::Active => "active",
+            LifecycleState::Finalized => "finalized",
+            LifecycleState::Configuring => "configuring",
+            LifecycleState::CleaningUp => "cleaningup",
+            LifecycleState::ShuttingDown => "shuttingdown",
+            LifecycleState::Activating => "activating",
+            LifecycleState::Deactivating => "deactivating",
+            LifecycleState::ErrorProcessing => "errorprocessing",
+        }
+    }
+}
+
+impl fmt::Display for LifecycleState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// `lifecycle_msgs/Transition` ids of the transitions that can be requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transition {
+    Configure = 1,
+    Cleanup = 2,
+    Activate = 3,
+    Deactivate = 4,
+    UnconfiguredShutdown = 5,
+    InactiveShutdown = 6,
+    ActiveShutdown = 7,
+}
+
+impl Transition {
+    pub const ALL: [Transition; 7] = [
+        Transition::Configure,
+        Transition::Cleanup,
+        Transition::Activate,
+        Transition::Deactivate,
+        Transition::UnconfiguredShutdown,
+        Transition::InactiveShutdown,
+        Transition::ActiveShutdown,
+    ];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Label used by rclcpp and `ros2 lifecycle set`, e.g. `configure`.
+    pub fn label(self) -> &'static str {
+        match self {
+            Transition::Configure => "configure",
+            Transition::Cleanup => "cleanup",
+            Transition::Activate => "activate",
+            Transition::Deactivate => "deactivate",
+            Transition::UnconfiguredShutdown
+            | Transition::InactiveShutdown
+            | Transition::ActiveShutdown => "shutdown",
+        }
+    }
+
+    /// State the transition starts from.
+    pub fn start(self) -> LifecycleState {
+        match self {
+            Transition::Configure | Transition::UnconfiguredShutdown => {
+                LifecycleState::Unconfigured
+            }
+            Transition::Cleanup | Transition::Activate | Transition::InactiveShutdown => {
+                LifecycleState::Inactive
+            }
+            Transition::Deactivate | Transition::ActiveShutdown => LifecycleState::Active,
+        }
+    }
+
+    /// State the node is in while the transition runs.
+    pub fn intermediate(self) -> LifecycleState {
+        match self {
+            Transition::Configure => LifecycleState::Configuring,
+            Transition::Cleanup => LifecycleState::CleaningUp,
+            Transition::Activate => LifecycleState::Activating,
+            Transition::Deactivate => LifecycleState::Deactivating,
+            Transition::UnconfiguredShutdown
+            | Transition::InactiveShutdown
+            | Transition::ActiveShutdown => LifecycleState::ShuttingDown,
+        }
+    }
+
+    /// State the node is in after the transition.
+    pub fn goal(self) -> LifecycleState {
+        match self {
+            Transition::Configure | Transition::Deactivate => LifecycleState::Inactive,
+            Transition::Cleanup => LifecycleState::Unconfigured,
+            Transition::Activate => LifecycleState::Active,
+            Transition::UnconfiguredShutdown
+            | Transition::InactiveShutdown
+            | Transition::ActiveShutdown => LifecycleState::Finalized,
+        }
+    }
+
+    /// Find the transition requested by id, or by label if the id is 0 like in rclcpp.
+    fn lookup(id: u8, label: &str, current: LifecycleState) -> Option<Transition> {
+        Transition::ALL.into_iter().find(|t| {
+            if id != 0 {
+                t.id() == id
+            } else {
+                t.label() == label && t.start() == current
+            }
+        })
+    }
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// A completed transition, see [`BridgeEvent::Lifecycle`](crate::BridgeEvent::Lifecycle).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleTransition {
+    pub transition: Transition,
+    pub start: LifecycleState,
+    pub goal: LifecycleState,
+}
+
+/// Read whether bridge nodes are managed from `ROS2_LIFECYCLE`, `false` if unset.
+pub fn lifecycle_from_env() -> eyre::Result<bool> {
+    match std::env::var(LIFECYCLE_ENV) {
+        Ok(value) => match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" | "managed" => Ok(true),
+            "0" | "false" | "no" | "off" | "" => Ok(false),
+            other => bail!("invalid {LIFECYCLE_ENV} `{other}`, expected true or false"),
+        },
+        Err(_) => Ok(false),
+    }
+}
+
+/// Lifecycle state of a bridge node.
+///
+/// Cheap to clone; all clones see the current state.
+#[derive(Debug, Clone)]
+pub struct Lifecycle {
+    state: Arc<RwLock<LifecycleState>>,
+    managed: bool,
+}
+
+impl Lifecycle {
+    /// State of nodes without lifecycle management: always active.
+    pub(crate) fn unmanaged() -> Self {
+        Self {
+            state: Arc::new(RwLock::new(LifecycleState::Active)),
+            managed: false,
+        }
+    }
+
+    /// Serve the lifecycle services of `node` on `executor`, starting `unconfigured`.
+    pub(crate) fn managed(
+        node: &mut ros2_client::Node,
+        executor: &futures::executor::ThreadPool,
+        resolver: &NameResolver,
+        clock: &Clock,
+    ) -> eyre::Result<(Self, flume::Receiver<LifecycleTransition>)> {
+        let lifecycle = Self {
+            state: Arc::new(RwLock::new(LifecycleState::Unconfigured)),
+            managed: true,
+        };
+        let (transitions_tx, transitions) = flume::unbounded();
+
+        let topic_name = resolver.resolve_name("~/transition_event")?;
+        let topic = node
+            .create_topic(
+                &topic_name,
+                ros2_client::MessageTypeName::new("lifecycle_msgs", "TransitionEvent"),
+                &QosConfig::default().to_policies(),
+            )
+            .context("failed to create transition_event topic")?;
+        let events = node
+            .create_publisher::<wire::TransitionEvent>(&topic, None)
+            .context("failed to create transition_event publisher")?;
+
+        let state = lifecycle.state.clone();
+        let clock = clock.clone();
+        serve(node, executor, resolver, "change_state", "ChangeState", move |request| {
+            let wire::ChangeStateRequest { transition } = request;
+            let mut state = state.write().unwrap();
+            let Some(transition) = Transition::lookup(transition.id, &transition.label, *state)
+                .filter(|t| t.start() == *state)
+            else {
+                warn!(
+                    "rejecting lifecycle transition `{}` ({}) in state `{}`",
+                    transition.label, transition.id, *state
+                );
+                return wire::ChangeStateResponse { success: false };
+            };
+            // like rclcpp, report the intermediate state before the goal state
+            for (start, goal) in [
+                (transition.start(), transition.intermediate()),
+                (transition.intermediate(), transition.goal()),
+            ] {
+                let event = wire::TransitionEvent {
+                    timestamp: clock.since_epoch().as_nanos() as u64,
+                    transition: wire::Transition::from(transition),
+                    start_state: start.into(),
+                    goal_state: goal.into(),
+                };
+                if let Err(err) = events.publish(event) {
+                    warn!("failed to publish transition event: {:?}", err.forget_data());
+                }
+            }
+            *state = transition.goal();
+            info!(
+                "lifecycle transition `{transition}`: {} -> {}",
+                transition.start(),
+                transition.goal()
+            );
+            let _ = transitions_tx.send(LifecycleTransition {
+                transition,
+                start: transition.start(),
+                goal: transition.goal(),
+            });
+            wire::ChangeStateResponse { success: true }
+        })?;
+
+        let state = lifecycle.state.clone();
+        serve(node, executor, resolver, "get_state", "GetState", move |_: wire::Empty| {
+            wire::GetStateResponse {
+                current_state: (*state.read().unwrap()).into(),
+            }
+        })?;
+
+        serve(
+            node,
+            executor,
+            resolver,
+            "get_available_states",
+            "GetAvailableStates",
+            |_: wire::Empty| wire::GetAvailableStatesResponse {
+                available_states: LifecycleState::PRIMARY
+                    .into_iter()
+                    .chain(LifecycleState::TRANSITIONAL)
+                    .map(Into::into)
+                    .collect(),
+            },
+        )?;
+
+        let state = lifecycle.state.clone();
+        serve(
+            node,
+            executor,
+            resolver,
+            "get_available_transitions",
+            "GetAvailableTransitions",
+            move |_: wire::Empty| {
+                let current = *state.read().unwrap();
+                wire::GetAvailableTransitionsResponse {
+                    available_transitions: Transition::ALL
+                        .into_iter()
+                        .filter(|t| t.start() == current)
+                        .map(Into::into)
+                        .collect(),
+                }
+            },
+        )?;
+
+        serve(
+            node,
+            executor,
+            resolver,
+            "get_transition_graph",
+            "GetAvailableTransitions",
+            |_: wire::Empty| wire::GetAvailableTransitionsResponse {
+                available_transitions: Transition::ALL.into_iter().map(Into::into).collect(),
+            },
+        )?;
+
+        Ok((lifecycle, transitions))
+    }
+
+    /// Whether the node serves the lifecycle services.
+    pub fn is_managed(&self) -> bool {
+        self.managed
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        *self.state.read().unwrap()
+    }
+
+    /// Whether publishers should publish, always `true` for unmanaged nodes.
+    pub fn is_active(&self) -> bool {
+        self.state() == LifecycleState::Active
+    }
+}
+
+/// A publisher that only publishes while its node is `active`, like rclcpp's
+/// `LifecyclePublisher`.
+pub struct LifecyclePublisher<M> {
+    publisher: Arc<ros2_client::Publisher<M>>,
+    lifecycle: Lifecycle,
+}
+
+impl<M: Serialize> LifecyclePublisher<M> {
+    /// `publisher` may be shared, e.g. with a [`QosEventMonitor`](crate::QosEventMonitor).
+    pub fn new(
+        publisher: impl Into<Arc<ros2_client::Publisher<M>>>,
+        lifecycle: &Lifecycle,
+    ) -> Self {
+        Self {
+            publisher: publisher.into(),
+            lifecycle: lifecycle.clone(),
+        }
+    }
+
+    /// Publish `message` if the node is active. Returns whether it was published.
+    pub fn publish(&self, message: M) -> eyre::Result<bool> {
+        if !self.lifecycle.is_active() {
+            return Ok(false);
+        }
+        self.publisher
+            .publish(message)
+            .map_err(|e| eyre!("failed to publish: {:?}", e.forget_data()))?;
+        Ok(true)
+    }
+
+    pub fn publisher(&self) -> &Arc<ros2_client::Publisher<M>> {
+        &self.publisher
+    }
+}
+
+/// Serve the lifecycle service `~/{name}` of type `lifecycle_msgs/{type_name}`.
+fn serve<Q, R>(
+    node: &mut ros2_client::Node,
+    executor: &futures::executor::ThreadPool,
+    resolver: &NameResolver,
+    name: &str,
+    type_name: &str,
+    handler: impl Fn(Q) -> R + Send + 'static,
+) -> eyre::Result<()>
+where
+    Q: ros2_client::Message + Send + 'static,
+    R: ros2_client::Message + Send + 'static,
+{
+    let service_name = resolver.resolve_name(&format!("~/{name}"))?;
+    let qos = QosConfig::new(QosProfile::ServicesDefault).to_policies();
+    let server = node
+        .create_server::<AService<Q, R>>(
+            ServiceMapping::Enhanced,
+            &service_name,
+            &ros2_client::ServiceTypeName::new("lifecycle_msgs", type_name),
+            qos.clone(),
+            qos,
+        )
+        .map_err(|e| eyre!("failed to create lifecycle service `{name}`: {e:?}"))?;
+    let name = name.to_owned();
+    executor
+        .spawn(async move {
+            let mut backoff = ReceiveBackoff::new(format!("lifecycle `{name}` requests"));
+            loop {
+                let (rmw_id, request) = match server.async_receive_request().await {
+                    Ok(request) => request,
+                    Err(err) => {
+                        backoff.failed(err).await;
+                        continue;
+                    }
+                };
+                backoff.succeeded();
+                if let Err(err) = server.send_response(rmw_id, handler(request)) {
+                    warn!("failed to answer lifecycle `{name}` request: {err:?}");
+                }
+            }
+        })
+        .with_context(|| format!("failed to spawn lifecycle service `{name}`"))
+}
+
+/// The `lifecycle_msgs` types used by the services, independent of the generated messages.
+mod wire {
+    use serde::{Deserialize, Serialize};
+
+    use super::{LifecycleState, Transition as TransitionId};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct State {
+        pub id: u8,
+        pub label: String,
+    }
+
+    impl From<LifecycleState> for State {
+        fn from(state: LifecycleState) -> Self {
+            Self {
+                id: state.id(),
+                label: state.label().to_owned(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Transition {
+        pub id: u8,
+        pub label: String,
+    }
+
+    impl From<TransitionId> for Transition {
+        fn from(transition: TransitionId) -> Self {
+            Self {
+                id: transition.id(),
+                label: transition.label().to_owned(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct TransitionDescription {
+        pub transition: Transition,
+        pub start_state: State,
+        pub goal_state: State,
+    }
+
+    impl From<TransitionId> for TransitionDescription {
+        fn from(transition: TransitionId) -> Self {
+            Self {
+                transition: transition.into(),
+                start_state: transition.start().into(),
+                goal_state: transition.goal().into(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct TransitionEvent {
+        /// Nanoseconds since the epoch.
+        pub timestamp: u64,
+        pub transition: Transition,
+        pub start_state: State,
+        pub goal_state: State,
+    }
+
+    /// Empty request; empty ROS2 messages carry a single placeholder byte.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Empty {
+        pub structure_needs_at_least_one_member: u8,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ChangeStateRequest {
+        pub transition: Transition,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ChangeStateResponse {
+        pub success: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct GetStateResponse {
+        pub current_state: State,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct GetAvailableStatesResponse {
+        pub available_states: Vec<State>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct GetAvailableTransitionsResponse {
+        pub available_transitions: Vec<TransitionDescription>,
+    }
+}
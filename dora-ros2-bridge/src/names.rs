@@ -0,0 +1,400 @@
+//! ROS2 name resolution and remapping.
+//!
+//! Implements the rules from the ROS2 "Topic and Service name mapping" design:
+//!
+//! - absolute names (`/robot/audio`) are used as is,
+//! - relative names (`audio`) are resolved against the node namespace,
+//! - private names (`~/audio`, `~`) are resolved against the node's fully qualified name,
+//! - `{node}` and `{ns}`/`{namespace}` substitutions are expanded,
+//! - remapping rules (`from:=to`, optionally `node_name:from:=to`) are applied to
+//!   the expanded name, the first matching rule wins.
+//!
+//! Remapping rules are read from the `ROS2_REMAP` environment variable, e.g.
+//! `ROS2_REMAP: "/audio:=/robot/audio image_raw:=camera/image_raw"`. The special
+//! rules `__ns:=` and `__node:=` override the node namespace and name.
+
+use eyre::{Context as _, bail, eyre};
+
+/// Environment variable holding whitespace or `;` separated remapping rules.
+pub const REMAP_ENV: &str = "ROS2_REMAP";
+
+/// A single `[node:]from:=to` remapping rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remap {
+    /// Only apply this rule to the node with this name.
+    pub node: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+/// An ordered list of remapping rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Remappings {
+    rules: Vec<Remap>,
+    namespace: Option<String>,
+    node_name: Option<String>,
+}
+
+impl Remappings {
+    /// Parse whitespace or `;` separated `[node:]from:=to` rules.
+    pub fn parse(rules: &str) -> eyre::Result<Self> {
+        let mut remappings = Self::default();
+        for rule in rules
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .filter(|r| !r.is_empty())
+        {
+            remappings.push_rule(rule)?;
+        }
+        Ok(remappings)
+    }
+
+    /// Read rules from the `ROS2_REMAP` environment variable, empty if unset.
+    pub fn from_env() -> eyre::Result<Self> {
+        match std::env::var(REMAP_ENV) {
+            Ok(rules) => Self::parse(&rules).with_context(|| format!("invalid {REMAP_ENV}")),
+            Err(std::env::VarError::NotPresent) => Ok(Self::default()),
+            Err(std::env::VarError::NotUnicode(s)) => {
+                bail!("{REMAP_ENV} is not valid unicode: `{}`", s.to_string_lossy())
+            }
+        }
+    }
+
+    /// Add a single `[node:]from:=to` rule.
+    pub fn push_rule(&mut self, rule: &str) -> eyre::Result<()> {
+        let (lhs, to) = rule
+            .split_once(":=")
+            .ok_or_else(|| eyre!("remapping rule `{rule}` is missing `:=`"))?;
+        let (node, from) = match lhs.split_once(':') {
+            Some((node, from)) => {
+                validate_token(node).with_context(|| format!("invalid node name in `{rule}`"))?;
+                (Some(node.to_owned()), from)
+            }
+            None => (None, lhs),
+        };
+        if from.is_empty() || to.is_empty() {
+            bail!("remapping rule `{rule}` has an empty side");
+        }
+
+        match from {
+            "__ns" => {
+                validate_namespace(to).with_context(|| format!("invalid `{rule}`"))?;
+                self.namespace = Some(to.to_owned());
+            }
+            "__node" | "__name" => {
+                validate_token(to).with_context(|| format!("invalid `{rule}`"))?;
+                self.node_name = Some(to.to_owned());
+            }
+            _ => self.rules.push(Remap {
+                node,
+                from: from.to_owned(),
+                to: to.to_owned(),
+            }),
+        }
+        Ok(())
+    }
+
+    /// Namespace set through a `__ns:=` rule.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Node name set through a `__node:=` rule.
+    pub fn node_name(&self) -> Option<&str> {
+        self.node_name.as_deref()
+    }
+
+    pub fn rules(&self) -> &[Remap] {
+        &self.rules
+    }
+}
+
+/// Resolves topic and service names against a node's namespace and remappings.
+#[derive(Debug, Clone)]
+pub struct NameResolver {
+    namespace: String,
+    node_name: String,
+    remappings: Remappings,
+}
+
+impl NameResolver {
+    pub fn new(namespace: &str, node_name: &str) -> eyre::Result<Self> {
+        validate_namespace(namespace)?;
+        validate_token(node_name).context("invalid node name")?;
+        Ok(Self {
+            namespace: namespace.to_owned(),
+            node_name: node_name.to_owned(),
+            remappings: Remappings::default(),
+        })
+    }
+
+    pub fn with_remappings(mut self, remappings: Remappings) -> Self {
+        self.remappings = remappings;
+        self
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn node_name(&self) -> &str {
+        &self.node_name
+    }
+
+    /// Fully qualified name of the node, e.g. `/dora/ros2_image_sink`.
+    pub fn fully_qualified_node_name(&self) -> String {
+        join(&self.namespace, &self.node_name)
+    }
+
+    /// Expand `name` to a fully qualified name without applying remappings.
+    pub fn expand(&self, name: &str) -> eyre::Result<String> {
+        let substituted = name
+            .replace("{node}", &self.node_name)
+            .replace("{namespace}", &self.namespace)
+            .replace("{ns}", &self.namespace);
+
+        let expanded = if substituted == "~" {
+            self.fully_qualified_node_name()
+        } else if let Some(rest) = substituted.strip_prefix("~/") {
+            join(&self.fully_qualified_node_name(), rest)
+        } else if substituted.starts_with('~') {
+            bail!("invalid name `{name}`: `~` must be followed by `/`");
+        } else if substituted.starts_with('/') {
+            substituted
+        } else {
+            join(&self.namespace, &substituted)
+        };
+
+        validate_fully_qualified(&expanded).with_context(|| format!("invalid ROS2 name `{name}`"))?;
+        Ok(expanded)
+    }
+
+    /// Expand `name` and apply the first matching remapping rule.
+    pub fn resolve(&self, name: &str) -> eyre::Result<String> {
+        let expanded = self.expand(name)?;
+        for rule in &self.remappings.rules {
+            if rule.node.as_ref().is_some_and(|node| node != &self.node_name) {
+                continue;
+            }
+            if self.expand(&rule.from)? == expanded {
+                return self.expand(&rule.to);
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Resolve `name` into a [`ros2_client::Name`] usable for topics and services.
+    pub fn resolve_name(&self, name: &str) -> eyre::Result<ros2_client::Name> {
+        let resolved = self.resolve(name)?;
+        to_ros2_name(&resolved)
+    }
+}
+
+/// Split a fully qualified name into the namespace and base name expected by `ros2_client`.
+pub fn to_ros2_name(fully_qualified: &str) -> eyre::Result<ros2_client::Name> {
+    validate_fully_qualified(fully_qualified)?;
+    let (namespace, base) = fully_qualified
+        .rsplit_once('/')
+        .expect("fully qualified names start with `/`");
+    let namespace = if namespace.is_empty() { "/" } else { namespace };
+    ros2_client::Name::new(namespace, base)
+        .map_err(|e| eyre!("failed to create ROS2 name `{fully_qualified}`: {e}"))
+}
+
+/// Check that `name` is a valid fully qualified ROS2 name, e.g. `/camera/image_raw`.
+pub fn validate_fully_qualified(name: &str) -> eyre::Result<()> {
+    let Some(rest) = name.strip_prefix('/') else {
+        bail!("`{name}` is not fully qualified");
+    };
+    if rest.is_empty() {
+        bail!("`/` is not a valid topic or service name");
+    }
+    for token in rest.split('/') {
+        validate_token(token).with_context(|| format!("invalid name `{name}`"))?;
+    }
+    Ok(())
+}
+
+fn validate_namespace(namespace: &str) -> eyre::Result<()> {
+    if namespace == "/" {
+        Ok(())
+    } else {
+        validate_fully_qualified(namespace).with_context(|| format!("invalid namespace `{namespace}`"))
+    }
+}
+
+fn validate_token(token: &str) -> eyre::Result<()> {
+    let mut chars = token.chars();
+    match chars.next() {
+        None => bail!("empty name token (repeated or trailing `/`)"),
+        Some(c) if c.is_ascii_digit() => bail!("token `{token}` must not start with a digit"),
+        Some(c) if !(c.is_ascii_alphanumeric() || c == '_') => {
+            bail!("token `{token}` contains invalid character `{c}`")
+        }
+        Some(_) => {}
+    }
+    if let Some(c) = chars.find(|c| !(c.is_ascii_alphanumeric() || *c == '_')) {
+        bail!("token `{token}` contains invalid character `{c}`");
+    }
+    if token.contains("__") {
+        bail!("token `{token}` must not contain repeated underscores");
+    }
+    Ok(())
+}
+
+fn join(namespace: &str, name: &str) -> String {
+    if namespace == "/" {
+        format!("/{name}")
+    } else {
+        format!("{namespace}/{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver(namespace: &str, rules: &str) -> NameResolver {
+        NameResolver::new(namespace, "node")
+            .unwrap()
+            .with_remappings(Remappings::parse(rules).unwrap())
+    }
+
+    #[test]
+    fn expand_names() {
+        let cases = [
+            ("/ns", "/abs", "/abs"),
+            ("/ns", "/abs/nested", "/abs/nested"),
+            ("/ns", "rel", "/ns/rel"),
+            ("/ns", "rel/nested", "/ns/rel/nested"),
+            ("/ns", "~", "/ns/node"),
+            ("/ns", "~/private", "/ns/node/private"),
+            ("/ns", "{node}/status", "/ns/node/status"),
+            ("/ns", "{ns}/status", "/ns/status"),
+            ("/ns", "{namespace}/status", "/ns/status"),
+            ("/a/b", "rel", "/a/b/rel"),
+            ("/", "rel", "/rel"),
+            ("/", "~", "/node"),
+            ("/", "~/private", "/node/private"),
+            ("/", "_leading_underscore", "/_leading_underscore"),
+        ];
+        for (namespace, name, expected) in cases {
+            let expanded = resolver(namespace, "").expand(name);
+            assert_eq!(expanded.ok().as_deref(), Some(expected), "`{name}` in `{namespace}`");
+        }
+    }
+
+    #[test]
+    fn reject_invalid_names() {
+        let resolver = resolver("/ns", "");
+        for name in [
+            "",
+            "/",
+            "//a",
+            "a//b",
+            "a/",
+            "/a/",
+            "1abc",
+            "/a/1b",
+            "a__b",
+            "a-b",
+            "a b",
+            "a.b",
+            "~foo",
+            "~~",
+            "/ä",
+        ] {
+            assert!(resolver.expand(name).is_err(), "`{name}` should be rejected");
+        }
+    }
+
+    #[test]
+    fn validate_fully_qualified_names() {
+        for name in ["/a", "/a/b_c", "/_a", "/A1/b2"] {
+            assert!(validate_fully_qualified(name).is_ok(), "`{name}`");
+        }
+        for name in ["a", "", "/", "/a/", "/a//b", "/a__b", "/9a"] {
+            assert!(validate_fully_qualified(name).is_err(), "`{name}`");
+        }
+    }
+
+    #[test]
+    fn reject_invalid_resolver() {
+        assert!(NameResolver::new("ns", "node").is_err());
+        assert!(NameResolver::new("/ns/", "node").is_err());
+        assert!(NameResolver::new("/ns", "no/de").is_err());
+        assert!(NameResolver::new("/ns", "1node").is_err());
+        assert!(NameResolver::new("/", "node").is_ok());
+    }
+
+    #[test]
+    fn parse_rules() {
+        let remappings = Remappings::parse(
+            "/audio:=/robot/audio;image_raw:=camera/image_raw\n node:~/in:=/out __ns:=/robot \
+             __node:=bridge",
+        )
+        .unwrap();
+        assert_eq!(
+            remappings.rules(),
+            [
+                Remap {
+                    node: None,
+                    from: "/audio".to_owned(),
+                    to: "/robot/audio".to_owned(),
+                },
+                Remap {
+                    node: None,
+                    from: "image_raw".to_owned(),
+                    to: "camera/image_raw".to_owned(),
+                },
+                Remap {
+                    node: Some("node".to_owned()),
+                    from: "~/in".to_owned(),
+                    to: "/out".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(remappings.namespace(), Some("/robot"));
+        assert_eq!(remappings.node_name(), Some("bridge"));
+        assert_eq!(Remappings::parse("").unwrap(), Remappings::default());
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        for rule in [
+            "audio",
+            "audio=/robot/audio",
+            ":=/robot/audio",
+            "audio:=",
+            "1node:a:=b",
+            "no-de:a:=b",
+            "__ns:=robot",
+            "__ns:=/robot/",
+            "__node:=a/b",
+        ] {
+            assert!(Remappings::parse(rule).is_err(), "`{rule}` should be rejected");
+        }
+    }
+
+    #[test]
+    fn resolve_with_remappings() {
+        let resolver = resolver(
+            "/ns",
+            "/audio:=/robot/audio image_raw:=camera/image_raw other:status:=/other \
+             node:~/cmd:=/cmd_vel /audio:=/ignored",
+        );
+        let cases = [
+            ("/audio", "/robot/audio"),
+            // relative rules match the expanded name, and expand their target
+            ("image_raw", "/ns/camera/image_raw"),
+            ("/ns/image_raw", "/ns/camera/image_raw"),
+            // rules for other nodes are ignored
+            ("status", "/ns/status"),
+            ("~/cmd", "/cmd_vel"),
+            ("/ns/node/cmd", "/cmd_vel"),
+            ("unmapped", "/ns/unmapped"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(resolver.resolve(name).unwrap(), expected, "`{name}`");
+        }
+    }
+}
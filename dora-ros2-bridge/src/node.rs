@@ -0,0 +1,463 @@
+//! Shared runtime for bridge nodes: owns the ROS2 node, its spinner and the executor.
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use dora_node_api::{Event, EventStream};
+use eyre::{Context as _, eyre};
+use futures::{
+    Stream, StreamExt,
+    executor::ThreadPool,
+    future::{BoxFuture, FutureExt},
+    stream,
+    task::SpawnExt,
+};
+use tracing::info;
+
+use crate::{
+    Ros2ContextConfig,
+    clock::{self, CLOCK_TOPIC, Clock, USE_SIM_TIME_PARAM},
+    lifecycle::{self, Lifecycle, LifecycleTransition},
+    names::{NameResolver, Remappings},
+    params::{ParameterChange, ParameterValue, Parameters},
+};
+
+/// Namespace used for bridge nodes unless configured otherwise.
+pub const DEFAULT_NAMESPACE: &str = "/dora";
+/// Environment variable overriding the namespace of bridge nodes.
+pub const NAMESPACE_ENV: &str = "ROS2_NAMESPACE";
+
+/// Builder for a [`BridgeNode`].
+pub struct BridgeNodeBuilder {
+    name: String,
+    namespace: Option<String>,
+    context: Option<Ros2ContextConfig>,
+    remappings: Option<Remappings>,
+    options: ros2_client::NodeOptions,
+    parameters: Vec<(String, ParameterValue)>,
+    read_only: HashSet<String>,
+    use_sim_time: Option<bool>,
+    lifecycle: Option<bool>,
+}
+
+impl BridgeNodeBuilder {
+    /// Namespace of the ROS2 node, defaults to `ROS2_NAMESPACE` or [`DEFAULT_NAMESPACE`].
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Context configuration, defaults to [`Ros2ContextConfig::from_env`].
+    pub fn context(mut self, config: Ros2ContextConfig) -> Self {
+        self.context = Some(config);
+        self
+    }
+
+    /// Remapping rules for topic and service names, defaults to [`Remappings::from_env`].
+    pub fn remappings(mut self, remappings: Remappings) -> Self {
+        self.remappings = Some(remappings);
+        self
+    }
+
+    /// Options passed to `ros2_client`.
+    ///
+    /// Its rosout writer is disabled by default: `/rosout` is published by the
+    /// [`RosoutLayer`](crate::RosoutLayer) installed with
+    /// [`rosout::init_tracing`](crate::rosout::init_tracing).
+    pub fn options(mut self, options: ros2_client::NodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Declare a ROS2 parameter that can be changed at runtime with `ros2 param set`.
+    ///
+    /// The type of the parameter is fixed by `value`.
+    pub fn parameter(mut self, name: impl Into<String>, value: ParameterValue) -> Self {
+        self.parameters.push((name.into(), value));
+        self
+    }
+
+    /// Declare a ROS2 parameter that can be read, but not changed at runtime.
+    ///
+    /// Used for settings that are only applied at startup, such as topic names.
+    pub fn read_only_parameter(mut self, name: impl Into<String>, value: ParameterValue) -> Self {
+        let name = name.into();
+        self.read_only.insert(name.clone());
+        self.parameter(name, value)
+    }
+
+    /// Follow the simulation time on `/clock`, defaults to `ROS2_USE_SIM_TIME`.
+    ///
+    /// Exposed as the read-only `use_sim_time` parameter.
+    pub fn use_sim_time(mut self, use_sim_time: bool) -> Self {
+        self.use_sim_time = Some(use_sim_time);
+        self
+    }
+
+    /// Make the node a managed lifecycle node, defaults to `ROS2_LIFECYCLE`.
+    ///
+    /// Managed nodes start `unconfigured` and serve the `lifecycle_msgs`
+    /// services, see the [`lifecycle`](crate::lifecycle) module.
+    pub fn lifecycle(mut self, managed: bool) -> Self {
+        self.lifecycle = Some(managed);
+        self
+    }
+
+    /// Create the ROS2 node and start its spinner in the background.
+    pub fn build(mut self) -> eyre::Result<BridgeNode> {
+        let use_sim_time = match self.use_sim_time {
+            Some(use_sim_time) => use_sim_time,
+            None => clock::use_sim_time_from_env()?,
+        };
+        let managed = match self.lifecycle {
+            Some(managed) => managed,
+            None => lifecycle::lifecycle_from_env()?,
+        };
+        self = self
+            .read_only_parameter(USE_SIM_TIME_PARAM, ParameterValue::Boolean(use_sim_time));
+        let remappings = match self.remappings {
+            Some(remappings) => remappings,
+            None => Remappings::from_env()?,
+        };
+        // `__ns:=` and `__node:=` rules take precedence, like for ROS2 command line arguments
+        let namespace = match (remappings.namespace(), self.namespace) {
+            (Some(namespace), _) => namespace.to_owned(),
+            (None, Some(namespace)) => namespace,
+            (None, None) => {
+                std::env::var(NAMESPACE_ENV).unwrap_or_else(|_| DEFAULT_NAMESPACE.to_owned())
+            }
+        };
+        let node_name = remappings.node_name().unwrap_or(&self.name).to_owned();
+        let resolver = NameResolver::new(&namespace, &node_name)?.with_remappings(remappings);
+        let context = match self.context {
+            Some(config) => config.build()?,
+            None => Ros2ContextConfig::from_env()
+                .context("invalid ROS2 context configuration")?
+                .build()?,
+        };
+
+        let parameters = Parameters::new(
+            self.parameters.iter().cloned().collect::<HashMap<_, _>>(),
+            self.read_only,
+        );
+        let (parameter_changes_tx, parameter_changes) = flume::unbounded();
+        let mut options = self.options;
+        for (name, value) in self.parameters {
+            options = options.declare_parameter(&name, value);
+        }
+        let validator = parameters.clone();
+        let applied = parameters.clone();
+        let options = options
+            .parameter_validator(Box::new(move |name, value| validator.validate(name, value)))
+            .parameter_set_action(Box::new(move |name, value| {
+                applied.set(name, value.clone());
+                let _ = parameter_changes_tx.send(ParameterChange {
+                    name: name.to_owned(),
+                    value: value.clone(),
+                });
+                Ok(())
+            }));
+
+        let name = ros2_client::NodeName::new(&namespace, &node_name)
+            .map_err(|e| eyre!("failed to create ROS2 node name: {e}"))?;
+        let mut node = context
+            .new_node(name, options)
+            .map_err(|e| eyre!("failed to create ros2 node: {e:?}"))?;
+
+        let executor = Arc::new(ThreadPool::new()?);
+        let (spinner_failed_tx, spinner_failed) = flume::bounded(1);
+        let spinner = node
+            .spinner()
+            .map_err(|e| eyre!("failed to create spinner: {e:?}"))?;
+        executor
+            .spawn(async move {
+                if let Err(err) = spinner.spin().await {
+                    let _ = spinner_failed_tx.send(eyre!("ros2 spinner failed: {err:?}"));
+                }
+            })
+            .context("failed to spawn ros2 spinner")?;
+
+        let clock = if use_sim_time {
+            let clock_topic = resolver.resolve_name(CLOCK_TOPIC)?;
+            Clock::sim_time(&mut node, &executor, &clock_topic)?
+        } else {
+            Clock::ros()
+        };
+
+        let (lifecycle, lifecycle_transitions) = if managed {
+            Lifecycle::managed(&mut node, &executor, &resolver, &clock)?
+        } else {
+            (Lifecycle::unmanaged(), flume::unbounded().1)
+        };
+
+        Ok(BridgeNode {
+            node,
+            context,
+            clock,
+            resolver,
+            executor,
+            spinner_failed,
+            parameters,
+            parameter_changes,
+            lifecycle,
+            lifecycle_transitions,
+            status_subscribers: Default::default(),
+        })
+    }
+}
+
+/// A ROS2 node with a running spinner, used by all bridge node binaries.
+///
+/// ```no_run
+/// # use dora_ros2_bridge::BridgeNode;
+/// let mut ros_node = BridgeNode::builder("my_bridge").build()?;
+/// // ... create publishers/subscriptions through `ros_node.node_mut()` ...
+/// let (_node, events) = dora_node_api::DoraNode::init_from_env()?;
+/// ros_node.run(events, |event| {
+///     // handle dora inputs
+///     Ok(())
+/// })?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+pub struct BridgeNode {
+    node: ros2_client::Node,
+    context: ros2_client::Context,
+    clock: Clock,
+    resolver: NameResolver,
+    executor: Arc<ThreadPool>,
+    spinner_failed: flume::Receiver<eyre::Report>,
+    parameters: Parameters,
+    parameter_changes: flume::Receiver<ParameterChange>,
+    lifecycle: Lifecycle,
+    lifecycle_transitions: flume::Receiver<LifecycleTransition>,
+    /// Subscribers of [`status_events`](Self::status_events), `None` until the first one.
+    status_subscribers: Arc<Mutex<Option<Vec<flume::Sender<ros2_client::NodeEvent>>>>>,
+}
+
+impl BridgeNode {
+    pub fn builder(name: impl Into<String>) -> BridgeNodeBuilder {
+        BridgeNodeBuilder {
+            name: name.into(),
+            namespace: None,
+            context: None,
+            remappings: None,
+            options: ros2_client::NodeOptions::new().enable_rosout(false),
+            parameters: Vec::new(),
+            read_only: HashSet::new(),
+            use_sim_time: None,
+            lifecycle: None,
+        }
+    }
+
+    pub fn node(&self) -> &ros2_client::Node {
+        &self.node
+    }
+
+    pub fn node_mut(&mut self) -> &mut ros2_client::Node {
+        &mut self.node
+    }
+
+    /// The ROS2 context (and DDS participant) the node was created in.
+    pub fn context(&self) -> &ros2_client::Context {
+        &self.context
+    }
+
+    /// The node's ROS clock, following `/clock` if `use_sim_time` is set.
+    ///
+    /// Use it for all header stamps so they work with simulators and bag playback.
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Resolver for topic and service names relative to this node.
+    pub fn resolver(&self) -> &NameResolver {
+        &self.resolver
+    }
+
+    /// Resolve a (possibly relative or private) topic or service name for this node.
+    pub fn resolve_name(&self, name: &str) -> eyre::Result<ros2_client::Name> {
+        self.resolver.resolve_name(name)
+    }
+
+    /// Current values of the declared parameters, updated on `ros2 param set`.
+    pub fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+
+    /// Lifecycle state of the node, always `active` unless it is managed.
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    /// Whether the node is `active`, i.e. should publish and forward data.
+    pub fn is_active(&self) -> bool {
+        self.lifecycle.is_active()
+    }
+
+    /// Executor shared by the spinner and any background tasks of the node.
+    pub fn executor(&self) -> &Arc<ThreadPool> {
+        &self.executor
+    }
+
+    /// Spawn a background task on the node's executor.
+    pub fn spawn<F>(&self, future: F) -> eyre::Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.executor
+            .spawn(future)
+            .context("failed to spawn task on ros2 executor")
+    }
+
+    /// Discovery and DDS status events of the node.
+    ///
+    /// `ros2_client` hands out the node's status events through a single
+    /// channel, so they are read once and every subscriber gets its own copy.
+    /// Subscribers only get the events that arrive after they subscribed.
+    pub fn status_events(&self) -> eyre::Result<flume::Receiver<ros2_client::NodeEvent>> {
+        let (tx, rx) = flume::unbounded();
+        let mut subscribers = self.status_subscribers.lock().unwrap();
+        match subscribers.as_mut() {
+            Some(subscribers) => subscribers.push(tx),
+            None => {
+                let mut statuses = self.node.status_receiver();
+                let forward_to = self.status_subscribers.clone();
+                self.spawn(async move {
+                    while let Some(status) = statuses.next().await {
+                        let mut subscribers = forward_to.lock().unwrap();
+                        let subscribers = subscribers.get_or_insert_with(Vec::new);
+                        subscribers.retain(|tx| tx.send(status.clone()).is_ok());
+                    }
+                })?;
+                *subscribers = Some(vec![tx]);
+            }
+        }
+        Ok(rx)
+    }
+
+    /// Resolves to the error of the ROS2 spinner once it stops.
+    ///
+    /// The run loops watch the spinner of their own node. To also stop when
+    /// the spinner of another node fails, e.g. the second node of a domain
+    /// relay, merge this into the `external` stream of [`run_with`](Self::run_with).
+    pub fn spinner_failure(&self) -> impl Future<Output = eyre::Report> + Send + 'static {
+        self.spinner_failed
+            .clone()
+            .into_recv_async()
+            .map(|result| match result {
+                Ok(err) => err,
+                // sender dropped without error -> spinner exited cleanly
+                Err(flume::RecvError::Disconnected) => eyre!("ros2 spinner stopped unexpectedly"),
+            })
+    }
+
+    /// Run the dora event loop until `Event::Stop`.
+    ///
+    /// All events except `Stop` are passed to `handler`. Parameter changes are
+    /// only applied to [`parameters`](Self::parameters), lifecycle transitions
+    /// only to [`lifecycle`](Self::lifecycle). Returns an error if the
+    /// handler fails or the ROS2 spinner dies. The ROS2 node and its spinner are
+    /// shut down when this function returns.
+    pub fn run<F>(self, dora_events: EventStream, mut handler: F) -> eyre::Result<()>
+    where
+        F: FnMut(Event) -> eyre::Result<()>,
+    {
+        self.run_with(dora_events, stream::empty::<()>(), |event| match event {
+            BridgeEvent::Dora(event) => handler(event),
+            BridgeEvent::Parameter(_) | BridgeEvent::Lifecycle(_) | BridgeEvent::External(()) => {
+                Ok(())
+            }
+        })
+    }
+
+    /// Like [`run`](Self::run), but additionally merges `external` into the event loop.
+    ///
+    /// This is used to forward ROS2 messages, service requests, etc. to dora
+    /// without polling on a timer.
+    pub fn run_with<S, T, F>(
+        self,
+        dora_events: EventStream,
+        external: S,
+        mut handler: F,
+    ) -> eyre::Result<()>
+    where
+        S: Stream<Item = T> + Send + 'static,
+        F: FnMut(BridgeEvent<T>) -> eyre::Result<()>,
+    {
+        self.run_with_node(dora_events, external, |_, event| handler(event))
+    }
+
+    /// Like [`run_with`](Self::run_with), but also passes the node to `handler`.
+    ///
+    /// This allows creating publishers and subscriptions while the event loop is
+    /// running, e.g. for topics that are discovered at runtime.
+    pub fn run_with_node<S, T, F>(
+        mut self,
+        dora_events: EventStream,
+        external: S,
+        mut handler: F,
+    ) -> eyre::Result<()>
+    where
+        S: Stream<Item = T> + Send + 'static,
+        F: FnMut(&mut BridgeNode, BridgeEvent<T>) -> eyre::Result<()>,
+    {
+        let spinner_failed: BoxFuture<'static, LoopEvent<T>> =
+            self.spinner_failure().map(LoopEvent::SpinnerFailed).boxed();
+        let parameter_changes = self
+            .parameter_changes
+            .clone()
+            .into_stream()
+            .map(|change| LoopEvent::Bridge(BridgeEvent::Parameter(change)));
+        let lifecycle_transitions = self
+            .lifecycle_transitions
+            .clone()
+            .into_stream()
+            .map(|transition| LoopEvent::Bridge(BridgeEvent::Lifecycle(transition)));
+        let events = stream::select(
+            stream::select(
+                dora_events.map(|e| LoopEvent::Bridge(BridgeEvent::Dora(e))),
+                external.map(|e| LoopEvent::Bridge(BridgeEvent::External(e))),
+            ),
+            stream::select(
+                stream::select(parameter_changes, lifecycle_transitions),
+                stream::once(spinner_failed),
+            ),
+        );
+
+        for event in futures::executor::block_on_stream(events) {
+            match event {
+                LoopEvent::Bridge(BridgeEvent::Dora(Event::Stop(_))) => {
+                    info!("Received stop");
+                    break;
+                }
+                LoopEvent::Bridge(event) => handler(&mut self, event)?,
+                LoopEvent::SpinnerFailed(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Event passed to the handler of [`BridgeNode::run_with`].
+#[derive(Debug)]
+pub enum BridgeEvent<T> {
+    /// An event from the dora daemon (never `Event::Stop`).
+    Dora(Event),
+    /// A parameter was changed through the ROS2 parameter services.
+    Parameter(ParameterChange),
+    /// The node completed a lifecycle transition, e.g. `deactivate`.
+    ///
+    /// Only sent for managed nodes, see [`BridgeNodeBuilder::lifecycle`].
+    Lifecycle(LifecycleTransition),
+    /// An item of the external stream.
+    External(T),
+}
+
+enum LoopEvent<T> {
+    Bridge(BridgeEvent<T>),
+    SpinnerFailed(eyre::Report),
+}
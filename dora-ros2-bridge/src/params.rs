@@ -0,0 +1,98 @@
+//! ROS2 parameters of bridge nodes.
+//!
+//! Parameters are declared through [`BridgeNodeBuilder::parameter`](crate::BridgeNodeBuilder::parameter)
+//! and served by `ros2_client` through the standard `rcl_interfaces` parameter
+//! services, so `ros2 param list/get/set` work against bridge nodes. Changes are
+//! published on `/parameter_events`, applied to the shared [`Parameters`] handle
+//! and passed to the event loop as [`BridgeEvent::Parameter`](crate::BridgeEvent::Parameter).
+
+use std::{
+    collections::{HashMap, HashSet},
+    mem::discriminant,
+    sync::{Arc, RwLock},
+};
+
+pub use ros2_client::ParameterValue;
+
+/// A parameter changed through the ROS2 parameter services.
+#[derive(Debug, Clone)]
+pub struct ParameterChange {
+    pub name: String,
+    pub value: ParameterValue,
+}
+
+/// Current values of the declared parameters.
+///
+/// Cheap to clone; all clones see the latest values, so node logic can read
+/// parameters inside the dora event loop.
+#[derive(Debug, Clone, Default)]
+pub struct Parameters {
+    values: Arc<RwLock<HashMap<String, ParameterValue>>>,
+    read_only: Arc<HashSet<String>>,
+}
+
+impl Parameters {
+    pub(crate) fn new(
+        values: HashMap<String, ParameterValue>,
+        read_only: HashSet<String>,
+    ) -> Self {
+        Self {
+            values: Arc::new(RwLock::new(values)),
+            read_only: Arc::new(read_only),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<ParameterValue> {
+        self.values.read().unwrap().get(name).cloned()
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<String> {
+        match self.get(name)? {
+            ParameterValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_integer(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            ParameterValue::Integer(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Double value of the parameter; integer parameters are converted.
+    pub fn get_double(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            ParameterValue::Double(value) => Some(value),
+            ParameterValue::Integer(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            ParameterValue::Boolean(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Check a requested change like rclcpp does for statically typed parameters.
+    pub(crate) fn validate(&self, name: &str, value: &ParameterValue) -> Result<(), String> {
+        if self.read_only.contains(name) {
+            return Err(format!(
+                "parameter `{name}` is read-only, restart the node to change it"
+            ));
+        }
+        match self.values.read().unwrap().get(name) {
+            None => Err(format!("parameter `{name}` is not declared")),
+            Some(current) if discriminant(current) != discriminant(value) => Err(format!(
+                "wrong type for parameter `{name}`: expected {current:?}, got {value:?}"
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn set(&self, name: &str, value: ParameterValue) {
+        self.values.write().unwrap().insert(name.to_owned(), value);
+    }
+}
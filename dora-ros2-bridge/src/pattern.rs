@@ -0,0 +1,106 @@
+//! Topic name patterns, for selecting discovered topics by name.
+
+use eyre::Context as _;
+use regex::Regex;
+use serde::Deserialize;
+
+/// Topic name pattern, either a glob or a regex prefixed with `regex:`.
+///
+/// In globs, `**` matches any number of characters including `/`, `*` matches
+/// within a single name segment and `?` matches one character. Globs that
+/// don't start with `/` match at any depth, e.g. `*/image_raw` matches
+/// `/camera/image_raw` and `/robot/camera/image_raw`.
+#[derive(Debug, Clone)]
+pub struct TopicPattern {
+    pattern: String,
+    regex: Regex,
+}
+
+impl TopicPattern {
+    pub fn new(pattern: &str) -> eyre::Result<Self> {
+        let regex = match pattern.strip_prefix("regex:") {
+            Some(regex) => regex.to_owned(),
+            None => glob_to_regex(pattern),
+        };
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            regex: Regex::new(&regex)
+                .with_context(|| format!("invalid topic pattern `{pattern}`"))?,
+        })
+    }
+
+    /// The pattern `**`, matching all topics.
+    pub fn any() -> Self {
+        Self::new("**").expect("`**` is a valid pattern")
+    }
+
+    pub fn matches(&self, topic: &str) -> bool {
+        self.regex.is_match(topic)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl<'de> Deserialize<'de> for TopicPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        TopicPattern::new(&pattern).map_err(|e| serde::de::Error::custom(format!("{e:#}")))
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from(if glob.starts_with('/') { "^" } else { "^(.*/)?" });
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        let cases = [
+            ("/robot/**", "/robot/camera/image_raw", true),
+            ("/robot/**", "/robot", false),
+            ("/robot/**", "/robots/odom", false),
+            ("/robot/*", "/robot/odom", true),
+            ("/robot/*", "/robot/camera/image_raw", false),
+            ("*/image_raw", "/camera/image_raw", true),
+            ("*/image_raw", "/robot/camera/image_raw", true),
+            ("*/image_raw", "/camera/image_raw/compressed", false),
+            ("/cam?/info", "/cam1/info", true),
+            ("/cam?/info", "/cam/info", false),
+            ("/cam?/info", "/cam//info", false),
+            ("/a.b", "/a.b", true),
+            ("/a.b", "/axb", false),
+            ("/tf", "/tf_static", false),
+            ("**", "/tf", true),
+            ("**", "/robot/camera/image_raw", true),
+            ("regex:^/tf(_static)?$", "/tf_static", true),
+            ("regex:^/tf(_static)?$", "/tf2", false),
+        ];
+        for (pattern, topic, expected) in cases {
+            let matches = TopicPattern::new(pattern).unwrap().matches(topic);
+            assert_eq!(matches, expected, "`{pattern}` on `{topic}`");
+        }
+        assert_eq!(glob_to_regex("/a/**/b*"), "^/a/.*/b[^/]*$");
+        assert_eq!(glob_to_regex("c?d"), "^(.*/)?c[^/]d$");
+        assert!(TopicPattern::new("regex:(").is_err());
+        assert!(TopicPattern::any().matches("/any/topic"));
+    }
+}
@@ -0,0 +1,731 @@
+//! Named QoS profiles and per-topic QoS overrides.
+//!
+//! The profiles mirror the rclcpp ones (`rmw_qos_profile_*`). Overrides can be
+//! given as a string, e.g. from an env variable:
+//!
+//! ```text
+//! sensor_data
+//! sensor_data,depth=1
+//! reliability=reliable,history=keep_last,depth=10,durability=transient_local,deadline=100ms,lifespan=2s
+//! ```
+//!
+//! or as a YAML/serde struct:
+//!
+//! ```yaml
+//! qos:
+//!   profile: sensor_data
+//!   depth: 1
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use eyre::{Context as _, bail, eyre};
+use rustdds::{QosPolicies, QosPolicyBuilder, policy};
+use serde::Deserialize;
+
+/// Max blocking time (in milliseconds) used for reliable writers unless configured otherwise.
+pub const DEFAULT_MAX_BLOCKING_TIME_MS: i64 = 100;
+
+/// Standard rclcpp QoS profiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QosProfile {
+    /// `rmw_qos_profile_default`: reliable, volatile, keep last 10
+    #[default]
+    Default,
+    /// `rmw_qos_profile_sensor_data`: best effort, volatile, keep last 5
+    SensorData,
+    /// `rmw_qos_profile_system_default`: DDS implementation defaults
+    SystemDefault,
+    /// `rmw_qos_profile_services_default`: reliable, volatile, keep last 10
+    ServicesDefault,
+    /// `rmw_qos_profile_parameters`: reliable, volatile, keep last 1000
+    Parameters,
+    /// `rmw_qos_profile_parameter_events`: reliable, volatile, keep last 1000
+    ParameterEvents,
+}
+
+impl FromStr for QosProfile {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "sensor_data" => Ok(Self::SensorData),
+            "system_default" => Ok(Self::SystemDefault),
+            "services_default" => Ok(Self::ServicesDefault),
+            "parameters" => Ok(Self::Parameters),
+            "parameter_events" => Ok(Self::ParameterEvents),
+            other => bail!(
+                "unknown QoS profile `{other}`, expected one of default, sensor_data, \
+                 system_default, services_default, parameters, parameter_events"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reliability {
+    Reliable,
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum History {
+    KeepLast,
+    KeepAll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Durability {
+    Volatile,
+    TransientLocal,
+}
+
+/// A duration in the QoS syntax: `inf`, `250us`, `100ms`, `1.5s` or plain seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QosDuration {
+    Infinite,
+    Finite(std::time::Duration),
+}
+
+impl From<QosDuration> for rustdds::Duration {
+    fn from(value: QosDuration) -> Self {
+        match value {
+            QosDuration::Infinite => rustdds::Duration::INFINITE,
+            QosDuration::Finite(d) => rustdds::Duration::from_frac_seconds(d.as_secs_f64()),
+        }
+    }
+}
+
+impl FromStr for QosDuration {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if matches!(s, "inf" | "infinite") {
+            return Ok(Self::Infinite);
+        }
+        let (number, scale) = if let Some(n) = s.strip_suffix("us") {
+            (n, 1e-6)
+        } else if let Some(n) = s.strip_suffix("ms") {
+            (n, 1e-3)
+        } else if let Some(n) = s.strip_suffix('s') {
+            (n, 1.0)
+        } else {
+            (s, 1.0)
+        };
+        let value: f64 = number
+            .trim()
+            .parse()
+            .map_err(|e| eyre!("invalid duration `{s}`: {e}"))?;
+        if !value.is_finite() || value < 0.0 {
+            bail!("invalid duration `{s}`: must be a non-negative number");
+        }
+        Ok(Self::Finite(std::time::Duration::from_secs_f64(value * scale)))
+    }
+}
+
+impl<'de> Deserialize<'de> for QosDuration {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(f64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Seconds(s) => s.to_string().parse(),
+            Raw::Text(s) => s.parse(),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A QoS profile plus optional per-topic overrides.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QosConfig {
+    pub profile: QosProfile,
+    pub reliability: Option<Reliability>,
+    pub history: Option<History>,
+    pub depth: Option<i32>,
+    pub durability: Option<Durability>,
+    pub deadline: Option<QosDuration>,
+    pub lifespan: Option<QosDuration>,
+    pub max_blocking_time: Option<QosDuration>,
+}
+
+impl QosConfig {
+    pub fn new(profile: QosProfile) -> Self {
+        Self {
+            profile,
+            ..Default::default()
+        }
+    }
+
+    pub fn reliability(mut self, reliability: Reliability) -> Self {
+        self.reliability = Some(reliability);
+        self
+    }
+
+    pub fn keep_last(mut self, depth: i32) -> Self {
+        self.history = Some(History::KeepLast);
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = Some(durability);
+        self
+    }
+
+    /// A reader QoS that is compatible with a writer using `writer` QoS.
+    ///
+    /// Reliability and durability follow the writer, so a best effort writer
+    /// is read best effort and transient local (latched) samples are received.
+    pub fn compatible_with(writer: &QosPolicies) -> Self {
+        QosConfig {
+            reliability: Some(match writer.reliability() {
+                Some(policy::Reliability::BestEffort) => Reliability::BestEffort,
+                _ => Reliability::Reliable,
+            }),
+            durability: Some(match writer.durability() {
+                Some(policy::Durability::TransientLocal)
+                | Some(policy::Durability::Transient)
+                | Some(policy::Durability::Persistent) => Durability::TransientLocal,
+                _ => Durability::Volatile,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Read the QoS from the env variable `name`, or use `default` if it is unset.
+    ///
+    /// The variable uses the string syntax described in the [module docs](self).
+    pub fn from_env_or(name: &str, default: QosConfig) -> eyre::Result<Self> {
+        match std::env::var(name) {
+            Ok(value) => value.parse().with_context(|| format!("invalid {name}")),
+            Err(std::env::VarError::NotPresent) => Ok(default),
+            Err(std::env::VarError::NotUnicode(s)) => {
+                bail!("{name} is not valid unicode: `{}`", s.to_string_lossy())
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> eyre::Result<()> {
+        match key {
+            "profile" => self.profile = value.parse()?,
+            "reliability" => {
+                self.reliability = Some(match value {
+                    "reliable" => Reliability::Reliable,
+                    "best_effort" => Reliability::BestEffort,
+                    other => bail!("invalid reliability `{other}`"),
+                })
+            }
+            "history" => {
+                self.history = Some(match value {
+                    "keep_last" => History::KeepLast,
+                    "keep_all" => History::KeepAll,
+                    other => bail!("invalid history `{other}`"),
+                })
+            }
+            "depth" => {
+                let depth: i32 = value
+                    .parse()
+                    .map_err(|e| eyre!("invalid depth `{value}`: {e}"))?;
+                if depth < 1 {
+                    bail!("depth must be at least 1, got {depth}");
+                }
+                self.depth = Some(depth);
+            }
+            "durability" => {
+                self.durability = Some(match value {
+                    "volatile" => Durability::Volatile,
+                    "transient_local" => Durability::TransientLocal,
+                    other => bail!("invalid durability `{other}`"),
+                })
+            }
+            "deadline" => self.deadline = Some(value.parse()?),
+            "lifespan" => self.lifespan = Some(value.parse()?),
+            "max_blocking_time" => self.max_blocking_time = Some(value.parse()?),
+            other => bail!("unknown QoS setting `{other}`"),
+        }
+        Ok(())
+    }
+
+    /// Convert into the `rustdds` QoS policies used for topics, publishers and subscriptions.
+    pub fn to_policies(&self) -> QosPolicies {
+        let (reliability, history, depth, durability) = match self.profile {
+            QosProfile::Default | QosProfile::ServicesDefault => (
+                Some(Reliability::Reliable),
+                Some(History::KeepLast),
+                10,
+                Some(Durability::Volatile),
+            ),
+            QosProfile::SensorData => (
+                Some(Reliability::BestEffort),
+                Some(History::KeepLast),
+                5,
+                Some(Durability::Volatile),
+            ),
+            QosProfile::Parameters | QosProfile::ParameterEvents => (
+                Some(Reliability::Reliable),
+                Some(History::KeepLast),
+                1000,
+                Some(Durability::Volatile),
+            ),
+            QosProfile::SystemDefault => (None, None, 1, None),
+        };
+        let reliability = self.reliability.or(reliability);
+        let history = match (self.history.or(history), self.depth) {
+            // an explicit depth implies keep last
+            (None, Some(_)) => Some(History::KeepLast),
+            (history, _) => history,
+        };
+        let depth = self.depth.unwrap_or(depth);
+        let durability = self.durability.or(durability);
+
+        let mut builder = QosPolicyBuilder::new().liveliness(policy::Liveliness::Automatic {
+            lease_duration: rustdds::Duration::INFINITE,
+        });
+        if let Some(reliability) = reliability {
+            builder = builder.reliability(match reliability {
+                Reliability::Reliable => policy::Reliability::Reliable {
+                    max_blocking_time: self.max_blocking_time.map(Into::into).unwrap_or_else(
+                        || rustdds::Duration::from_millis(DEFAULT_MAX_BLOCKING_TIME_MS),
+                    ),
+                },
+                Reliability::BestEffort => policy::Reliability::BestEffort,
+            });
+        }
+        if let Some(history) = history {
+            builder = builder.history(match history {
+                History::KeepLast => policy::History::KeepLast { depth },
+                History::KeepAll => policy::History::KeepAll,
+            });
+        }
+        if let Some(durability) = durability {
+            builder = builder.durability(match durability {
+                Durability::Volatile => policy::Durability::Volatile,
+                Durability::TransientLocal => policy::Durability::TransientLocal,
+            });
+        }
+        if let Some(deadline) = self.deadline {
+            builder = builder.deadline(policy::Deadline(deadline.into()));
+        }
+        if let Some(lifespan) = self.lifespan {
+            builder = builder.lifespan(policy::Lifespan {
+                duration: lifespan.into(),
+            });
+        }
+        builder.build()
+    }
+}
+
+/// QoS of the services and topics that make up a ROS2 action.
+///
+/// Defaults mirror `rcl_action`: `services_default` for the goal, result and
+/// cancel services, `default` for feedback and a reliable, transient local
+/// depth 1 status topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionQosConfig {
+    pub goal_service: QosConfig,
+    pub result_service: QosConfig,
+    pub cancel_service: QosConfig,
+    pub feedback: QosConfig,
+    pub status: QosConfig,
+}
+
+impl Default for ActionQosConfig {
+    fn default() -> Self {
+        Self {
+            goal_service: QosConfig::new(QosProfile::ServicesDefault),
+            result_service: QosConfig::new(QosProfile::ServicesDefault),
+            cancel_service: QosConfig::new(QosProfile::ServicesDefault),
+            feedback: QosConfig::new(QosProfile::Default),
+            status: QosConfig::new(QosProfile::Default)
+                .keep_last(1)
+                .durability(Durability::TransientLocal),
+        }
+    }
+}
+
+impl ActionQosConfig {
+    /// Read overrides from `{prefix}_GOAL_QOS`, `{prefix}_RESULT_QOS`,
+    /// `{prefix}_CANCEL_QOS`, `{prefix}_FEEDBACK_QOS` and `{prefix}_STATUS_QOS`.
+    pub fn from_env(prefix: &str) -> eyre::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            goal_service: QosConfig::from_env_or(
+                &format!("{prefix}_GOAL_QOS"),
+                default.goal_service,
+            )?,
+            result_service: QosConfig::from_env_or(
+                &format!("{prefix}_RESULT_QOS"),
+                default.result_service,
+            )?,
+            cancel_service: QosConfig::from_env_or(
+                &format!("{prefix}_CANCEL_QOS"),
+                default.cancel_service,
+            )?,
+            feedback: QosConfig::from_env_or(
+                &format!("{prefix}_FEEDBACK_QOS"),
+                default.feedback,
+            )?,
+            status: QosConfig::from_env_or(&format!("{prefix}_STATUS_QOS"), default.status)?,
+        })
+    }
+
+    pub fn to_client_policies(&self) -> ros2_client::action::ActionClientQosPolicies {
+        ros2_client::action::ActionClientQosPolicies {
+            goal_service: self.goal_service.to_policies(),
+            result_service: self.result_service.to_policies(),
+            cancel_service: self.cancel_service.to_policies(),
+            feedback_subscription: self.feedback.to_policies(),
+            status_subscription: self.status.to_policies(),
+        }
+    }
+
+    pub fn to_server_policies(&self) -> ros2_client::action::ActionServerQosPolicies {
+        ros2_client::action::ActionServerQosPolicies {
+            goal_service: self.goal_service.to_policies(),
+            result_service: self.result_service.to_policies(),
+            cancel_service: self.cancel_service.to_policies(),
+            feedback_publisher: self.feedback.to_policies(),
+            status_publisher: self.status.to_policies(),
+        }
+    }
+}
+
+impl From<QosProfile> for QosConfig {
+    fn from(profile: QosProfile) -> Self {
+        Self::new(profile)
+    }
+}
+
+impl From<&QosConfig> for QosPolicies {
+    fn from(value: &QosConfig) -> Self {
+        value.to_policies()
+    }
+}
+
+impl FromStr for QosConfig {
+    type Err = eyre::Report;
+
+    /// Parse `[profile][,key=value]*`, see the [module docs](self).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = QosConfig::default();
+        for (i, part) in s.split(',').map(str::trim).enumerate() {
+            if part.is_empty() {
+                continue;
+            }
+            match part.split_once('=') {
+                Some((key, value)) => config
+                    .set(key.trim(), value.trim())
+                    .with_context(|| format!("invalid QoS `{s}`"))?,
+                None if i == 0 => {
+                    config.profile = part.parse().with_context(|| format!("invalid QoS `{s}`"))?
+                }
+                None => bail!("invalid QoS `{s}`: expected `key=value`, got `{part}`"),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for QosProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QosProfile::Default => "default",
+            QosProfile::SensorData => "sensor_data",
+            QosProfile::SystemDefault => "system_default",
+            QosProfile::ServicesDefault => "services_default",
+            QosProfile::Parameters => "parameters",
+            QosProfile::ParameterEvents => "parameter_events",
+        };
+        f.write_str(name)
+    }
+}
+
+impl<'de> Deserialize<'de> for QosConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Default, Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        struct Fields {
+            profile: QosProfile,
+            reliability: Option<Reliability>,
+            history: Option<History>,
+            depth: Option<i32>,
+            durability: Option<Durability>,
+            deadline: Option<QosDuration>,
+            lifespan: Option<QosDuration>,
+            max_blocking_time: Option<QosDuration>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Fields(Fields),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(s) => s.parse().map_err(serde::de::Error::custom),
+            Raw::Fields(f) => {
+                if f.depth.is_some_and(|d| d < 1) {
+                    return Err(serde::de::Error::custom("QoS depth must be at least 1"));
+                }
+                Ok(QosConfig {
+                    profile: f.profile,
+                    reliability: f.reliability,
+                    history: f.history,
+                    depth: f.depth,
+                    durability: f.durability,
+                    deadline: f.deadline,
+                    lifespan: f.lifespan,
+                    max_blocking_time: f.max_blocking_time,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn parse_overrides() {
+        let cases = [
+            ("", QosConfig::default()),
+            ("sensor_data", QosConfig::new(QosProfile::SensorData)),
+            (
+                "sensor_data,depth=1",
+                QosConfig {
+                    depth: Some(1),
+                    ..QosConfig::new(QosProfile::SensorData)
+                },
+            ),
+            (
+                " parameters , reliability = best_effort ",
+                QosConfig::new(QosProfile::Parameters).reliability(Reliability::BestEffort),
+            ),
+            (
+                "reliability=reliable,history=keep_last,depth=10,durability=transient_local",
+                QosConfig::default()
+                    .reliability(Reliability::Reliable)
+                    .keep_last(10)
+                    .durability(Durability::TransientLocal),
+            ),
+            (
+                "history=keep_all,durability=volatile",
+                QosConfig {
+                    history: Some(History::KeepAll),
+                    durability: Some(Durability::Volatile),
+                    ..QosConfig::default()
+                },
+            ),
+            (
+                "profile=system_default,deadline=100ms,lifespan=2s,max_blocking_time=inf",
+                QosConfig {
+                    deadline: Some(QosDuration::Finite(Duration::from_millis(100))),
+                    lifespan: Some(QosDuration::Finite(Duration::from_secs(2))),
+                    max_blocking_time: Some(QosDuration::Infinite),
+                    ..QosConfig::new(QosProfile::SystemDefault)
+                },
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<QosConfig>().ok(), Some(expected), "`{input}`");
+        }
+    }
+
+    #[test]
+    fn reject_invalid_overrides() {
+        for input in [
+            "fast",
+            "depth=1,sensor_data",
+            "reliability=sometimes",
+            "history=keep_some",
+            "durability=persistent",
+            "depth=0",
+            "depth=-1",
+            "depth=ten",
+            "deadline=soon",
+            "deadline=-1s",
+            "colour=blue",
+        ] {
+            assert!(
+                input.parse::<QosConfig>().is_err(),
+                "`{input}` should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_durations() {
+        let cases = [
+            ("inf", QosDuration::Infinite),
+            ("infinite", QosDuration::Infinite),
+            ("250us", QosDuration::Finite(Duration::from_micros(250))),
+            ("100ms", QosDuration::Finite(Duration::from_millis(100))),
+            ("1.5s", QosDuration::Finite(Duration::from_millis(1500))),
+            ("2", QosDuration::Finite(Duration::from_secs(2))),
+            (" 0s ", QosDuration::Finite(Duration::ZERO)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                input.parse::<QosDuration>().ok(),
+                Some(expected),
+                "`{input}`"
+            );
+        }
+        for input in ["", "ms", "-1ms", "NaN", "1h"] {
+            assert!(
+                input.parse::<QosDuration>().is_err(),
+                "`{input}` should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_config() {
+        let parse = |yaml: &str| serde_yaml::from_str::<QosConfig>(yaml);
+
+        assert_eq!(
+            parse("sensor_data,depth=1").unwrap(),
+            parse("{profile: sensor_data, depth: 1}").unwrap()
+        );
+        assert_eq!(
+            parse("{durability: transient_local, deadline: 0.5}").unwrap(),
+            QosConfig {
+                deadline: Some(QosDuration::Finite(Duration::from_millis(500))),
+                ..QosConfig::default().durability(Durability::TransientLocal)
+            }
+        );
+        assert!(parse("{depth: 0}").is_err());
+        assert!(parse("{colour: blue}").is_err());
+    }
+
+    #[test]
+    fn profile_defaults() {
+        let reliable = policy::Reliability::Reliable {
+            max_blocking_time: rustdds::Duration::from_millis(DEFAULT_MAX_BLOCKING_TIME_MS),
+        };
+        let volatile = Some(policy::Durability::Volatile);
+        let cases = [
+            (QosProfile::Default, Some(reliable), Some(10), volatile),
+            (
+                QosProfile::ServicesDefault,
+                Some(reliable),
+                Some(10),
+                volatile,
+            ),
+            (
+                QosProfile::SensorData,
+                Some(policy::Reliability::BestEffort),
+                Some(5),
+                volatile,
+            ),
+            (QosProfile::Parameters, Some(reliable), Some(1000), volatile),
+            (
+                QosProfile::ParameterEvents,
+                Some(reliable),
+                Some(1000),
+                volatile,
+            ),
+            (QosProfile::SystemDefault, None, None, None),
+        ];
+        for (profile, reliability, depth, durability) in cases {
+            let policies = QosConfig::new(profile).to_policies();
+            assert_eq!(policies.reliability(), reliability, "{profile}");
+            assert_eq!(
+                policies.history(),
+                depth.map(|depth| policy::History::KeepLast { depth }),
+                "{profile}"
+            );
+            assert_eq!(policies.durability(), durability, "{profile}");
+            assert_eq!(
+                profile.to_string().parse::<QosProfile>().ok(),
+                Some(profile)
+            );
+        }
+    }
+
+    #[test]
+    fn overrides_apply_to_profile() {
+        let policies = "sensor_data,reliability=reliable,depth=1,max_blocking_time=1s"
+            .parse::<QosConfig>()
+            .unwrap()
+            .to_policies();
+        assert_eq!(
+            policies.reliability(),
+            Some(policy::Reliability::Reliable {
+                max_blocking_time: rustdds::Duration::from_secs(1),
+            })
+        );
+        assert_eq!(
+            policies.history(),
+            Some(policy::History::KeepLast { depth: 1 })
+        );
+
+        // an explicit depth implies keep last, even without a profile default
+        let policies = "system_default,depth=3"
+            .parse::<QosConfig>()
+            .unwrap()
+            .to_policies();
+        assert_eq!(
+            policies.history(),
+            Some(policy::History::KeepLast { depth: 3 })
+        );
+
+        let policies = "history=keep_all"
+            .parse::<QosConfig>()
+            .unwrap()
+            .to_policies();
+        assert_eq!(policies.history(), Some(policy::History::KeepAll));
+    }
+
+    #[test]
+    fn compatible_reader() {
+        let cases = [
+            ("default", Reliability::Reliable, Durability::Volatile),
+            ("sensor_data", Reliability::BestEffort, Durability::Volatile),
+            (
+                "default,depth=1,durability=transient_local",
+                Reliability::Reliable,
+                Durability::TransientLocal,
+            ),
+            (
+                "sensor_data,durability=transient_local",
+                Reliability::BestEffort,
+                Durability::TransientLocal,
+            ),
+            // unset policies fall back to the DDS defaults for writers
+            (
+                "system_default",
+                Reliability::Reliable,
+                Durability::Volatile,
+            ),
+        ];
+        for (writer, reliability, durability) in cases {
+            let writer_policies = writer.parse::<QosConfig>().unwrap().to_policies();
+            let reader = QosConfig::compatible_with(&writer_policies);
+            assert_eq!(reader.reliability, Some(reliability), "`{writer}`");
+            assert_eq!(reader.durability, Some(durability), "`{writer}`");
+        }
+
+        let writer = QosPolicyBuilder::new()
+            .durability(policy::Durability::Persistent)
+            .build();
+        assert_eq!(
+            QosConfig::compatible_with(&writer).durability,
+            Some(Durability::TransientLocal)
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! DDS QoS status events of a bridge node's readers and writers.
+//!
+//! [`QosEventMonitor`] reports endpoints that can't communicate or lose data,
+//! which otherwise fail silently:
+//!
+//! - requested/offered incompatible QoS, for every reader and writer of the
+//!   node's participant, with the offending policy,
+//! - requested/offered deadline missed, sample lost, liveliness changed and
+//!   liveliness lost, for the publishers and subscriptions passed to
+//!   [`QosEventMonitor::watch_publisher`] and [`QosEventMonitor::watch_subscription`].
+//!
+//! Events are logged as warnings and can be sent on a dora output, set with
+//! `ROS2_QOS_EVENTS_OUTPUT`, as `{kind, topic, endpoint, policy, message}` records.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use dora_node_api::{
+    DoraNode, MetadataParameters,
+    arrow::{
+        array::{ArrayRef, StringArray, StructArray},
+        datatypes::{DataType, Field},
+    },
+    dora_core::config::DataId,
+};
+use eyre::Context as _;
+use futures::{
+    StreamExt,
+    future::{AbortHandle, Abortable},
+    task::SpawnExt,
+};
+use ros2_client::NodeEvent;
+use rustdds::{
+    GUID,
+    dds::statusevents::{
+        DataReaderStatus, DataWriterStatus, DomainParticipantStatusEvent, StatusEvented,
+    },
+};
+
+use crate::BridgeNode;
+
+/// Environment variable with the dora output id for QoS events.
+pub const QOS_EVENTS_OUTPUT_ENV: &str = "ROS2_QOS_EVENTS_OUTPUT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QosEventKind {
+    /// A local reader requested a QoS that a remote writer doesn't offer.
+    RequestedIncompatibleQos,
+    /// A local writer offers a QoS that doesn't satisfy a remote reader.
+    OfferedIncompatibleQos,
+    RequestedDeadlineMissed,
+    OfferedDeadlineMissed,
+    SampleLost,
+    LivelinessChanged,
+    LivelinessLost,
+}
+
+impl QosEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QosEventKind::RequestedIncompatibleQos => "requested_incompatible_qos",
+            QosEventKind::OfferedIncompatibleQos => "offered_incompatible_qos",
+            QosEventKind::RequestedDeadlineMissed => "requested_deadline_missed",
+            QosEventKind::OfferedDeadlineMissed => "offered_deadline_missed",
+            QosEventKind::SampleLost => "sample_lost",
+            QosEventKind::LivelinessChanged => "liveliness_changed",
+            QosEventKind::LivelinessLost => "liveliness_lost",
+        }
+    }
+}
+
+impl fmt::Display for QosEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Reader,
+    Writer,
+}
+
+/// A QoS status change of a local reader or writer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QosEvent {
+    pub kind: QosEventKind,
+    /// ROS2 topic name, or the endpoint GUID if the endpoint isn't watched.
+    pub topic: String,
+    pub endpoint: Endpoint,
+    /// The offending policy for incompatible QoS events, e.g. `Reliability`.
+    pub policy: Option<String>,
+    pub message: String,
+}
+
+impl QosEvent {
+    /// Arrow struct with one `{kind, topic, endpoint, policy, message}` row.
+    pub fn to_arrow(&self) -> StructArray {
+        let endpoint = match self.endpoint {
+            Endpoint::Reader => "reader",
+            Endpoint::Writer => "writer",
+        };
+        let fields = vec![
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("topic", DataType::Utf8, false),
+            Field::new("endpoint", DataType::Utf8, false),
+            Field::new("policy", DataType::Utf8, true),
+            Field::new("message", DataType::Utf8, false),
+        ];
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![self.kind.as_str()])),
+            Arc::new(StringArray::from(vec![self.topic.as_str()])),
+            Arc::new(StringArray::from(vec![endpoint])),
+            Arc::new(StringArray::from(vec![self.policy.as_deref()])),
+            Arc::new(StringArray::from(vec![self.message.as_str()])),
+        ];
+        StructArray::new(fields.into(), columns, None)
+    }
+}
+
+impl fmt::Display for QosEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on `{}`", self.kind, self.topic)?;
+        if let Some(policy) = &self.policy {
+            write!(f, " (policy {policy})")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Reports the QoS status events of a bridge node, see the [module docs](self).
+///
+/// Cheap to clone; all clones share the same event stream.
+#[derive(Clone)]
+pub struct QosEventMonitor {
+    /// Topics of the watched endpoints, to name the participant-level events.
+    topics: Arc<RwLock<HashMap<GUID, String>>>,
+    events_tx: flume::Sender<QosEvent>,
+    events_rx: flume::Receiver<QosEvent>,
+    executor: Arc<futures::executor::ThreadPool>,
+    output: Option<DataId>,
+}
+
+impl QosEventMonitor {
+    /// Start listening for incompatible QoS on all endpoints of `ros_node`.
+    ///
+    /// Events are sent on the dora output `output`, if given.
+    pub fn new(ros_node: &BridgeNode, output: Option<DataId>) -> eyre::Result<Self> {
+        let topics: Arc<RwLock<HashMap<GUID, String>>> = Default::default();
+        let (events_tx, events_rx) = flume::unbounded();
+
+        let mut statuses = ros_node.status_events()?.into_stream();
+        let known = topics.clone();
+        let tx = events_tx.clone();
+        ros_node.spawn(async move {
+            while let Some(status) = statuses.next().await {
+                let NodeEvent::DDS(event) = status else {
+                    continue;
+                };
+                let (kind, endpoint, local, remote, requested, offered) = match event {
+                    DomainParticipantStatusEvent::RemoteReaderQosIncompatible {
+                        local_writer,
+                        remote_reader,
+                        requested_qos,
+                        offered_qos,
+                    } => (
+                        QosEventKind::OfferedIncompatibleQos,
+                        Endpoint::Writer,
+                        local_writer,
+                        remote_reader,
+                        requested_qos,
+                        offered_qos,
+                    ),
+                    DomainParticipantStatusEvent::RemoteWriterQosIncompatible {
+                        local_reader,
+                        remote_writer,
+                        requested_qos,
+                        offered_qos,
+                    } => (
+                        QosEventKind::RequestedIncompatibleQos,
+                        Endpoint::Reader,
+                        local_reader,
+                        remote_writer,
+                        requested_qos,
+                        offered_qos,
+                    ),
+                    _ => continue,
+                };
+                let topic = known
+                    .read()
+                    .unwrap()
+                    .get(&local)
+                    .cloned()
+                    .unwrap_or_else(|| format!("{local:?}"));
+                let event = QosEvent {
+                    kind,
+                    topic,
+                    endpoint,
+                    policy: offered
+                        .compliance_failure_wrt(&requested)
+                        .map(|policy| format!("{policy:?}")),
+                    message: format!(
+                        "remote {remote:?} requested {requested:?}, offered {offered:?}"
+                    ),
+                };
+                if !report(&tx, event) {
+                    break;
+                }
+            }
+        })?;
+
+        Ok(Self {
+            topics,
+            events_tx,
+            events_rx,
+            executor: ros_node.executor().clone(),
+            output,
+        })
+    }
+
+    /// Like [`new`](Self::new), with the output id from `ROS2_QOS_EVENTS_OUTPUT`.
+    pub fn from_env(ros_node: &BridgeNode) -> eyre::Result<Self> {
+        let output = std::env::var(QOS_EVENTS_OUTPUT_ENV)
+            .ok()
+            .filter(|id| !id.is_empty())
+            .map(DataId::from);
+        Self::new(ros_node, output)
+    }
+
+    /// Report deadline missed and liveliness lost events of `publisher` on `topic`.
+    ///
+    /// The returned handle stops watching, and releases `publisher`, when aborted.
+    pub fn watch_publisher<M>(
+        &self,
+        topic: &str,
+        publisher: Arc<ros2_client::Publisher<M>>,
+    ) -> eyre::Result<AbortHandle>
+    where
+        M: Send + Sync + 'static,
+    {
+        let guid = publisher.guid();
+        let topic_name = topic.to_owned();
+        let tx = self.events_tx.clone();
+        self.watch(guid, topic, async move {
+            let statuses = publisher.as_async_status_stream();
+            futures::pin_mut!(statuses);
+            while let Some(status) = statuses.next().await {
+                let kind = match &status {
+                    DataWriterStatus::OfferedDeadlineMissed { .. } => {
+                        QosEventKind::OfferedDeadlineMissed
+                    }
+                    DataWriterStatus::LivelinessLost { .. } => QosEventKind::LivelinessLost,
+                    // incompatible QoS is reported with both QoS by the participant listener
+                    DataWriterStatus::OfferedIncompatibleQos { .. }
+                    | DataWriterStatus::PublicationMatched { .. } => continue,
+                };
+                let event = QosEvent {
+                    kind,
+                    topic: topic_name.clone(),
+                    endpoint: Endpoint::Writer,
+                    policy: None,
+                    message: format!("{status:?}"),
+                };
+                if !report(&tx, event) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Report deadline, sample lost and liveliness events of `subscription` on `topic`.
+    ///
+    /// The returned handle stops watching, and releases `subscription`, when aborted.
+    pub fn watch_subscription<M>(
+        &self,
+        topic: &str,
+        subscription: Arc<ros2_client::Subscription<M>>,
+    ) -> eyre::Result<AbortHandle>
+    where
+        M: Send + Sync + 'static,
+    {
+        let guid = subscription.guid();
+        let topic_name = topic.to_owned();
+        let tx = self.events_tx.clone();
+        self.watch(guid, topic, async move {
+            let statuses = subscription.as_async_status_stream();
+            futures::pin_mut!(statuses);
+            while let Some(status) = statuses.next().await {
+                let kind = match &status {
+                    DataReaderStatus::RequestedDeadlineMissed { .. } => {
+                        QosEventKind::RequestedDeadlineMissed
+                    }
+                    DataReaderStatus::SampleLost { .. } => QosEventKind::SampleLost,
+                    DataReaderStatus::LivelinessChanged { .. } => QosEventKind::LivelinessChanged,
+                    // incompatible QoS is reported with both QoS by the participant listener
+                    DataReaderStatus::RequestedIncompatibleQos { .. }
+                    | DataReaderStatus::SampleRejected { .. }
+                    | DataReaderStatus::SubscriptionMatched { .. } => continue,
+                };
+                let event = QosEvent {
+                    kind,
+                    topic: topic_name.clone(),
+                    endpoint: Endpoint::Reader,
+                    policy: None,
+                    message: format!("{status:?}"),
+                };
+                if !report(&tx, event) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Stream of QoS events, to be merged into the dora event loop.
+    pub fn events(&self) -> flume::r#async::RecvStream<'static, QosEvent> {
+        self.events_rx.clone().into_stream()
+    }
+
+    /// Send `event` on the configured dora output, if any.
+    pub fn send_output(&self, node: &mut DoraNode, event: &QosEvent) -> eyre::Result<()> {
+        let Some(output) = &self.output else {
+            return Ok(());
+        };
+        node.send_output(output.clone(), MetadataParameters::default(), event.to_arrow())
+    }
+
+    /// Run `watch` for the endpoint `guid` until it ends or the returned handle is aborted.
+    fn watch<F>(&self, guid: GUID, topic: &str, watch: F) -> eyre::Result<AbortHandle>
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.topics.write().unwrap().insert(guid, topic.to_owned());
+        let topics = self.topics.clone();
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        self.executor
+            .spawn(async move {
+                let _ = Abortable::new(watch, abort_registration).await;
+                topics.write().unwrap().remove(&guid);
+            })
+            .context("failed to spawn QoS event listener")?;
+        Ok(abort_handle)
+    }
+}
+
+/// Log `event` and queue it for the event loop. Returns `false` once the monitor is dropped.
+fn report(tx: &flume::Sender<QosEvent>, event: QosEvent) -> bool {
+    tracing::warn!("{event}");
+    tx.send(event).is_ok()
+}
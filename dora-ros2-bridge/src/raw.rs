@@ -0,0 +1,134 @@
+//! Serialized ROS2 samples, read and written without decoding them.
+//!
+//! Used where messages only need to be passed on, such as the
+//! [`relay`](crate::relay) and the MCAP recorder, so no message definitions
+//! are needed to handle a topic.
+
+use std::convert::Infallible;
+
+use bytes::Bytes;
+use eyre::{bail, eyre};
+use rustdds::{
+    QosPolicies, RepresentationIdentifier, TopicKind,
+    no_key::{self, Decode, DefaultDecoder, DeserializerAdapter, SerializerAdapter},
+};
+
+use crate::BridgeNode;
+
+/// Encapsulation header of little endian CDR, which precedes the payload in
+/// serialized ROS2 messages (e.g. in rosbag2 files).
+pub const CDR_LE_HEADER: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+
+/// CDR payload of a sample, without the encapsulation header.
+#[derive(Debug, Clone)]
+pub struct RawSample(Bytes);
+
+impl RawSample {
+    pub fn new(payload: Bytes) -> Self {
+        Self(payload)
+    }
+
+    pub fn payload(&self) -> &Bytes {
+        &self.0
+    }
+
+    /// The serialized message including the encapsulation header, as stored by rosbag2.
+    pub fn to_serialized_message(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(CDR_LE_HEADER.len() + self.0.len());
+        message.extend_from_slice(&CDR_LE_HEADER);
+        message.extend_from_slice(&self.0);
+        message
+    }
+}
+
+/// Passes payloads through unchanged. Only little endian CDR is supported,
+/// which is what all common ROS2 platforms use.
+pub struct RawAdapter;
+
+#[derive(Clone)]
+pub struct RawDecoder;
+
+impl DeserializerAdapter<RawSample> for RawAdapter {
+    type Error = Infallible;
+    type Decoded = RawSample;
+
+    fn supported_encodings() -> &'static [RepresentationIdentifier] {
+        &[RepresentationIdentifier::CDR_LE]
+    }
+
+    fn transform_decoded(decoded: Self::Decoded) -> RawSample {
+        decoded
+    }
+}
+
+impl DefaultDecoder<RawSample> for RawAdapter {
+    type Decoder = RawDecoder;
+    const DECODER: Self::Decoder = RawDecoder;
+}
+
+impl Decode<RawSample> for RawDecoder {
+    type Error = Infallible;
+
+    fn decode_bytes(
+        self,
+        input_bytes: &[u8],
+        _encoding: RepresentationIdentifier,
+    ) -> Result<RawSample, Self::Error> {
+        Ok(RawSample(Bytes::copy_from_slice(input_bytes)))
+    }
+}
+
+impl SerializerAdapter<RawSample> for RawAdapter {
+    type Error = Infallible;
+
+    fn output_encoding() -> RepresentationIdentifier {
+        RepresentationIdentifier::CDR_LE
+    }
+
+    fn to_bytes(value: &RawSample) -> Result<Bytes, Self::Error> {
+        Ok(value.0.clone())
+    }
+}
+
+/// Create a reader for the serialized samples of the ROS2 topic `topic`.
+///
+/// `topic` is a fully qualified ROS2 name and `type_name` a message type like
+/// `sensor_msgs/Image` or `sensor_msgs/msg/Image`.
+pub fn create_reader(
+    node: &BridgeNode,
+    topic: &str,
+    type_name: &str,
+    qos: &QosPolicies,
+) -> eyre::Result<no_key::DataReader<RawSample, RawAdapter>> {
+    let participant = node.context().domain_participant();
+    let dds_topic = participant
+        .create_topic(
+            format!("rt{topic}"),
+            dds_type_name(type_name, "msg", "")?,
+            qos,
+            TopicKind::NoKey,
+        )
+        .map_err(|e| eyre!("failed to create topic `{topic}`: {e:?}"))?;
+    participant
+        .create_subscriber(qos)
+        .and_then(|s| s.create_datareader_no_key::<RawSample, RawAdapter>(&dds_topic, None))
+        .map_err(|e| eyre!("failed to create reader for `{topic}`: {e:?}"))
+}
+
+/// Split `package/Name` or `package/kind/Name` into package and name.
+pub fn split_type<'a>(type_name: &'a str, kind: &str) -> eyre::Result<(&'a str, &'a str)> {
+    let parts: Vec<_> = type_name.split('/').collect();
+    match parts.as_slice() {
+        [package, name] => Ok((package, name)),
+        [package, k, name] if *k == kind => Ok((package, name)),
+        _ => bail!(
+            "expected a type like `package/Name` or `package/{kind}/Name`, got `{type_name}`"
+        ),
+    }
+}
+
+/// DDS type name of the ROS2 type, e.g. `std_srvs::srv::dds_::SetBool_Request_`.
+pub fn dds_type_name(type_name: &str, kind: &str, suffix: &str) -> eyre::Result<String> {
+    let (package, name) = split_type(type_name, kind)?;
+    Ok(format!("{package}::{kind}::dds_::{name}{suffix}_"))
+}
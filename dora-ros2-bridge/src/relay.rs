@@ -0,0 +1,621 @@
+//! Relay topics, services and actions between two DDS domains.
+//!
+//! [`DomainRelay`] connects two bridge nodes created on different domains
+//! (called `a` and `b`, e.g. the robot on `ROS_DOMAIN_ID=0` and a lab
+//! workstation on another domain) and forwards the configured endpoints:
+//!
+//! ```yaml
+//! a: { domain_id: 0 }
+//! b: { domain_id: 42 }
+//! topics:
+//!   - topic: /robot/odom
+//!     direction: a_to_b          # a_to_b, b_to_a or both
+//!     remap:
+//!       a_to_b: /lab/robot/odom  # name in the destination domain
+//! services:
+//!   - service: /robot/set_mode
+//!     type: std_srvs/SetBool
+//!     direction: b_to_a          # clients in b, server in a
+//! actions:
+//!   - action: /navigate_to_pose
+//!     type: nav2_msgs/NavigateToPose
+//!     direction: b_to_a
+//! ```
+//!
+//! Samples are relayed as serialized CDR, so no message definitions are
+//! needed. Topics without a configured `qos` are relayed once a publisher is
+//! discovered in the source domain, with the QoS (and, if no `type` is given,
+//! the type) of that publisher, so e.g. transient local topics stay latched.
+//!
+//! Samples written by the relay's own participants are never relayed, so
+//! `both` directions, or two routes forming a cycle, don't loop.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eyre::{Context as _, bail, eyre};
+use futures::{StreamExt, executor::ThreadPool, task::SpawnExt};
+use rustdds::{
+    DomainParticipant, GuidPrefix, QosPolicies, TopicKind, WriteOptionsBuilder, no_key,
+    rpc::SampleIdentity,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{
+    BridgeNode, GraphEvent, GraphMonitor, NameResolver, QosConfig, QosProfile, Ros2ContextConfig,
+    graph::TopicInfo,
+    raw::{RawAdapter, RawSample, dds_type_name, split_type},
+};
+
+/// Requests that are not answered within this time are forgotten.
+const PENDING_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One of the two relayed domains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    A,
+    B,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::A => "a",
+            Side::B => "b",
+        })
+    }
+}
+
+/// Direction of topic samples, or of service and action requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayDirection {
+    AToB,
+    BToA,
+    Both,
+}
+
+impl RelayDirection {
+    /// `(source, destination)` pairs.
+    fn routes(self) -> &'static [(Side, Side)] {
+        match self {
+            RelayDirection::AToB => &[(Side::A, Side::B)],
+            RelayDirection::BToA => &[(Side::B, Side::A)],
+            RelayDirection::Both => &[(Side::A, Side::B), (Side::B, Side::A)],
+        }
+    }
+}
+
+/// Names used in the destination domain, per direction. Unset names are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Remap {
+    pub a_to_b: Option<String>,
+    pub b_to_a: Option<String>,
+}
+
+impl Remap {
+    fn destination_name<'a>(&'a self, source: Side, name: &'a str) -> &'a str {
+        match source {
+            Side::A => self.a_to_b.as_deref(),
+            Side::B => self.b_to_a.as_deref(),
+        }
+        .unwrap_or(name)
+    }
+}
+
+/// A relayed topic, read in the source and published in the destination domain.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayTopic {
+    pub topic: String,
+    /// Message type like `sensor_msgs/Image`, defaults to the discovered type.
+    #[serde(rename = "type", default)]
+    pub message_type: Option<String>,
+    pub direction: RelayDirection,
+    #[serde(default)]
+    pub remap: Remap,
+    /// QoS of both sides, defaults to the QoS of the discovered publisher.
+    #[serde(default)]
+    pub qos: Option<QosConfig>,
+}
+
+/// A relayed service, served in the source and called in the destination domain.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayService {
+    pub service: String,
+    /// Service type like `std_srvs/SetBool`.
+    #[serde(rename = "type")]
+    pub service_type: String,
+    pub direction: RelayDirection,
+    #[serde(default)]
+    pub remap: Remap,
+    #[serde(default)]
+    pub qos: Option<QosConfig>,
+}
+
+/// A relayed action, served in the source and called in the destination domain.
+///
+/// Relays the goal, cancel and result services from the source to the
+/// destination, and the feedback and status topics back.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayAction {
+    pub action: String,
+    /// Action type like `nav2_msgs/NavigateToPose`.
+    #[serde(rename = "type")]
+    pub action_type: String,
+    pub direction: RelayDirection,
+    #[serde(default)]
+    pub remap: Remap,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    /// Context of domain `a`, defaults to the ROS2 environment variables.
+    pub a: Option<Ros2ContextConfig>,
+    pub b: Ros2ContextConfig,
+    pub topics: Vec<RelayTopic>,
+    pub services: Vec<RelayService>,
+    pub actions: Vec<RelayAction>,
+}
+
+impl RelayConfig {
+    /// Check the type names, before any node is created.
+    pub fn validate(&self) -> eyre::Result<()> {
+        for topic in &self.topics {
+            if let Some(message_type) = &topic.message_type {
+                split_type(message_type, "msg")?;
+            }
+        }
+        for service in &self.services {
+            split_type(&service.service_type, "srv")?;
+        }
+        for action in &self.actions {
+            split_type(&action.action_type, "action")?;
+        }
+        Ok(())
+    }
+}
+
+/// Forwards the endpoints of a [`RelayConfig`] between two bridge nodes, see
+/// the [module docs](self).
+pub struct DomainRelay {
+    routes: Vec<String>,
+}
+
+impl DomainRelay {
+    /// Start relaying between `a` and `b`, which must be on different domains.
+    pub fn new(a: &BridgeNode, b: &BridgeNode, config: &RelayConfig) -> eyre::Result<Self> {
+        let domains = [Domain::new(Side::A, a), Domain::new(Side::B, b)];
+        let domain = |side: Side| &domains[side as usize];
+        if domains[0].participant.domain_id() == domains[1].participant.domain_id() {
+            bail!("cannot relay within domain {}", domains[0].participant.domain_id());
+        }
+
+        let mut topics = Vec::new();
+        let mut services = Vec::new();
+        for topic in &config.topics {
+            let dds_type = topic
+                .message_type
+                .as_deref()
+                .map(|t| dds_type_name(t, "msg", ""))
+                .transpose()?;
+            for &(source, dest) in topic.direction.routes() {
+                topics.push(TopicRoute {
+                    source,
+                    dest,
+                    source_name: domain(source).resolve(&topic.topic)?,
+                    dest_name: domain(dest)
+                        .resolve(topic.remap.destination_name(source, &topic.topic))?,
+                    dds_type: dds_type.clone(),
+                    qos: topic.qos.as_ref().map(QosConfig::to_policies),
+                });
+            }
+        }
+        for service in &config.services {
+            let qos = service
+                .qos
+                .clone()
+                .unwrap_or_else(|| QosConfig::new(QosProfile::ServicesDefault));
+            for &(source, dest) in service.direction.routes() {
+                services.push(ServiceRoute {
+                    source,
+                    dest,
+                    source_name: domain(source).resolve(&service.service)?,
+                    dest_name: domain(dest)
+                        .resolve(service.remap.destination_name(source, &service.service))?,
+                    request_type: dds_type_name(&service.service_type, "srv", "_Request")?,
+                    response_type: dds_type_name(&service.service_type, "srv", "_Response")?,
+                    qos: qos.to_policies(),
+                });
+            }
+        }
+        for action in &config.actions {
+            let qos = QosConfig::new(QosProfile::ServicesDefault).to_policies();
+            for &(source, dest) in action.direction.routes() {
+                let source_name = domain(source).resolve(&action.action)?;
+                let dest_name = domain(dest)
+                    .resolve(action.remap.destination_name(source, &action.action))?;
+                let action_type = |suffix| dds_type_name(&action.action_type, "action", suffix);
+                let cancel_type = |suffix| dds_type_name("action_msgs/CancelGoal", "srv", suffix);
+                for (name, request_type, response_type) in [
+                    (
+                        "send_goal",
+                        action_type("_SendGoal_Request")?,
+                        action_type("_SendGoal_Response")?,
+                    ),
+                    ("cancel_goal", cancel_type("_Request")?, cancel_type("_Response")?),
+                    (
+                        "get_result",
+                        action_type("_GetResult_Request")?,
+                        action_type("_GetResult_Response")?,
+                    ),
+                ] {
+                    services.push(ServiceRoute {
+                        source,
+                        dest,
+                        source_name: format!("{source_name}/_action/{name}"),
+                        dest_name: format!("{dest_name}/_action/{name}"),
+                        request_type,
+                        response_type,
+                        qos: qos.clone(),
+                    });
+                }
+                // published by the action server, in the destination domain
+                for (name, dds_type) in [
+                    ("feedback", action_type("_FeedbackMessage")?),
+                    ("status", dds_type_name("action_msgs/GoalStatusArray", "msg", "")?),
+                ] {
+                    topics.push(TopicRoute {
+                        source: dest,
+                        dest: source,
+                        source_name: format!("{dest_name}/_action/{name}"),
+                        dest_name: format!("{source_name}/_action/{name}"),
+                        dds_type: Some(dds_type),
+                        qos: None,
+                    });
+                }
+            }
+        }
+
+        let mut routes = Vec::new();
+        for route in &services {
+            route.start(domain(route.source), domain(route.dest))?;
+            routes.push(route.to_string());
+        }
+        let mut discovered = [Vec::new(), Vec::new()];
+        for route in topics {
+            routes.push(route.to_string());
+            match (&route.dds_type, &route.qos) {
+                (Some(dds_type), Some(qos)) => {
+                    route.start(domain(route.source), domain(route.dest), dds_type, qos)?
+                }
+                // wait for a publisher to learn the type and QoS
+                _ => discovered[route.source as usize].push(route),
+            }
+        }
+        for (node, routes) in [a, b].into_iter().zip(discovered) {
+            if !routes.is_empty() {
+                start_discovered(GraphMonitor::new(node)?, &domains, routes)?;
+            }
+        }
+        for route in &routes {
+            info!("relaying {route}");
+        }
+        Ok(Self { routes })
+    }
+
+    /// Description of every relayed topic and service, e.g. `topic /odom (a) -> /odom (b)`.
+    pub fn routes(&self) -> &[String] {
+        &self.routes
+    }
+}
+
+/// The DDS participant of one side.
+#[derive(Clone)]
+struct Domain {
+    side: Side,
+    participant: DomainParticipant,
+    resolver: NameResolver,
+    executor: Arc<ThreadPool>,
+}
+
+impl Domain {
+    fn new(side: Side, node: &BridgeNode) -> Self {
+        Self {
+            side,
+            participant: node.context().domain_participant().clone(),
+            resolver: node.resolver().clone(),
+            executor: node.executor().clone(),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> eyre::Result<String> {
+        self.resolver.resolve(name)
+    }
+
+    /// Samples written with this prefix come from the relay itself.
+    fn own_prefix(&self) -> GuidPrefix {
+        self.participant.guid().prefix
+    }
+
+    fn topic(&self, name: &str, dds_type: &str, qos: &QosPolicies) -> eyre::Result<rustdds::Topic> {
+        self.participant
+            .create_topic(name.to_owned(), dds_type.to_owned(), qos, TopicKind::NoKey)
+            .map_err(|e| eyre!("failed to create topic `{name}` in domain {}: {e:?}", self.side))
+    }
+
+    fn reader(
+        &self,
+        name: &str,
+        dds_type: &str,
+        qos: &QosPolicies,
+    ) -> eyre::Result<no_key::DataReader<RawSample, RawAdapter>> {
+        let topic = self.topic(name, dds_type, qos)?;
+        self.participant
+            .create_subscriber(qos)
+            .and_then(|s| s.create_datareader_no_key::<RawSample, RawAdapter>(&topic, None))
+            .map_err(|e| {
+                eyre!("failed to create reader for `{name}` in domain {}: {e:?}", self.side)
+            })
+    }
+
+    fn writer(
+        &self,
+        name: &str,
+        dds_type: &str,
+        qos: &QosPolicies,
+    ) -> eyre::Result<no_key::DataWriter<RawSample, RawAdapter>> {
+        let topic = self.topic(name, dds_type, qos)?;
+        self.participant
+            .create_publisher(qos)
+            .and_then(|p| p.create_datawriter_no_key::<RawSample, RawAdapter>(&topic, None))
+            .map_err(|e| {
+                eyre!("failed to create writer for `{name}` in domain {}: {e:?}", self.side)
+            })
+    }
+
+    fn spawn<F>(&self, future: F) -> eyre::Result<()>
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.executor
+            .spawn(future)
+            .context("failed to spawn relay task")
+    }
+}
+
+struct TopicRoute {
+    source: Side,
+    dest: Side,
+    source_name: String,
+    dest_name: String,
+    /// DDS type name, e.g. `std_msgs::msg::dds_::String_`.
+    dds_type: Option<String>,
+    qos: Option<QosPolicies>,
+}
+
+impl TopicRoute {
+    fn start(
+        &self,
+        source: &Domain,
+        dest: &Domain,
+        dds_type: &str,
+        qos: &QosPolicies,
+    ) -> eyre::Result<()> {
+        let reader = source.reader(&format!("rt{}", self.source_name), dds_type, qos)?;
+        let writer = dest.writer(&format!("rt{}", self.dest_name), dds_type, qos)?;
+        let own_prefix = source.own_prefix();
+        let route = self.to_string();
+        source.spawn(async move {
+            let samples = reader.async_sample_stream();
+            futures::pin_mut!(samples);
+            while let Some(sample) = samples.next().await {
+                let sample = match sample {
+                    Ok(sample) => sample,
+                    Err(err) => {
+                        warn!("failed to read {route}: {err:?}");
+                        continue;
+                    }
+                };
+                // written by the relay, i.e. already relayed
+                if sample.sample_info().writer_guid().prefix == own_prefix {
+                    continue;
+                }
+                let timestamp = sample.sample_info().source_timestamp();
+                if let Err(err) = writer.async_write(sample.into_value(), timestamp).await {
+                    warn!("failed to relay {route}: {err:?}");
+                }
+            }
+        })
+    }
+
+    /// Start relaying with the type and QoS of `topic`. Returns `false` if they
+    /// are not known yet.
+    fn start_discovered(
+        &self,
+        source: &Domain,
+        dest: &Domain,
+        topic: &TopicInfo,
+    ) -> eyre::Result<bool> {
+        let dds_type = match (&self.dds_type, topic.type_names.as_slice()) {
+            (Some(dds_type), _) => dds_type.clone(),
+            (None, [type_name]) => dds_type_name(type_name, "msg", "")?,
+            (None, []) => return Ok(false),
+            (None, type_names) => bail!("conflicting types {type_names:?}"),
+        };
+        let Some(qos) = self.qos.as_ref().or(topic.qos.as_ref()) else {
+            return Ok(false);
+        };
+        self.start(source, dest, &dds_type, qos)?;
+        Ok(true)
+    }
+}
+
+impl fmt::Display for TopicRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "topic {} ({}) -> {} ({})",
+            self.source_name, self.source, self.dest_name, self.dest
+        )
+    }
+}
+
+/// Start `routes` once a publisher of their source topic is discovered by `monitor`.
+fn start_discovered(
+    monitor: GraphMonitor,
+    domains: &[Domain; 2],
+    mut routes: Vec<TopicRoute>,
+) -> eyre::Result<()> {
+    let domains = domains.clone();
+    let executor = domains[routes[0].source as usize].executor.clone();
+    let events = monitor.events();
+    executor
+        .spawn(async move {
+            futures::pin_mut!(events);
+            while let Some(event) = events.next().await {
+                let (GraphEvent::TopicAppeared(topic) | GraphEvent::TopicChanged(topic)) = event
+                else {
+                    continue;
+                };
+                if topic.publishers == 0 {
+                    continue;
+                }
+                routes.retain(|route| {
+                    if route.source_name != topic.name {
+                        return true;
+                    }
+                    let source = &domains[route.source as usize];
+                    let dest = &domains[route.dest as usize];
+                    match route.start_discovered(source, dest, &topic) {
+                        Ok(started) => !started,
+                        Err(err) => {
+                            warn!("not relaying {route}: {err:?}");
+                            false
+                        }
+                    }
+                });
+                if routes.is_empty() {
+                    break;
+                }
+            }
+        })
+        .context("failed to spawn relay discovery task")
+}
+
+struct ServiceRoute {
+    /// Domain of the clients, where the service is served by the relay.
+    source: Side,
+    /// Domain of the service server.
+    dest: Side,
+    source_name: String,
+    dest_name: String,
+    request_type: String,
+    response_type: String,
+    qos: QosPolicies,
+}
+
+impl ServiceRoute {
+    fn start(&self, source: &Domain, dest: &Domain) -> eyre::Result<()> {
+        let request_topic = |name: &str| format!("rq{name}Request");
+        let reply_topic = |name: &str| format!("rr{name}Reply");
+        let requests =
+            source.reader(&request_topic(&self.source_name), &self.request_type, &self.qos)?;
+        let replies_out =
+            source.writer(&reply_topic(&self.source_name), &self.response_type, &self.qos)?;
+        let requests_out =
+            dest.writer(&request_topic(&self.dest_name), &self.request_type, &self.qos)?;
+        let replies = dest.reader(&reply_topic(&self.dest_name), &self.response_type, &self.qos)?;
+
+        // identity of the relayed request -> identity of the original request
+        let pending: Arc<Mutex<HashMap<SampleIdentity, (SampleIdentity, Instant)>>> =
+            Default::default();
+
+        let forwarded = pending.clone();
+        let own_prefix = source.own_prefix();
+        let route = self.to_string();
+        source.spawn(async move {
+            let samples = requests.async_sample_stream();
+            futures::pin_mut!(samples);
+            while let Some(sample) = samples.next().await {
+                let sample = match sample {
+                    Ok(sample) => sample,
+                    Err(err) => {
+                        warn!("failed to read request of {route}: {err:?}");
+                        continue;
+                    }
+                };
+                if sample.sample_info().writer_guid().prefix == own_prefix {
+                    continue;
+                }
+                let original = sample.sample_info().sample_identity();
+                match requests_out.write_with_options(
+                    sample.into_value(),
+                    WriteOptionsBuilder::new().build(),
+                ) {
+                    Ok(relayed) => {
+                        let mut pending = forwarded.lock().unwrap();
+                        pending.retain(|_, (_, sent)| sent.elapsed() < PENDING_REQUEST_TIMEOUT);
+                        pending.insert(relayed, (original, Instant::now()));
+                    }
+                    Err(err) => warn!("failed to relay request of {route}: {err:?}"),
+                }
+            }
+        })?;
+
+        let own_prefix = dest.own_prefix();
+        let route = self.to_string();
+        dest.spawn(async move {
+            let samples = replies.async_sample_stream();
+            futures::pin_mut!(samples);
+            while let Some(sample) = samples.next().await {
+                let sample = match sample {
+                    Ok(sample) => sample,
+                    Err(err) => {
+                        warn!("failed to read reply of {route}: {err:?}");
+                        continue;
+                    }
+                };
+                if sample.sample_info().writer_guid().prefix == own_prefix {
+                    continue;
+                }
+                // replies to requests of other clients in the server's domain
+                // are not relayed; replies without a related identity carry the
+                // request id in their payload and are relayed as they are
+                let options = match sample.sample_info().related_sample_identity() {
+                    Some(related) => match pending.lock().unwrap().remove(&related) {
+                        Some((original, _)) => {
+                            WriteOptionsBuilder::new().related_sample_identity(original)
+                        }
+                        None => continue,
+                    },
+                    None => WriteOptionsBuilder::new(),
+                };
+                let reply = sample.into_value();
+                if let Err(err) = replies_out.write_with_options(reply, options.build()) {
+                    warn!("failed to relay reply of {route}: {err:?}");
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Display for ServiceRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "service {} ({}) -> {} ({})",
+            self.source_name, self.source, self.dest_name, self.dest
+        )
+    }
+}
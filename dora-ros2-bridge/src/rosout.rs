@@ -0,0 +1,256 @@
+//! Forward `tracing` events to `/rosout`.
+//!
+//! [`RosoutLayer`] publishes every event at or above a minimum level as an
+//! `rcl_interfaces/Log` message with the node's logger name (e.g.
+//! `dora.ros2_image_sink`), the source file, module and line, so bridge logs
+//! show up in `ros2 topic echo /rosout` and rqt_console. [`init_tracing`]
+//! installs it together with a stdout logger and dora's OpenTelemetry export
+//! as the global subscriber.
+//!
+//! The minimum level is read from `ROS2_ROSOUT_LEVEL` (`debug`, `info`,
+//! `warn`, `error` or `off`), `info` by default.
+//!
+//! dora's `tracing` feature installs its own global subscriber in
+//! `DoraNode::init_from_env`, which fails once [`init_tracing`] installed
+//! one, so nodes using it initialize dora with [`init_dora_node`] instead.
+
+use std::{cell::Cell, fmt, str::FromStr, time::Duration};
+
+use dora_node_api::{DoraNode, EventStream, dora_core::daemon_messages::NodeConfig};
+use eyre::{Context as _, bail};
+use serde::{Deserialize, Serialize};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    warn,
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, layer::Context as LayerContext, layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
+
+use crate::{
+    BridgeNode, Clock, QosConfig,
+    qos::{Durability, QosDuration},
+};
+
+/// Topic the log messages are published on.
+pub const ROSOUT_TOPIC: &str = "/rosout";
+/// Environment variable with the minimum level forwarded to `/rosout`.
+pub const ROSOUT_LEVEL_ENV: &str = "ROS2_ROSOUT_LEVEL";
+/// Environment variable with the Jaeger agent endpoint, set by dora to export traces.
+const JAEGER_ENDPOINT_ENV: &str = "DORA_JAEGER_TRACING";
+/// Environment variable with the node config, set by the dora daemon.
+const NODE_CONFIG_ENV: &str = "DORA_NODE_CONFIG";
+
+/// Read the minimum `/rosout` level from `ROS2_ROSOUT_LEVEL`, `info` if unset.
+pub fn rosout_level_from_env() -> eyre::Result<LevelFilter> {
+    match std::env::var(ROSOUT_LEVEL_ENV) {
+        Ok(value) => parse_level(&value).with_context(|| format!("invalid {ROSOUT_LEVEL_ENV}")),
+        Err(std::env::VarError::NotPresent) => Ok(LevelFilter::INFO),
+        Err(std::env::VarError::NotUnicode(s)) => {
+            bail!("{ROSOUT_LEVEL_ENV} is not valid unicode: `{}`", s.to_string_lossy())
+        }
+    }
+}
+
+/// Parse a level like `rclcpp`'s `--log-level`; `warning` and `fatal` are accepted as well.
+fn parse_level(value: &str) -> eyre::Result<LevelFilter> {
+    Ok(match value.trim().to_lowercase().as_str() {
+        "warning" => LevelFilter::WARN,
+        "fatal" => LevelFilter::ERROR,
+        "none" => LevelFilter::OFF,
+        other => LevelFilter::from_str(other).map_err(|_| {
+            eyre::eyre!("unknown log level `{other}`, expected debug, info, warn, error or off")
+        })?,
+    })
+}
+
+/// Install a stdout logger and a [`RosoutLayer`] for `ros_node` as the global subscriber.
+///
+/// The stdout filter is read from `RUST_LOG` (standard `EnvFilter` directives
+/// like `info,dora_ros2_bridge=debug`, `info` by default), the `/rosout` level
+/// from `ROS2_ROSOUT_LEVEL`. Like dora's
+/// `tracing` feature, spans are exported to Jaeger if `DORA_JAEGER_TRACING`
+/// is set. Fails if a global subscriber is already installed; initialize
+/// dora with [`init_dora_node`] afterwards.
+pub fn init_tracing(ros_node: &mut BridgeNode) -> eyre::Result<()> {
+    let stdout_filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) if std::env::var_os(EnvFilter::DEFAULT_ENV).is_none() => {
+            EnvFilter::new(LevelFilter::INFO.to_string())
+        }
+        Err(err) => return Err(err).context("invalid RUST_LOG"),
+    };
+    let rosout = RosoutLayer::new(ros_node, rosout_level_from_env()?)?;
+    let telemetry = match std::env::var(JAEGER_ENDPOINT_ENV) {
+        Ok(endpoint) => {
+            let tracer = dora_tracing::telemetry::init_jaeger_tracing(&rosout.name, &endpoint)
+                .context("failed to set up Jaeger tracing")?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        Err(_) => None,
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(stdout_filter))
+        .with(rosout)
+        .with(telemetry)
+        .try_init()
+        .context("failed to install the global tracing subscriber")
+}
+
+/// Initialize the dora node like `DoraNode::init_from_env`, but keep the
+/// subscriber installed by [`init_tracing`].
+pub fn init_dora_node() -> eyre::Result<(DoraNode, EventStream)> {
+    let raw = std::env::var(NODE_CONFIG_ENV)
+        .with_context(|| format!("{NODE_CONFIG_ENV} must be set, is the node started by dora?"))?;
+    let config: NodeConfig =
+        serde_yaml::from_str(&raw).with_context(|| format!("invalid {NODE_CONFIG_ENV}"))?;
+    DoraNode::init(config)
+}
+
+/// A `tracing` layer publishing events on `/rosout`.
+///
+/// It creates its own `/rosout` publisher, so the node is built with the
+/// `ros2_client` rosout writer disabled (the [`BridgeNode`] default).
+pub struct RosoutLayer {
+    publisher: ros2_client::Publisher<Log>,
+    /// Logger name, the fully qualified node name with `.` separators like in rclcpp.
+    name: String,
+    level: LevelFilter,
+    clock: Clock,
+}
+
+impl RosoutLayer {
+    pub fn new(ros_node: &mut BridgeNode, level: LevelFilter) -> eyre::Result<Self> {
+        // rclcpp's rosout QoS: reliable, transient local, keep last 1000, lifespan 10s
+        let qos = QosConfig {
+            lifespan: Some(QosDuration::Finite(Duration::from_secs(10))),
+            ..QosConfig::default()
+                .keep_last(1000)
+                .durability(Durability::TransientLocal)
+        };
+        let topic_name = ros_node.resolve_name(ROSOUT_TOPIC)?;
+        let topic = ros_node
+            .node_mut()
+            .create_topic(
+                &topic_name,
+                ros2_client::MessageTypeName::new("rcl_interfaces", "Log"),
+                &qos.to_policies(),
+            )
+            .context("failed to create /rosout topic")?;
+        let publisher = ros_node
+            .node_mut()
+            .create_publisher::<Log>(&topic, None)
+            .context("failed to create /rosout publisher")?;
+
+        let name = ros_node
+            .resolver()
+            .fully_qualified_node_name()
+            .trim_start_matches('/')
+            .replace('/', ".");
+        Ok(Self {
+            publisher,
+            name,
+            level,
+            clock: ros_node.clock().clone(),
+        })
+    }
+}
+
+thread_local! {
+    /// Set while publishing, so logs emitted by the DDS stack don't loop back.
+    static PUBLISHING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl<S: Subscriber> Layer<S> for RosoutLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > self.level || PUBLISHING.with(Cell::get) {
+            return;
+        }
+        PUBLISHING.with(|p| p.set(true));
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let since_epoch = self.clock.since_epoch();
+        let log = Log {
+            stamp: Stamp {
+                sec: since_epoch.as_secs() as i32,
+                nanosec: since_epoch.subsec_nanos(),
+            },
+            level: log_level(*metadata.level()),
+            name: self.name.clone(),
+            msg: visitor.message,
+            file: metadata.file().unwrap_or_default().to_owned(),
+            function: metadata.module_path().unwrap_or(metadata.target()).to_owned(),
+            line: metadata.line().unwrap_or(0),
+        };
+        if let Err(err) = self.publisher.publish(log) {
+            // `PUBLISHING` is set, so this is only logged to stdout
+            warn!("failed to publish to {ROSOUT_TOPIC}: {:?}", err.forget_data());
+        }
+
+        PUBLISHING.with(|p| p.set(false));
+    }
+}
+
+/// `rcl_interfaces/Log` level constants; there is no trace level, so it maps to debug.
+fn log_level(level: Level) -> u8 {
+    match level {
+        Level::TRACE | Level::DEBUG => 10,
+        Level::INFO => 20,
+        Level::WARN => 30,
+        Level::ERROR => 40,
+    }
+}
+
+/// Formats the `message` field followed by the other fields as `key=value`.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        use std::fmt::Write as _;
+
+        if field.name() == "message" {
+            if self.message.is_empty() {
+                let _ = write!(self.message, "{value:?}");
+            } else {
+                self.message = format!("{value:?} {}", self.message);
+            }
+        } else {
+            if !self.message.is_empty() {
+                self.message.push(' ');
+            }
+            let _ = write!(self.message, "{}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        // avoid the quotes added by `Debug`
+        self.record_debug(field, &format_args!("{value}"))
+    }
+}
+
+/// `builtin_interfaces/Time`, independent of the generated messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Stamp {
+    sec: i32,
+    nanosec: u32,
+}
+
+/// `rcl_interfaces/Log`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Log {
+    stamp: Stamp,
+    level: u8,
+    name: String,
+    msg: String,
+    file: String,
+    function: String,
+    line: u32,
+}
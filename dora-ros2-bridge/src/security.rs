@@ -0,0 +1,438 @@
+//! DDS Security for secured ROS2 networks, using SROS2 enclaves.
+//!
+//! The configuration follows the `rcl` security environment variables:
+//!
+//! - `ROS_SECURITY_ENABLE`: `true` to enable security
+//! - `ROS_SECURITY_KEYSTORE`: keystore created with `ros2 security create_keystore`
+//! - `ROS_SECURITY_ENCLAVE_OVERRIDE`: enclave within the keystore, `/` by default
+//! - `ROS_SECURITY_STRATEGY`: `Enforce` refuses to start without the enclave's
+//!   artifacts, `Permissive` (the default) then runs without security
+//! - `ROS2_SECURITY_GOVERNANCE`, `ROS2_SECURITY_PERMISSIONS`: signed governance
+//!   and permissions documents, instead of the enclave's `governance.p7s` and
+//!   `permissions.p7s`
+//!
+//! The artifacts are loaded through the `rustdds` security plugins, which
+//! are enabled by the `security` feature.
+
+use std::path::{Path, PathBuf};
+
+use eyre::{Context as _, bail, eyre};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::context::env_var;
+
+pub const SECURITY_ENABLE_ENV: &str = "ROS_SECURITY_ENABLE";
+pub const SECURITY_KEYSTORE_ENV: &str = "ROS_SECURITY_KEYSTORE";
+pub const SECURITY_ENCLAVE_ENV: &str = "ROS_SECURITY_ENCLAVE_OVERRIDE";
+pub const SECURITY_STRATEGY_ENV: &str = "ROS_SECURITY_STRATEGY";
+pub const SECURITY_GOVERNANCE_ENV: &str = "ROS2_SECURITY_GOVERNANCE";
+pub const SECURITY_PERMISSIONS_ENV: &str = "ROS2_SECURITY_PERMISSIONS";
+
+/// Enclave used unless overridden, like `rcl` without `--enclave`.
+pub const ROOT_ENCLAVE: &str = "/";
+
+/// PIN passed to the security plugins; SROS2 private keys are not on a PKCS#11 token.
+#[cfg(feature = "security")]
+const PKCS11_PIN: &str = "no_pkcs11_pin";
+
+/// Mirrors `ROS_SECURITY_STRATEGY`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum SecurityStrategy {
+    /// Use security if the enclave's artifacts are found, run without otherwise.
+    #[default]
+    #[serde(alias = "permissive")]
+    Permissive,
+    /// Refuse to start without the enclave's artifacts.
+    #[serde(alias = "enforce")]
+    Enforce,
+}
+
+impl std::str::FromStr for SecurityStrategy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "permissive" | "" => Ok(Self::Permissive),
+            "enforce" => Ok(Self::Enforce),
+            other => Err(eyre!(
+                "invalid security strategy `{other}`, expected Enforce or Permissive"
+            )),
+        }
+    }
+}
+
+/// An SROS2 enclave in a keystore.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    pub keystore: PathBuf,
+    #[serde(default = "root_enclave")]
+    pub enclave: String,
+    #[serde(default)]
+    pub strategy: SecurityStrategy,
+    /// Signed governance document, defaults to the enclave's `governance.p7s`.
+    #[serde(default)]
+    pub governance: Option<PathBuf>,
+    /// Signed permissions document, defaults to the enclave's `permissions.p7s`.
+    #[serde(default)]
+    pub permissions: Option<PathBuf>,
+}
+
+fn root_enclave() -> String {
+    ROOT_ENCLAVE.to_owned()
+}
+
+/// Files of an enclave, see [`SecurityConfig::artifacts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnclaveArtifacts {
+    pub directory: PathBuf,
+    pub identity_ca: PathBuf,
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+    pub permissions_ca: PathBuf,
+    pub governance: PathBuf,
+    pub permissions: PathBuf,
+}
+
+impl EnclaveArtifacts {
+    fn files(&self) -> [&Path; 6] {
+        [
+            &self.identity_ca,
+            &self.certificate,
+            &self.private_key,
+            &self.permissions_ca,
+            &self.governance,
+            &self.permissions,
+        ]
+    }
+}
+
+impl SecurityConfig {
+    pub fn new(keystore: impl Into<PathBuf>) -> Self {
+        Self {
+            keystore: keystore.into(),
+            enclave: root_enclave(),
+            strategy: SecurityStrategy::default(),
+            governance: None,
+            permissions: None,
+        }
+    }
+
+    pub fn enclave(mut self, enclave: impl Into<String>) -> Self {
+        self.enclave = enclave.into();
+        self
+    }
+
+    pub fn strategy(mut self, strategy: SecurityStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Read the configuration from the security environment variables.
+    ///
+    /// Returns `None` unless `ROS_SECURITY_ENABLE` is `true`.
+    pub fn from_env() -> eyre::Result<Option<Self>> {
+        Self::from_vars(&env_var)
+    }
+
+    /// [`Self::from_env`] with the variables looked up by `var`.
+    pub(crate) fn from_vars(
+        var: &dyn Fn(&str) -> eyre::Result<Option<String>>,
+    ) -> eyre::Result<Option<Self>> {
+        match var(SECURITY_ENABLE_ENV)?.as_deref().map(str::trim) {
+            Some("true") => {}
+            None | Some("false" | "") => return Ok(None),
+            Some(other) => {
+                bail!("invalid {SECURITY_ENABLE_ENV} `{other}`, expected `true` or `false`")
+            }
+        }
+        let strategy = match var(SECURITY_STRATEGY_ENV)? {
+            Some(value) => value
+                .parse()
+                .with_context(|| format!("invalid {SECURITY_STRATEGY_ENV}"))?,
+            None => SecurityStrategy::default(),
+        };
+        let Some(keystore) = var(SECURITY_KEYSTORE_ENV)? else {
+            bail!("{SECURITY_ENABLE_ENV} is set, but {SECURITY_KEYSTORE_ENV} is not");
+        };
+        Ok(Some(Self {
+            keystore: keystore.into(),
+            enclave: var(SECURITY_ENCLAVE_ENV)?.unwrap_or_else(root_enclave),
+            strategy,
+            governance: var(SECURITY_GOVERNANCE_ENV)?.map(PathBuf::from),
+            permissions: var(SECURITY_PERMISSIONS_ENV)?.map(PathBuf::from),
+        }))
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.keystore.as_os_str().is_empty() {
+            bail!("empty security keystore path");
+        }
+        if !self.enclave.starts_with('/')
+            || self.enclave.split('/').any(|part| part == ".." || part == ".")
+        {
+            bail!("invalid enclave `{}`, expected an absolute path like `/dora`", self.enclave);
+        }
+        Ok(())
+    }
+
+    /// Paths of the enclave's artifacts, in `<keystore>/enclaves/<enclave>`.
+    pub fn artifacts(&self) -> EnclaveArtifacts {
+        let directory = self
+            .keystore
+            .join("enclaves")
+            .join(self.enclave.trim_start_matches('/'));
+        EnclaveArtifacts {
+            identity_ca: directory.join("identity_ca.cert.pem"),
+            certificate: directory.join("cert.pem"),
+            private_key: directory.join("key.pem"),
+            permissions_ca: directory.join("permissions_ca.cert.pem"),
+            governance: self
+                .governance
+                .clone()
+                .unwrap_or_else(|| directory.join("governance.p7s")),
+            permissions: self
+                .permissions
+                .clone()
+                .unwrap_or_else(|| directory.join("permissions.p7s")),
+            directory,
+        }
+    }
+
+    /// The enclave's artifacts, or an error listing the missing files.
+    pub fn check(&self) -> eyre::Result<EnclaveArtifacts> {
+        self.validate()?;
+        let artifacts = self.artifacts();
+        let missing: Vec<_> = artifacts
+            .files()
+            .into_iter()
+            .filter(|file| !file.is_file())
+            .map(|file| file.display().to_string())
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "missing security artifacts for enclave `{}`: {}",
+                self.enclave,
+                missing.join(", ")
+            );
+        }
+        Ok(artifacts)
+    }
+
+    /// The artifacts to enable security with, `None` to run without security.
+    ///
+    /// Missing artifacts, or a build without the `security` feature, are an
+    /// error with the `Enforce` strategy and disable security otherwise.
+    pub(crate) fn resolve(&self) -> eyre::Result<Option<EnclaveArtifacts>> {
+        let checked = self.check().and_then(|artifacts| {
+            if !cfg!(feature = "security") {
+                bail!("dora-ros2-bridge was built without the `security` feature");
+            }
+            Ok(artifacts)
+        });
+        match (checked, self.strategy) {
+            (Ok(artifacts), _) => Ok(Some(artifacts)),
+            (Err(err), SecurityStrategy::Enforce) => Err(err.wrap_err(format!(
+                "refusing to start without DDS security ({SECURITY_STRATEGY_ENV}=Enforce)"
+            ))),
+            (Err(err), SecurityStrategy::Permissive) => {
+                warn!("running without DDS security: {err:#}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Enable the security plugins on `participant`, see [`Self::resolve`].
+    pub(crate) fn configure(
+        &self,
+        participant: rustdds::DomainParticipantBuilder,
+    ) -> eyre::Result<rustdds::DomainParticipantBuilder> {
+        Ok(match self.resolve()? {
+            Some(artifacts) => enable(participant, artifacts),
+            None => participant,
+        })
+    }
+}
+
+#[cfg(feature = "security")]
+fn enable(
+    participant: rustdds::DomainParticipantBuilder,
+    artifacts: EnclaveArtifacts,
+) -> rustdds::DomainParticipantBuilder {
+    use rustdds::DomainParticipantSecurityConfigFiles;
+
+    let mut files = DomainParticipantSecurityConfigFiles::with_ros_default_names(
+        &artifacts.directory,
+        PKCS11_PIN.to_owned(),
+    );
+    files.domain_governance_document = artifacts.governance;
+    files.participant_permissions_document = artifacts.permissions;
+    participant.builder_with_security(files)
+}
+
+#[cfg(not(feature = "security"))]
+fn enable(
+    participant: rustdds::DomainParticipantBuilder,
+    _artifacts: EnclaveArtifacts,
+) -> rustdds::DomainParticipantBuilder {
+    participant
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const ENCLAVE_FILES: [&str; 6] = [
+        "identity_ca.cert.pem",
+        "cert.pem",
+        "key.pem",
+        "permissions_ca.cert.pem",
+        "governance.p7s",
+        "permissions.p7s",
+    ];
+
+    /// A keystore with a `/dora` enclave containing `files`.
+    fn keystore(files: &[&str]) -> tempfile::TempDir {
+        let keystore = tempfile::tempdir().unwrap();
+        let enclave = keystore.path().join("enclaves/dora");
+        fs::create_dir_all(&enclave).unwrap();
+        for file in files {
+            fs::write(enclave.join(file), "").unwrap();
+        }
+        keystore
+    }
+
+    fn from_vars(vars: &[(&str, &str)]) -> eyre::Result<Option<SecurityConfig>> {
+        SecurityConfig::from_vars(&|name| {
+            Ok(vars
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string()))
+        })
+    }
+
+    #[test]
+    fn config_from_env() {
+        assert_eq!(from_vars(&[]).unwrap(), None);
+        assert_eq!(
+            from_vars(&[("ROS_SECURITY_ENABLE", "false")]).unwrap(),
+            None
+        );
+        let config = from_vars(&[
+            ("ROS_SECURITY_ENABLE", "true"),
+            ("ROS_SECURITY_KEYSTORE", "/keystore"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(config, SecurityConfig::new("/keystore"));
+        let config = from_vars(&[
+            ("ROS_SECURITY_ENABLE", "true"),
+            ("ROS_SECURITY_KEYSTORE", "/keystore"),
+            ("ROS_SECURITY_ENCLAVE_OVERRIDE", "/dora"),
+            ("ROS_SECURITY_STRATEGY", "Enforce"),
+            ("ROS2_SECURITY_PERMISSIONS", "/permissions.p7s"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.enclave, "/dora");
+        assert_eq!(config.strategy, SecurityStrategy::Enforce);
+        assert_eq!(config.governance, None);
+        assert_eq!(config.permissions, Some("/permissions.p7s".into()));
+
+        assert!(from_vars(&[("ROS_SECURITY_ENABLE", "yes")]).is_err());
+        assert!(from_vars(&[("ROS_SECURITY_ENABLE", "true")]).is_err());
+        assert!(
+            from_vars(&[
+                ("ROS_SECURITY_ENABLE", "true"),
+                ("ROS_SECURITY_KEYSTORE", "/keystore"),
+                ("ROS_SECURITY_STRATEGY", "Strict"),
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn validate_enclave() {
+        for enclave in ["/", "/dora", "/robot/camera"] {
+            let config = SecurityConfig::new("/keystore").enclave(enclave);
+            assert!(config.validate().is_ok(), "`{enclave}`");
+        }
+        for enclave in ["", "dora", "/dora/../other", "/./dora"] {
+            let config = SecurityConfig::new("/keystore").enclave(enclave);
+            assert!(config.validate().is_err(), "`{enclave}`");
+        }
+        assert!(SecurityConfig::new("").validate().is_err());
+    }
+
+    #[test]
+    fn check_enclave() {
+        let full = keystore(&ENCLAVE_FILES);
+        let artifacts = SecurityConfig::new(full.path())
+            .enclave("/dora")
+            .check()
+            .unwrap();
+        assert_eq!(artifacts.directory, full.path().join("enclaves/dora"));
+        assert_eq!(artifacts.private_key, artifacts.directory.join("key.pem"));
+
+        let err = SecurityConfig::new(full.path())
+            .enclave("/other")
+            .check()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("enclave `/other`"), "{err}");
+
+        let partial = keystore(&ENCLAVE_FILES[..4]);
+        let err = SecurityConfig::new(partial.path())
+            .enclave("/dora")
+            .check()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("governance.p7s") && err.contains("permissions.p7s"),
+            "{err}"
+        );
+        assert!(!err.contains("key.pem"), "{err}");
+
+        // documents outside the enclave replace the missing ones
+        let mut config = SecurityConfig::new(partial.path()).enclave("/dora");
+        config.governance = Some(full.path().join("enclaves/dora/governance.p7s"));
+        config.permissions = Some(full.path().join("enclaves/dora/permissions.p7s"));
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn strategies() {
+        let full = keystore(&ENCLAVE_FILES);
+        let partial = keystore(&ENCLAVE_FILES[1..]);
+        let missing = tempfile::tempdir().unwrap();
+        for strategy in [SecurityStrategy::Permissive, SecurityStrategy::Enforce] {
+            let config = SecurityConfig::new(full.path())
+                .enclave("/dora")
+                .strategy(strategy);
+            if cfg!(feature = "security") {
+                assert!(config.resolve().unwrap().is_some(), "{strategy:?}");
+            } else if strategy == SecurityStrategy::Enforce {
+                let err = format!("{:#}", config.resolve().unwrap_err());
+                assert!(err.contains("without the `security` feature"), "{err}");
+            } else {
+                assert_eq!(config.resolve().unwrap(), None);
+            }
+        }
+        for keystore in [partial.path(), missing.path()] {
+            let config = SecurityConfig::new(keystore).enclave("/dora");
+            assert_eq!(config.clone().resolve().unwrap(), None);
+            let err = format!(
+                "{:#}",
+                config
+                    .strategy(SecurityStrategy::Enforce)
+                    .resolve()
+                    .unwrap_err()
+            );
+            assert!(err.contains("ROS_SECURITY_STRATEGY=Enforce"), "{err}");
+            assert!(err.contains("identity_ca.cert.pem"), "{err}");
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Call ROS2 services from a dora dataflow.
+//!
+//! Each dora input becomes a service request. Requests are tagged with a
+//! `request_id` metadata parameter (taken from the input if present, generated
+//! otherwise), so several calls can be in flight at the same time. The response,
+//! or a structured error, is sent on a dora output with the same `request_id`.
+
+use std::{
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use dora_node_api::{
+    ArrowData, DoraNode, IntoArrow, MetadataParameters, Parameter,
+    arrow::{
+        array::{ArrayRef, StringArray, StructArray},
+        datatypes::{DataType, Field},
+    },
+    dora_core::config::DataId,
+};
+use eyre::{Context as _, eyre};
+use futures::{
+    executor::ThreadPool,
+    future::{Either, select},
+    task::SpawnExt,
+};
+use ros2_client::{Service, ServiceMapping};
+use tracing::warn;
+
+use crate::{BridgeNode, QosConfig, QosProfile};
+
+/// Metadata parameter used to correlate requests and responses.
+pub const REQUEST_ID_PARAM: &str = "request_id";
+
+#[derive(Debug, Clone)]
+pub struct ServiceClientConfig {
+    /// Service name, resolved against the node namespace.
+    pub service_name: String,
+    /// Time to wait for a response before reporting a timeout error.
+    pub timeout: Duration,
+    /// QoS for the request writer and response reader, `services_default` by default.
+    pub qos: QosConfig,
+    pub response_output: DataId,
+    pub error_output: DataId,
+}
+
+impl ServiceClientConfig {
+    pub fn new(service_name: impl Into<String>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            timeout: Duration::from_secs(5),
+            qos: QosConfig::new(QosProfile::ServicesDefault),
+            response_output: DataId::from("response".to_owned()),
+            error_output: DataId::from("error".to_owned()),
+        }
+    }
+}
+
+/// Why a service call did not produce a response.
+#[derive(Debug, Clone)]
+pub enum ServiceCallError {
+    /// The dora input could not be converted into a request.
+    InvalidRequest(String),
+    /// No response arrived within the configured timeout.
+    Timeout(Duration),
+    /// The call failed on the DDS level.
+    Failed(String),
+}
+
+impl ServiceCallError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServiceCallError::InvalidRequest(_) => "invalid_request",
+            ServiceCallError::Timeout(_) => "timeout",
+            ServiceCallError::Failed(_) => "failed",
+        }
+    }
+
+    /// Arrow struct `{request_id, kind, message}` sent on the error output.
+    pub fn to_arrow(&self, request_id: &str) -> StructArray {
+        self.to_arrow_with_id("request_id", request_id)
+    }
+
+    /// Arrow struct `{<id_field>, kind, message}`, e.g. keyed by `goal_id` for actions.
+    pub(crate) fn to_arrow_with_id(&self, id_field: &str, id: &str) -> StructArray {
+        let fields = vec![
+            Field::new(id_field, DataType::Utf8, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("message", DataType::Utf8, false),
+        ];
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![id.to_owned()])),
+            Arc::new(StringArray::from(vec![self.kind()])),
+            Arc::new(StringArray::from(vec![self.to_string()])),
+        ];
+        StructArray::new(fields.into(), columns, None)
+    }
+}
+
+impl std::fmt::Display for ServiceCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceCallError::InvalidRequest(err) => write!(f, "invalid request: {err}"),
+            ServiceCallError::Timeout(timeout) => {
+                write!(f, "no response within {:.3}s", timeout.as_secs_f64())
+            }
+            ServiceCallError::Failed(err) => write!(f, "service call failed: {err}"),
+        }
+    }
+}
+
+/// Outcome of a single service call.
+#[derive(Debug)]
+pub struct ServiceCallResult<R> {
+    pub request_id: String,
+    pub result: Result<R, ServiceCallError>,
+}
+
+/// A ROS2 service client driven by dora inputs.
+pub struct ServiceClientBridge<S: Service> {
+    client: Arc<ros2_client::Client<S>>,
+    config: ServiceClientConfig,
+    executor: Arc<ThreadPool>,
+    results_tx: flume::Sender<ServiceCallResult<S::Response>>,
+    results_rx: flume::Receiver<ServiceCallResult<S::Response>>,
+    next_request_id: AtomicU64,
+}
+
+impl<S> ServiceClientBridge<S>
+where
+    S: Service + 'static,
+    S::Request: Clone + Send + 'static,
+    S::Response: Send + 'static,
+{
+    pub fn new(
+        ros_node: &mut BridgeNode,
+        service_type: ros2_client::ServiceTypeName,
+        config: ServiceClientConfig,
+    ) -> eyre::Result<Self> {
+        let service_name = ros_node.resolve_name(&config.service_name)?;
+        let qos = config.qos.to_policies();
+        let client = ros_node
+            .node_mut()
+            .create_client::<S>(
+                ServiceMapping::Enhanced,
+                &service_name,
+                &service_type,
+                qos.clone(),
+                qos,
+            )
+            .map_err(|e| {
+                eyre!("failed to create client for `{}`: {e:?}", config.service_name)
+            })?;
+        let (results_tx, results_rx) = flume::unbounded();
+
+        Ok(Self {
+            client: Arc::new(client),
+            config,
+            executor: ros_node.executor().clone(),
+            results_tx,
+            results_rx,
+            next_request_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Convert a dora input into a request and start the call.
+    ///
+    /// Conversion errors are reported as [`ServiceCallError::InvalidRequest`] results.
+    /// Returns the request id.
+    pub fn handle_input(
+        &self,
+        parameters: &MetadataParameters,
+        data: &ArrowData,
+    ) -> eyre::Result<String>
+    where
+        S::Request: for<'a> TryFrom<&'a ArrowData, Error = eyre::Report>,
+    {
+        match S::Request::try_from(data) {
+            Ok(request) => self.call(parameters, request),
+            Err(err) => {
+                let request_id = self.request_id(parameters);
+                self.send_result(ServiceCallResult {
+                    request_id: request_id.clone(),
+                    result: Err(ServiceCallError::InvalidRequest(format!("{err:#}"))),
+                });
+                Ok(request_id)
+            }
+        }
+    }
+
+    /// Start a service call in the background and return its request id.
+    ///
+    /// The outcome is delivered through [`results`](Self::results).
+    pub fn call(
+        &self,
+        parameters: &MetadataParameters,
+        request: S::Request,
+    ) -> eyre::Result<String> {
+        let request_id = self.request_id(parameters);
+        let client = self.client.clone();
+        let results_tx = self.results_tx.clone();
+        let timeout = self.config.timeout;
+        let id = request_id.clone();
+
+        self.executor
+            .spawn(async move {
+                let result = with_timeout(client.async_call_service(request), timeout).await;
+                let _ = results_tx.send(ServiceCallResult { request_id: id, result });
+            })
+            .context("failed to spawn service call")?;
+
+        Ok(request_id)
+    }
+
+    /// Stream of finished calls, to be merged into the dora event loop.
+    pub fn results(&self) -> flume::r#async::RecvStream<'static, ServiceCallResult<S::Response>> {
+        self.results_rx.clone().into_stream()
+    }
+
+    /// Send the response or the error of a finished call on the configured dora outputs.
+    pub fn send_output(
+        &self,
+        node: &mut DoraNode,
+        result: ServiceCallResult<S::Response>,
+    ) -> eyre::Result<()>
+    where
+        S::Response: IntoArrow,
+    {
+        let mut params = MetadataParameters::default();
+        params.insert(
+            REQUEST_ID_PARAM.into(),
+            Parameter::String(result.request_id.clone()),
+        );
+        match result.result {
+            Ok(response) => node.send_output(
+                self.config.response_output.clone(),
+                params,
+                response.into_arrow(),
+            ),
+            Err(err) => {
+                warn!(
+                    "service call `{}` to `{}` failed: {err}",
+                    result.request_id, self.config.service_name
+                );
+                node.send_output(
+                    self.config.error_output.clone(),
+                    params,
+                    err.to_arrow(&result.request_id),
+                )
+            }
+        }
+    }
+
+    fn send_result(&self, result: ServiceCallResult<S::Response>) {
+        let _ = self.results_tx.send(result);
+    }
+
+    fn request_id(&self, parameters: &MetadataParameters) -> String {
+        match parameters.get(REQUEST_ID_PARAM) {
+            Some(Parameter::String(id)) => id.clone(),
+            Some(Parameter::Integer(id)) => id.to_string(),
+            _ => self.next_request_id.fetch_add(1, Ordering::Relaxed).to_string(),
+        }
+    }
+}
+
+/// Await a ROS2 service call, failing with [`ServiceCallError::Timeout`] after `timeout`.
+pub(crate) async fn with_timeout<T, E, F>(
+    call: F,
+    timeout: Duration,
+) -> Result<T, ServiceCallError>
+where
+    F: Future<Output = Result<T, E>>,
+    E: std::fmt::Debug,
+{
+    match select(pin!(call), futures_timer::Delay::new(timeout)).await {
+        Either::Left((Ok(response), _)) => Ok(response),
+        Either::Left((Err(err), _)) => Err(ServiceCallError::Failed(format!("{err:?}"))),
+        Either::Right(_) => Err(ServiceCallError::Timeout(timeout)),
+    }
+}
@@ -0,0 +1,261 @@
+//! Serve ROS2 services from a dora dataflow.
+//!
+//! Every incoming ROS2 request is sent on a dora output, tagged with a
+//! `request_id` metadata parameter. The dataflow answers by sending the
+//! response to a dora input with the same `request_id`. Requests that are not
+//! answered within the configured deadline are answered with a timeout
+//! response, so ROS2 callers never hang.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use dora_node_api::{
+    ArrowData, DoraNode, IntoArrow, MetadataParameters, Parameter, dora_core::config::DataId,
+};
+use eyre::eyre;
+use futures::task::SpawnExt;
+use ros2_client::{Service, ServiceMapping, service::RmwRequestId};
+use tracing::warn;
+
+use crate::{
+    BridgeNode, QosConfig, QosProfile, backoff::ReceiveBackoff, service_client::REQUEST_ID_PARAM,
+};
+
+#[derive(Debug, Clone)]
+pub struct ServiceServerConfig {
+    /// Service name, resolved against the node namespace.
+    pub service_name: String,
+    /// Time the dataflow has to answer a request before the timeout response is sent.
+    pub timeout: Duration,
+    /// QoS for the request reader and response writer, `services_default` by default.
+    pub qos: QosConfig,
+    pub request_output: DataId,
+}
+
+impl ServiceServerConfig {
+    pub fn new(service_name: impl Into<String>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            timeout: Duration::from_secs(5),
+            qos: QosConfig::new(QosProfile::ServicesDefault),
+            request_output: DataId::from("request".to_owned()),
+        }
+    }
+}
+
+/// A ROS2 request waiting to be forwarded to dora.
+#[derive(Debug)]
+pub struct ServiceRequest<Q> {
+    pub request_id: String,
+    pub request: Q,
+}
+
+type ErrorResponse<R> = Arc<dyn Fn(&str) -> R + Send + Sync>;
+
+/// State shared between the bridge and its background tasks.
+struct Shared<S: Service> {
+    server: ros2_client::Server<S>,
+    pending: Mutex<HashMap<String, RmwRequestId>>,
+    error_response: ErrorResponse<S::Response>,
+    service_name: String,
+}
+
+impl<S: Service> Shared<S> {
+    /// Send `response` if the request is still pending. Returns `false` if it
+    /// was already answered (e.g. by a timeout).
+    fn respond(&self, request_id: &str, response: S::Response) -> eyre::Result<bool> {
+        let Some(rmw_id) = self.pending.lock().unwrap().remove(request_id) else {
+            return Ok(false);
+        };
+        self.server
+            .send_response(rmw_id, response)
+            .map_err(|e| eyre!("failed to send response on `{}`: {e:?}", self.service_name))?;
+        Ok(true)
+    }
+
+    fn respond_with_error(&self, request_id: &str, message: &str) {
+        warn!(
+            "service request `{request_id}` on `{}` failed: {message}",
+            self.service_name
+        );
+        if let Err(err) = self.respond(request_id, (self.error_response)(message)) {
+            warn!("{err:?}");
+        }
+    }
+}
+
+/// A ROS2 service server whose requests are answered by the dora dataflow.
+pub struct ServiceServerBridge<S: Service> {
+    shared: Arc<Shared<S>>,
+    config: ServiceServerConfig,
+    requests_rx: flume::Receiver<ServiceRequest<S::Request>>,
+}
+
+impl<S> ServiceServerBridge<S>
+where
+    S: Service + 'static,
+    S::Request: Send + 'static,
+    S::Response: Send + 'static,
+{
+    /// Create the server and start receiving requests.
+    ///
+    /// `error_response` builds the response sent for requests that time out or
+    /// whose dora response can't be converted; it gets a description of the error.
+    pub fn new(
+        ros_node: &mut BridgeNode,
+        service_type: ros2_client::ServiceTypeName,
+        config: ServiceServerConfig,
+        error_response: impl Fn(&str) -> S::Response + Send + Sync + 'static,
+    ) -> eyre::Result<Self> {
+        let service_name = ros_node.resolve_name(&config.service_name)?;
+        let qos = config.qos.to_policies();
+        let server = ros_node
+            .node_mut()
+            .create_server::<S>(
+                ServiceMapping::Enhanced,
+                &service_name,
+                &service_type,
+                qos.clone(),
+                qos,
+            )
+            .map_err(|e| {
+                eyre!("failed to create server for `{}`: {e:?}", config.service_name)
+            })?;
+        let shared = Arc::new(Shared {
+            server,
+            pending: Mutex::new(HashMap::new()),
+            error_response: Arc::new(error_response),
+            service_name: config.service_name.clone(),
+        });
+        let (requests_tx, requests_rx) = flume::unbounded();
+
+        let receiver = shared.clone();
+        let executor = ros_node.executor().clone();
+        let timeout = config.timeout;
+        ros_node.spawn(async move {
+            let next_request_id = AtomicU64::new(0);
+            let mut backoff =
+                ReceiveBackoff::new(format!("requests on `{}`", receiver.service_name));
+            loop {
+                let (rmw_id, request) = match receiver.server.async_receive_request().await {
+                    Ok(request) => request,
+                    Err(err) => {
+                        backoff.failed(err).await;
+                        continue;
+                    }
+                };
+                backoff.succeeded();
+                let request_id = next_request_id.fetch_add(1, Ordering::Relaxed).to_string();
+                receiver
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .insert(request_id.clone(), rmw_id);
+
+                let expired = receiver.clone();
+                let expired_id = request_id.clone();
+                let deadline = async move {
+                    futures_timer::Delay::new(timeout).await;
+                    if expired.pending.lock().unwrap().contains_key(&expired_id) {
+                        let message =
+                            format!("no response within {:.3}s", timeout.as_secs_f64());
+                        expired.respond_with_error(&expired_id, &message);
+                    }
+                };
+                if let Err(err) = executor.spawn(deadline) {
+                    warn!("failed to spawn request deadline: {err:?}");
+                }
+
+                if requests_tx
+                    .send(ServiceRequest { request_id, request })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        })?;
+
+        Ok(Self {
+            shared,
+            config,
+            requests_rx,
+        })
+    }
+
+    /// Stream of incoming requests, to be merged into the dora event loop.
+    pub fn requests(&self) -> flume::r#async::RecvStream<'static, ServiceRequest<S::Request>> {
+        self.requests_rx.clone().into_stream()
+    }
+
+    /// Send a received request on the configured dora output.
+    pub fn send_output(
+        &self,
+        node: &mut DoraNode,
+        request: ServiceRequest<S::Request>,
+    ) -> eyre::Result<()>
+    where
+        S::Request: IntoArrow,
+    {
+        let mut params = MetadataParameters::default();
+        params.insert(
+            REQUEST_ID_PARAM.into(),
+            Parameter::String(request.request_id),
+        );
+        node.send_output(
+            self.config.request_output.clone(),
+            params,
+            request.request.into_arrow(),
+        )
+    }
+
+    /// Answer a pending request with the response received as dora input.
+    ///
+    /// Responses without `request_id`, or for unknown or already timed out
+    /// requests, are dropped with a warning. Responses that can't be converted
+    /// are answered with the error response.
+    pub fn handle_input(
+        &self,
+        parameters: &MetadataParameters,
+        data: &ArrowData,
+    ) -> eyre::Result<()>
+    where
+        S::Response: for<'a> TryFrom<&'a ArrowData, Error = eyre::Report>,
+    {
+        let request_id = match parameters.get(REQUEST_ID_PARAM) {
+            Some(Parameter::String(id)) => id.clone(),
+            Some(Parameter::Integer(id)) => id.to_string(),
+            _ => {
+                warn!(
+                    "dropping response for `{}` without `{REQUEST_ID_PARAM}` metadata",
+                    self.config.service_name
+                );
+                return Ok(());
+            }
+        };
+        match S::Response::try_from(data) {
+            Ok(response) => self.respond(&request_id, response),
+            Err(err) => {
+                self.shared
+                    .respond_with_error(&request_id, &format!("invalid response: {err:#}"));
+                Ok(())
+            }
+        }
+    }
+
+    /// Answer a pending request.
+    pub fn respond(&self, request_id: &str, response: S::Response) -> eyre::Result<()> {
+        if !self.shared.respond(request_id, response)? {
+            warn!(
+                "dropping response for unknown or expired request `{request_id}` on `{}`",
+                self.config.service_name
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Publish TF2 transforms from the dataflow.
+//!
+//! Static transforms, e.g. from the node config, are published on `/tf_static`
+//! with transient local QoS so late joiners like RViz receive them. Dynamic
+//! transforms received on a dora input are published on `/tf`. Both topics use
+//! `tf2_msgs/TFMessage`, like the tf2 broadcasters.
+//!
+//! Both publishers are [`LifecyclePublisher`]s. Static transforms added while a
+//! managed node is not active are kept and published once it is activated, see
+//! [`TfPublisher::publish_pending_static`].
+
+use std::collections::BTreeMap;
+
+use dora_node_api::{
+    ArrowData,
+    arrow::array::{Array, AsArray},
+};
+use eyre::{Context as _, ContextCompat};
+use serde::Deserialize;
+
+use crate::{
+    BridgeNode, Clock, LifecyclePublisher, QosConfig,
+    arrow_convert::ArrowStruct,
+    messages::{
+        builtin_interfaces::msg::Time,
+        geometry_msgs::msg::{Quaternion, Transform, TransformStamped, Vector3},
+        std_msgs::msg::Header,
+        tf2_msgs::msg::TFMessage,
+    },
+    qos::Durability,
+};
+
+/// Topic for dynamic transforms.
+pub const TF_TOPIC: &str = "/tf";
+/// Topic for static transforms.
+pub const TF_STATIC_TOPIC: &str = "/tf_static";
+
+/// A fixed transform from `parent` to `child`.
+///
+/// ```yaml
+/// - parent: base_link
+///   child: camera_frame
+///   translation: [0.1, 0.0, 1.2]
+///   rotation: [0.0, 0.0, 0.0, 1.0]   # quaternion x, y, z, w
+/// - parent: base_link
+///   child: robot_microphone
+///   rotation: [0.0, 0.0, 1.5708]     # roll, pitch, yaw in radians
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticTransform {
+    pub parent: String,
+    pub child: String,
+    /// Translation in meters, zero by default.
+    #[serde(default)]
+    pub translation: [f64; 3],
+    #[serde(default)]
+    pub rotation: Rotation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Rotation {
+    /// Quaternion `[x, y, z, w]`.
+    Quaternion([f64; 4]),
+    /// Fixed axis `[roll, pitch, yaw]` in radians.
+    Rpy([f64; 3]),
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation::Quaternion([0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+impl Rotation {
+    pub fn to_quaternion(self) -> Quaternion {
+        let [x, y, z, w] = match self {
+            Rotation::Quaternion(q) => q,
+            Rotation::Rpy([roll, pitch, yaw]) => {
+                let (sr, cr) = (roll / 2.0).sin_cos();
+                let (sp, cp) = (pitch / 2.0).sin_cos();
+                let (sy, cy) = (yaw / 2.0).sin_cos();
+                [
+                    sr * cp * cy - cr * sp * sy,
+                    cr * sp * cy + sr * cp * sy,
+                    cr * cp * sy - sr * sp * cy,
+                    cr * cp * cy + sr * sp * sy,
+                ]
+            }
+        };
+        Quaternion { x, y, z, w }
+    }
+}
+
+impl StaticTransform {
+    pub fn to_transform_stamped(&self, stamp: Time) -> TransformStamped {
+        let [x, y, z] = self.translation;
+        TransformStamped {
+            header: Header {
+                stamp,
+                frame_id: self.parent.clone(),
+            },
+            child_frame_id: self.child.clone(),
+            transform: Transform {
+                translation: Vector3 { x, y, z },
+                rotation: self.rotation.to_quaternion(),
+            },
+        }
+    }
+}
+
+/// Publishes static transforms on `/tf_static` and dynamic ones on `/tf`.
+pub struct TfPublisher {
+    tf: LifecyclePublisher<TFMessage>,
+    tf_static: LifecyclePublisher<TFMessage>,
+    /// All static transforms added so far, by child frame.
+    static_transforms: BTreeMap<String, TransformStamped>,
+    /// Static transforms were added while the node was not active.
+    static_pending: bool,
+    clock: Clock,
+}
+
+impl TfPublisher {
+    pub fn new(ros_node: &mut BridgeNode) -> eyre::Result<Self> {
+        // same QoS as the tf2 `TransformBroadcaster`/`StaticTransformBroadcaster`
+        let tf_qos = QosConfig::default().keep_last(100);
+        let tf_static_qos = QosConfig::default()
+            .keep_last(1)
+            .durability(Durability::TransientLocal);
+        let lifecycle = ros_node.lifecycle().clone();
+        let tf = create_publisher(ros_node, TF_TOPIC, &tf_qos)?;
+        let tf_static = create_publisher(ros_node, TF_STATIC_TOPIC, &tf_static_qos)?;
+        Ok(Self {
+            tf: LifecyclePublisher::new(tf, &lifecycle),
+            tf_static: LifecyclePublisher::new(tf_static, &lifecycle),
+            static_transforms: BTreeMap::new(),
+            static_pending: false,
+            clock: ros_node.clock().clone(),
+        })
+    }
+
+    /// Add static transforms, replacing earlier ones with the same child frame.
+    ///
+    /// `/tf_static` is latched per publisher, so every call republishes the full set.
+    /// While the node is not active the set is only updated and published on
+    /// activation. Returns whether the set was published.
+    pub fn publish_static(
+        &mut self,
+        transforms: impl IntoIterator<Item = TransformStamped>,
+    ) -> eyre::Result<bool> {
+        for transform in transforms {
+            let transform = self.stamped(transform);
+            self.static_transforms
+                .insert(transform.child_frame_id.clone(), transform);
+        }
+        self.static_pending = true;
+        self.publish_pending_static()
+    }
+
+    /// Publish static transforms from the config, stamped with the current time.
+    pub fn publish_static_config(&mut self, transforms: &[StaticTransform]) -> eyre::Result<bool> {
+        let stamp = self.clock.now();
+        self.publish_static(transforms.iter().map(|t| t.to_transform_stamped(stamp.clone())))
+    }
+
+    /// Publish static transforms that were added while the node was not active.
+    ///
+    /// Call this when a managed node is activated. Returns whether the set was published.
+    pub fn publish_pending_static(&mut self) -> eyre::Result<bool> {
+        if !self.static_pending {
+            return Ok(false);
+        }
+        let message = TFMessage {
+            transforms: self.static_transforms.values().cloned().collect(),
+        };
+        let published = self
+            .tf_static
+            .publish(message)
+            .context("failed to publish static transforms")?;
+        self.static_pending = !published;
+        Ok(published)
+    }
+
+    /// Publish dynamic transforms on `/tf` if the node is active. Transforms
+    /// without stamp get the current time. Returns whether they were published.
+    pub fn publish(&self, transforms: Vec<TransformStamped>) -> eyre::Result<bool> {
+        let message = TFMessage {
+            transforms: transforms.into_iter().map(|t| self.stamped(t)).collect(),
+        };
+        self.tf
+            .publish(message)
+            .context("failed to publish transforms")
+    }
+
+    /// Publish the transforms of a dora input on `/tf`.
+    ///
+    /// Accepts a `tf2_msgs/TFMessage` struct or a `geometry_msgs/TransformStamped`
+    /// struct array with one transform per row. Returns the number of transforms.
+    pub fn handle_input(&self, data: &ArrowData) -> eyre::Result<usize> {
+        let transforms = transforms_from_arrow(data)?;
+        let count = transforms.len();
+        self.publish(transforms)?;
+        Ok(count)
+    }
+
+    /// Add the static transforms of a dora input, see [`handle_input`](Self::handle_input).
+    pub fn handle_static_input(&mut self, data: &ArrowData) -> eyre::Result<usize> {
+        let transforms = transforms_from_arrow(data)?;
+        let count = transforms.len();
+        self.publish_static(transforms)?;
+        Ok(count)
+    }
+
+    fn stamped(&self, mut transform: TransformStamped) -> TransformStamped {
+        if transform.header.stamp.sec == 0 && transform.header.stamp.nanosec == 0 {
+            transform.header.stamp = self.clock.now();
+        }
+        transform
+    }
+}
+
+fn transforms_from_arrow(data: &ArrowData) -> eyre::Result<Vec<TransformStamped>> {
+    let array = data
+        .as_struct_opt()
+        .with_context(|| format!("expected struct array, got {}", data.data_type()))?;
+    if array.column_by_name("transforms").is_some() {
+        let message = TFMessage::try_from(data).context("failed to convert TFMessage")?;
+        Ok(message.transforms)
+    } else {
+        TransformStamped::from_columns(array).context("failed to convert TransformStamped")
+    }
+}
+
+fn create_publisher(
+    ros_node: &mut BridgeNode,
+    topic: &str,
+    qos: &QosConfig,
+) -> eyre::Result<ros2_client::Publisher<TFMessage>> {
+    let topic_name = ros_node.resolve_name(topic)?;
+    let topic = ros_node
+        .node_mut()
+        .create_topic(
+            &topic_name,
+            ros2_client::MessageTypeName::new("tf2_msgs", "TFMessage"),
+            &qos.to_policies(),
+        )
+        .with_context(|| format!("failed to create topic `{topic}`"))?;
+    ros_node
+        .node_mut()
+        .create_publisher(&topic, None)
+        .with_context(|| format!("failed to create publisher for `{topic_name}`"))
+}
@@ -0,0 +1,649 @@
+//! Per-topic rate limiting, decimation and bandwidth control.
+//!
+//! A [`ThrottleConfig`] is given as a string, e.g. in `ROS2_THROTTLE` or the
+//! `throttle` key of a bridged topic:
+//!
+//! ```text
+//! max_rate=10
+//! every_nth=3
+//! bytes_per_second=2MB,burst=4MB
+//! max_rate=5,latest_only
+//! ```
+//!
+//! - `max_rate`: at most this many messages per second (Hz)
+//! - `every_nth`: keep only every Nth message
+//! - `bytes_per_second`: token bucket limiting the bandwidth, with `burst`
+//!   bytes of capacity (one second of bandwidth by default); sizes accept
+//!   `B`, `KB`, `MB`, `GB`, `KiB`, `MiB` and `GiB` suffixes
+//! - `latest_only`: instead of dropping messages over `max_rate` or
+//!   `bytes_per_second`, hold back the newest one and send it as soon as the
+//!   limits allow
+//!
+//! Settings can be combined; a message is forwarded if it passes all of them.
+//! [`Throttle::admit`] decides on messages as they arrive, which is all a
+//! node can do for dora inputs. Holding back messages for `latest_only`
+//! needs a [`Throttle::stream`], as used for ROS2 subscriptions.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eyre::{Context as _, bail, eyre};
+use futures::{
+    Stream, StreamExt,
+    future::{Either, select},
+    stream,
+};
+use futures_timer::Delay;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThrottleConfig {
+    /// Maximum message rate in Hz.
+    pub max_rate: Option<f64>,
+    /// Keep only every Nth message.
+    pub every_nth: Option<u64>,
+    /// Maximum bandwidth in bytes per second.
+    pub bytes_per_second: Option<u64>,
+    /// Token bucket capacity in bytes, defaults to `bytes_per_second`.
+    pub burst: Option<u64>,
+    /// Hold back the newest message instead of dropping it.
+    pub latest_only: bool,
+}
+
+impl ThrottleConfig {
+    /// Read the configuration from the env variable `name`, `None` if it is unset.
+    pub fn from_env(name: &str) -> eyre::Result<Option<Self>> {
+        match std::env::var(name) {
+            Ok(value) => value
+                .parse()
+                .map(Some)
+                .with_context(|| format!("invalid {name}")),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(s)) => {
+                bail!("{name} is not valid unicode: `{}`", s.to_string_lossy())
+            }
+        }
+    }
+
+    pub fn max_rate(mut self, hz: f64) -> Self {
+        self.max_rate = Some(hz);
+        self
+    }
+
+    pub fn every_nth(mut self, n: u64) -> Self {
+        self.every_nth = Some(n);
+        self
+    }
+
+    pub fn bytes_per_second(mut self, bytes: u64) -> Self {
+        self.bytes_per_second = Some(bytes);
+        self
+    }
+
+    pub fn burst(mut self, bytes: u64) -> Self {
+        self.burst = Some(bytes);
+        self
+    }
+
+    pub fn latest_only(mut self) -> Self {
+        self.latest_only = true;
+        self
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        if let Some(rate) = self.max_rate {
+            if !rate.is_finite() || rate <= 0.0 {
+                bail!("max_rate must be a positive number of Hz, got {rate}");
+            }
+        }
+        if self.every_nth == Some(0) {
+            bail!("every_nth must be at least 1");
+        }
+        if self.bytes_per_second == Some(0) {
+            bail!("bytes_per_second must be at least 1");
+        }
+        if self.burst.is_some() && self.bytes_per_second.is_none() {
+            bail!("burst requires bytes_per_second");
+        }
+        if self.burst == Some(0) {
+            bail!("burst must be at least 1 byte");
+        }
+        if self.latest_only && self.max_rate.is_none() && self.bytes_per_second.is_none() {
+            bail!("latest_only requires max_rate or bytes_per_second");
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ThrottleConfig {
+    type Err = eyre::Report;
+
+    /// Parse `key=value[,key=value]*`, see the [module docs](self).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = ThrottleConfig::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = match part.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None if part == "latest_only" => {
+                    config.latest_only = true;
+                    continue;
+                }
+                None => bail!("invalid throttle config `{s}`: unexpected `{part}`"),
+            };
+            match key {
+                "max_rate" => {
+                    let hz = value.strip_suffix("hz").or(value.strip_suffix("Hz"));
+                    config.max_rate = Some(parse(key, hz.unwrap_or(value).trim())?);
+                }
+                "every_nth" => config.every_nth = Some(parse(key, value)?),
+                "bytes_per_second" => config.bytes_per_second = Some(parse_size(value)?),
+                "burst" => config.burst = Some(parse_size(value)?),
+                "latest_only" => config.latest_only = parse(key, value)?,
+                other => bail!("unknown throttle setting `{other}`"),
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl<'de> Deserialize<'de> for ThrottleConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Default, Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        struct Fields {
+            max_rate: Option<f64>,
+            every_nth: Option<u64>,
+            bytes_per_second: Option<Size>,
+            burst: Option<Size>,
+            latest_only: bool,
+        }
+
+        /// Sizes are numbers of bytes or strings with a unit.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Size {
+            Bytes(u64),
+            Text(String),
+        }
+
+        impl Size {
+            fn bytes(self) -> eyre::Result<u64> {
+                match self {
+                    Size::Bytes(b) => Ok(b),
+                    Size::Text(s) => parse_size(&s),
+                }
+            }
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Fields(Fields),
+        }
+
+        fn from_fields(f: Fields) -> eyre::Result<ThrottleConfig> {
+            let config = ThrottleConfig {
+                max_rate: f.max_rate,
+                every_nth: f.every_nth,
+                bytes_per_second: f.bytes_per_second.map(Size::bytes).transpose()?,
+                burst: f.burst.map(Size::bytes).transpose()?,
+                latest_only: f.latest_only,
+            };
+            config.validate()?;
+            Ok(config)
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(s) => s.parse(),
+            Raw::Fields(f) => from_fields(f),
+        }
+        .map_err(|e| serde::de::Error::custom(format!("{e:#}")))
+    }
+}
+
+impl fmt::Display for ThrottleConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(rate) = self.max_rate {
+            parts.push(format!("max_rate={rate}"));
+        }
+        if let Some(n) = self.every_nth {
+            parts.push(format!("every_nth={n}"));
+        }
+        if let Some(bytes) = self.bytes_per_second {
+            parts.push(format!("bytes_per_second={bytes}"));
+        }
+        if let Some(bytes) = self.burst {
+            parts.push(format!("burst={bytes}"));
+        }
+        if self.latest_only {
+            parts.push("latest_only".to_owned());
+        }
+        f.write_str(&parts.join(","))
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> eyre::Result<T>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| eyre!("invalid {key} `{value}`: {e}"))
+}
+
+/// Parse a size like `512`, `64KB` or `2MiB` into bytes.
+pub fn parse_size(value: &str) -> eyre::Result<u64> {
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let scale: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "KB" | "kB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        other => bail!("invalid size `{value}`: unknown unit `{other}`"),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|e| eyre!("invalid size `{value}`: {e}"))?;
+    Ok((number * scale as f64).round() as u64)
+}
+
+type DropHook = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Applies a [`ThrottleConfig`] to the messages of one topic.
+pub struct Throttle {
+    config: ThrottleConfig,
+    received: u64,
+    dropped: u64,
+    last_sent: Option<Instant>,
+    tokens: f64,
+    refilled: Instant,
+    on_drop: Option<DropHook>,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        let mut throttle = Self {
+            config,
+            received: 0,
+            dropped: 0,
+            last_sent: None,
+            tokens: 0.0,
+            refilled: Instant::now(),
+            on_drop: None,
+        };
+        throttle.tokens = throttle.capacity();
+        throttle
+    }
+
+    /// Called with the total number of dropped messages whenever one is dropped,
+    /// e.g. to report them in the diagnostics.
+    pub fn on_drop(mut self, hook: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.on_drop = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn config(&self) -> &ThrottleConfig {
+        &self.config
+    }
+
+    /// Number of messages dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Decide on a message of `bytes` bytes that arrives now.
+    ///
+    /// Returns `false` if the message is dropped. `latest_only` can't hold
+    /// back messages here, so messages over the limits are always dropped.
+    pub fn admit(&mut self, bytes: usize) -> bool {
+        self.admit_at(bytes, Instant::now())
+    }
+
+    /// Like [`admit`](Self::admit), for a message that arrived at `now`.
+    pub fn admit_at(&mut self, bytes: usize, now: Instant) -> bool {
+        if !self.keep_nth() || self.delay(now, bytes).is_some() {
+            self.drop_message();
+            return false;
+        }
+        self.sent(now, bytes);
+        true
+    }
+
+    /// Throttle a stream of messages, holding back the newest one if `latest_only` is set.
+    ///
+    /// `size` returns the size of a message in bytes, or `None` for items that
+    /// bypass the throttle, such as errors.
+    pub fn stream<S>(
+        self,
+        stream: S,
+        size: impl Fn(&S::Item) -> Option<usize>,
+    ) -> impl Stream<Item = S::Item>
+    where
+        S: Stream + Unpin,
+    {
+        let state = (self, stream.fuse(), None, size);
+        stream::unfold(state, |(mut throttle, mut stream, mut pending, size)| async move {
+            loop {
+                let incoming = match pending.take() {
+                    Some((item, bytes)) => {
+                        let now = Instant::now();
+                        let Some(delay) = throttle.delay(now, bytes) else {
+                            throttle.sent(now, bytes);
+                            return Some((item, (throttle, stream, None, size)));
+                        };
+                        if stream.is_done() {
+                            Delay::new(delay).await;
+                            pending = Some((item, bytes));
+                            continue;
+                        }
+                        pending = Some((item, bytes));
+                        match select(stream.next(), Delay::new(delay)).await {
+                            Either::Left((Some(incoming), _)) => Some(incoming),
+                            // send the held back message once the delay is over
+                            Either::Left((None, _)) | Either::Right(((), _)) => continue,
+                        }
+                    }
+                    None => stream.next().await,
+                };
+                // the stream ended without a held back message
+                let item = incoming?;
+                let Some(bytes) = size(&item) else {
+                    return Some((item, (throttle, stream, pending, size)));
+                };
+                if !throttle.keep_nth() {
+                    throttle.drop_message();
+                } else if throttle.config.latest_only {
+                    if pending.replace((item, bytes)).is_some() {
+                        throttle.drop_message();
+                    }
+                } else {
+                    let now = Instant::now();
+                    if throttle.delay(now, bytes).is_none() {
+                        throttle.sent(now, bytes);
+                        return Some((item, (throttle, stream, pending, size)));
+                    }
+                    throttle.drop_message();
+                }
+            }
+        })
+    }
+
+    /// Whether the current message is kept by `every_nth`.
+    fn keep_nth(&mut self) -> bool {
+        let n = self.config.every_nth.unwrap_or(1).max(1);
+        let keep = self.received % n == 0;
+        self.received += 1;
+        keep
+    }
+
+    fn capacity(&self) -> f64 {
+        match self.config.bytes_per_second {
+            Some(rate) => self.config.burst.unwrap_or(rate) as f64,
+            None => 0.0,
+        }
+    }
+
+    /// Time until a message of `bytes` bytes fits into `max_rate` and
+    /// `bytes_per_second`, `None` if it can be sent now.
+    fn delay(&mut self, now: Instant, bytes: usize) -> Option<Duration> {
+        let mut delay = Duration::ZERO;
+        if let (Some(rate), Some(last_sent)) = (self.config.max_rate, self.last_sent) {
+            let next = last_sent + Duration::from_secs_f64(1.0 / rate);
+            delay = delay.max(next.saturating_duration_since(now));
+        }
+        if let Some(rate) = self.config.bytes_per_second {
+            let capacity = self.capacity();
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate as f64).min(capacity);
+            self.refilled = now;
+            // messages larger than the bucket are sent once it is full
+            let needed = (bytes as f64).min(capacity);
+            if self.tokens < needed {
+                delay = delay.max(Duration::from_secs_f64((needed - self.tokens) / rate as f64));
+            }
+        }
+        (!delay.is_zero()).then_some(delay)
+    }
+
+    fn sent(&mut self, now: Instant, bytes: usize) {
+        self.last_sent = Some(now);
+        if self.config.bytes_per_second.is_some() {
+            self.tokens -= bytes as f64;
+        }
+    }
+
+    fn drop_message(&mut self) {
+        self.dropped += 1;
+        if let Some(hook) = &self.on_drop {
+            hook(self.dropped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// Admit messages of `bytes` bytes arriving at the given milliseconds after `start`.
+    fn admitted(throttle: &mut Throttle, start: Instant, arrivals: &[(u64, usize)]) -> Vec<bool> {
+        arrivals
+            .iter()
+            .map(|&(ms, bytes)| throttle.admit_at(bytes, start + MS * ms as u32))
+            .collect()
+    }
+
+    #[test]
+    fn parse_sizes() {
+        let cases = [
+            ("0", 0),
+            ("512", 512),
+            ("512B", 512),
+            ("64KB", 64_000),
+            ("64kB", 64_000),
+            ("2MB", 2_000_000),
+            ("1GB", 1_000_000_000),
+            ("1KiB", 1_024),
+            ("2MiB", 2 * 1_048_576),
+            ("1GiB", 1_073_741_824),
+            ("1.5KB", 1_500),
+            ("0.5MiB", 524_288),
+            ("3 MB", 3_000_000),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_size(value).unwrap(), expected, "`{value}`");
+        }
+
+        let errors = [
+            ("5TB", "unknown unit `TB`"),
+            ("5mb", "unknown unit `mb`"),
+            ("MB", "invalid size `MB`"),
+            ("", "invalid size ``"),
+            ("1.2.3KB", "invalid size `1.2.3KB`"),
+            ("-1", "unknown unit `-1`"),
+        ];
+        for (value, expected) in errors {
+            let err = parse_size(value).unwrap_err();
+            assert!(format!("{err}").contains(expected), "`{value}`: {err}");
+        }
+    }
+
+    #[test]
+    fn parse_configs() {
+        let cases = [
+            ("max_rate=10", ThrottleConfig::default().max_rate(10.0)),
+            ("max_rate=2.5Hz", ThrottleConfig::default().max_rate(2.5)),
+            ("every_nth=3", ThrottleConfig::default().every_nth(3)),
+            (
+                "bytes_per_second=2MB, burst=4MiB",
+                ThrottleConfig::default()
+                    .bytes_per_second(2_000_000)
+                    .burst(4 << 20),
+            ),
+            (
+                "max_rate=5,latest_only",
+                ThrottleConfig::default().max_rate(5.0).latest_only(),
+            ),
+            (
+                "bytes_per_second=100,latest_only=true",
+                ThrottleConfig::default().bytes_per_second(100).latest_only(),
+            ),
+        ];
+        for (text, expected) in cases {
+            let config: ThrottleConfig = text.parse().unwrap();
+            assert_eq!(config, expected, "`{text}`");
+            assert_eq!(config.to_string().parse::<ThrottleConfig>().unwrap(), config);
+        }
+
+        let yaml: ThrottleConfig =
+            serde_yaml::from_str("{bytes_per_second: 1KiB, burst: 4096}").unwrap();
+        assert_eq!(yaml, ThrottleConfig::default().bytes_per_second(1024).burst(4096));
+
+        let errors = [
+            ("max_rate=0", "max_rate must be a positive number"),
+            ("max_rate=fast", "invalid max_rate `fast`"),
+            ("every_nth=0", "every_nth must be at least 1"),
+            ("burst=1MB", "burst requires bytes_per_second"),
+            ("latest_only", "latest_only requires max_rate or bytes_per_second"),
+            ("min_rate=1", "unknown throttle setting `min_rate`"),
+            ("max_rate", "unexpected `max_rate`"),
+        ];
+        for (text, expected) in errors {
+            let err = text.parse::<ThrottleConfig>().unwrap_err();
+            assert!(format!("{err}").contains(expected), "`{text}`: {err}");
+        }
+    }
+
+    #[test]
+    fn max_rate() {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let reported = dropped.clone();
+        let mut throttle = Throttle::new(ThrottleConfig::default().max_rate(10.0))
+            .on_drop(move |n| reported.store(n, Ordering::Relaxed));
+        let start = Instant::now();
+        let arrivals = [(0, 1), (50, 1), (99, 1), (100, 1), (150, 1), (250, 1), (260, 1)];
+        assert_eq!(
+            admitted(&mut throttle, start, &arrivals),
+            [true, false, false, true, false, true, false]
+        );
+        assert_eq!(throttle.dropped(), 4);
+        assert_eq!(dropped.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn every_nth() {
+        let mut throttle = Throttle::new(ThrottleConfig::default().every_nth(3));
+        let start = Instant::now();
+        let arrivals: Vec<_> = (0..7).map(|i| (i, 1)).collect();
+        assert_eq!(
+            admitted(&mut throttle, start, &arrivals),
+            [true, false, false, true, false, false, true]
+        );
+        assert_eq!(throttle.dropped(), 4);
+
+        // decimation counts all messages, also the ones dropped by the rate
+        let config = ThrottleConfig::default().every_nth(2).max_rate(10.0);
+        let mut throttle = Throttle::new(config);
+        let arrivals = [(0, 1), (10, 1), (20, 1), (110, 1), (120, 1), (130, 1)];
+        assert_eq!(
+            admitted(&mut throttle, start, &arrivals),
+            [true, false, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn token_bucket() {
+        let mut throttle = Throttle::new(ThrottleConfig::default().bytes_per_second(1_000));
+        let start = Instant::now();
+        let arrivals = [
+            // the bucket starts full
+            (0, 600),
+            (0, 600),
+            (0, 400),
+            // refilled by 250 bytes
+            (250, 300),
+            (250, 250),
+            // a message larger than the bucket is sent once it is full, going into debt
+            (1_500, 5_000),
+            (2_500, 1),
+            (6_500, 1_000),
+        ];
+        assert_eq!(
+            admitted(&mut throttle, start, &arrivals),
+            [true, false, true, false, true, true, false, true]
+        );
+        assert_eq!(throttle.dropped(), 3);
+
+        // `burst` limits how much is saved up
+        let config = ThrottleConfig::default().bytes_per_second(1_000).burst(100);
+        let mut throttle = Throttle::new(config);
+        let arrivals = [(0, 100), (0, 1), (10_000, 200), (10_000, 1), (10_200, 100)];
+        assert_eq!(
+            admitted(&mut throttle, start, &arrivals),
+            [true, false, true, false, true]
+        );
+    }
+
+    #[test]
+    fn latest_only_delay() {
+        // `admit` can't hold back messages, so `latest_only` drops like without it
+        let mut throttle = Throttle::new(ThrottleConfig::default().max_rate(10.0).latest_only());
+        let start = Instant::now();
+        assert_eq!(
+            admitted(&mut throttle, start, &[(0, 1), (30, 1), (100, 1)]),
+            [true, false, true]
+        );
+
+        // the held back message is released when the rate allows
+        let mut throttle = Throttle::new(ThrottleConfig::default().max_rate(10.0).latest_only());
+        assert_eq!(throttle.delay(start, 1), None);
+        throttle.sent(start, 1);
+        assert_eq!(throttle.delay(start + MS * 30, 1), Some(MS * 70));
+        assert_eq!(throttle.delay(start + MS * 100, 1), None);
+
+        let config = ThrottleConfig::default().bytes_per_second(1_000).latest_only();
+        let mut throttle = Throttle::new(config);
+        throttle.sent(start, 1_000);
+        assert_eq!(throttle.delay(start, 500), Some(MS * 500));
+        // large messages wait for a full bucket, not for their own size
+        assert_eq!(throttle.delay(start, 5_000), Some(MS * 1_000));
+    }
+
+    #[test]
+    fn latest_only_stream() {
+        let config = ThrottleConfig::default().max_rate(50.0).latest_only();
+        let throttle = Throttle::new(config);
+        // `None` items bypass the throttle, like errors of a subscription
+        let items = stream::iter([Some(1), Some(2), None, Some(3), Some(4)]);
+        let start = Instant::now();
+        let sent: Vec<_> = futures::executor::block_on(
+            throttle.stream(items, |item| item.map(|_| 1)).collect(),
+        );
+        // the first message is sent right away, the newest one after the rate period
+        assert_eq!(sent, [Some(1), None, Some(4)]);
+        assert!(start.elapsed() >= MS * 20);
+
+        let config = ThrottleConfig::default().max_rate(50.0);
+        let items = stream::iter([Some(1), Some(2), Some(3)]);
+        let sent: Vec<_> = futures::executor::block_on(
+            Throttle::new(config).stream(items, |item| item.map(|_| 1)).collect(),
+        );
+        assert_eq!(sent, [Some(1)]);
+    }
+}
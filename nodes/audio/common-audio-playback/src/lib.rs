@@ -3,6 +3,7 @@ use cpal::SampleFormat;
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, error, info};
 
 /// Audio playback manager that handles continuous audio streaming
 pub struct AudioPlayback {
@@ -31,10 +32,10 @@ impl AudioPlayback {
         let device = host.default_output_device()
             .ok_or_else(|| eyre::eyre!("No output device available"))?;
         
-        println!("Using audio device: {}", device.name()?);
+        info!("using audio device: {}", device.name()?);
         
         let config = device.default_output_config()?;
-        println!("Default config: {:?}", config);
+        debug!("default config: {config:?}");
         
         let sample_rate = 48000;
         let _channels = 1; // Mono audio
@@ -103,12 +104,12 @@ impl AudioPlayback {
                     buffer_guard.pop_front();
                 }
             },
-            |err| eprintln!("Audio playback error: {}", err),
+            |err| error!("audio playback error: {err}"),
             None,
         )?;
         
         stream.play()?;
-        println!("Audio playback started");
+        info!("audio playback started");
         
         self.stream = Some(stream);
         Ok(())
@@ -143,10 +144,10 @@ pub fn run_audio_playback_thread(
     let device = host.default_output_device()
         .ok_or_else(|| eyre::eyre!("No output device available"))?;
     
-    println!("Using audio device: {}", device.name()?);
+    info!("using audio device: {}", device.name()?);
     
     let supported = device.default_output_config()?;
-    println!("Default config: {:?}", supported);
+    debug!("default config: {supported:?}");
     
     let device_rate_hz: u32 = supported.sample_rate().0;
     let config = cpal::StreamConfig {
@@ -227,7 +228,7 @@ pub fn run_audio_playback_thread(
                 let max_buffer_samples = device_rate_hz as usize * 5;
                 while buffer_guard.len() > max_buffer_samples { buffer_guard.pop_front(); }
             },
-            |err| eprintln!("Audio playback error: {}", err),
+            |err| error!("audio playback error: {err}"),
             None,
         )?)
     };
@@ -275,7 +276,7 @@ pub fn run_audio_playback_thread(
                 let max_buffer_samples = device_rate_hz as usize * 5;
                 while buffer_guard.len() > max_buffer_samples { buffer_guard.pop_front(); }
             },
-            |err| eprintln!("Audio playback error: {}", err),
+            |err| error!("audio playback error: {err}"),
             None,
         )?)
     };
@@ -288,13 +289,13 @@ pub fn run_audio_playback_thread(
     };
     
     stream.play()?;
-    println!("Audio playback started");
+    info!("audio playback started");
     
     // Keep the playback running with proper shutdown handling
     while !shutdown_signal.load(Ordering::Relaxed) {
         std::thread::sleep(std::time::Duration::from_millis(100));
     }
     
-    println!("Audio playback thread shutting down");
+    info!("audio playback thread shutting down");
     Ok(())
 }
@@ -1,4 +1,8 @@
 use dora_node_api::{self, DoraNode, Event, Parameter};
+use dora_ros2_bridge::{
+    BridgeNode, Diagnostics, DiagnosticsConfig,
+    tracing::{debug, error, info, warn},
+};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
@@ -6,6 +10,9 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use common_audio_playback::run_audio_playback_thread;
 
+/// Max converted packets kept for playback
+const PLAYBACK_QUEUE_CAP: usize = 100;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct AudioDebugInfo {
     timestamp: std::time::SystemTime,
@@ -28,7 +35,13 @@ struct AudioDataStats {
 }
 
 fn main() -> eyre::Result<()> {
-    println!("Starting Dora audio sink debug node with audio playback");
+    // --- ROS 2 setup: only used for /rosout and /diagnostics -------------------------------
+    let mut ros_node = BridgeNode::builder("dora_audio_sink").build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting Dora audio sink debug node with audio playback");
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    let audio_diagnostics = diagnostics.channel("audio");
+    let playback_diagnostics = diagnostics.channel("playback");
 
     // Configuration
     let enable_debug = std::env::var("ENABLE_DEBUG")
@@ -68,7 +81,7 @@ fn main() -> eyre::Result<()> {
     // Set up signal handler for graceful shutdown
     let shutdown_signal_for_handler = shutdown_signal.clone();
     ctrlc::set_handler(move || {
-        println!("Received interrupt signal, shutting down gracefully...");
+        info!("received interrupt signal, shutting down gracefully");
         shutdown_signal_for_handler.store(true, Ordering::Relaxed);
         std::process::exit(0);
     }).expect("Error setting Ctrl-C handler");
@@ -77,12 +90,12 @@ fn main() -> eyre::Result<()> {
     let (_node, dora_events) = DoraNode::init_from_env()?;
     let mut events = futures::executor::block_on_stream(dora_events);
 
-    println!("Dora audio sink debug node ready. Listening for audio data...");
+    info!("Dora audio sink debug node ready, listening for audio data");
 
     while let Some(event) = events.next() {
         // Check if shutdown signal is set
         if shutdown_signal.load(Ordering::Relaxed) {
-            println!("Shutdown signal received, stopping...");
+            info!("shutdown signal received, stopping");
             break;
         }
 
@@ -96,6 +109,9 @@ fn main() -> eyre::Result<()> {
                         .map_err(|e| eyre::eyre!("Failed to convert data to bytes: {e}"))?;
                     
                     total_bytes += audio_data.len();
+                    audio_diagnostics.message(audio_data.len());
+                    audio_diagnostics.value("total_packets", total_packets);
+                    audio_diagnostics.value("total_bytes", total_bytes);
                     
                     // Extract metadata
                     let sample_rate = metadata.parameters.get("sample_rate")
@@ -126,10 +142,10 @@ fn main() -> eyre::Result<()> {
                         let sr_hz: u32 = sample_rate.parse().unwrap_or(48000);
                         let queue_clone = audio_queue.clone();
                         let shutdown_signal_clone2 = shutdown_signal_clone.clone();
-                        println!("Starting audio playback thread at {} Hz", sr_hz);
+                        info!("starting audio playback thread at {sr_hz} Hz");
                         audio_thread_handle = Some(thread::spawn(move || {
                             if let Err(e) = run_audio_playback_thread(queue_clone, shutdown_signal_clone2, sr_hz) {
-                                eprintln!("Audio playback error: {}", e);
+                                error!("audio playback error: {e:?}");
                             }
                         }));
                         playback_started = true;
@@ -154,9 +170,14 @@ fn main() -> eyre::Result<()> {
                     if enable_playback {
                         let channels_num: usize = channels.parse().unwrap_or(1);
                         let converted = to_s16le_mono(&audio_data, &format, channels_num);
+                        playback_diagnostics.message(converted.len());
                         if let Ok(mut guard) = audio_queue.lock() {
                             guard.push_back(converted);
-                            while guard.len() > 100 { guard.pop_front(); }
+                            while guard.len() > PLAYBACK_QUEUE_CAP {
+                                guard.pop_front();
+                                playback_diagnostics.dropped(1);
+                            }
+                            playback_diagnostics.queue(guard.len(), PLAYBACK_QUEUE_CAP);
                         }
                     }
                     
@@ -168,18 +189,24 @@ fn main() -> eyre::Result<()> {
                         }
                         debug_history.push_back(debug_info.clone());
                         
-                        // Print detailed debug information
-                        println!("=== Audio Packet #{} ===", total_packets);
-                        println!("Timestamp: {:?}", debug_info.timestamp);
-                        println!("Data length: {} bytes", debug_info.data_length);
-                        println!("Sample rate: {}Hz", debug_info.sample_rate);
-                        println!("Channels: {}", debug_info.channels);
-                        println!("Format: {}", debug_info.format);
-                        println!("First 16 bytes: {:02x?}", debug_info.first_bytes);
-                        println!("Last 16 bytes: {:02x?}", debug_info.last_bytes);
-                        println!("Data stats: {:?}", debug_info.data_stats);
-                        println!("Total packets: {}, Total bytes: {}", total_packets, total_bytes);
-                        println!("Average packet size: {:.2} bytes", total_bytes as f64 / total_packets as f64);
+                        // Log detailed debug information
+                        debug!(
+                            "audio packet #{total_packets}: {} bytes, {}Hz, {} channels, {}",
+                            debug_info.data_length,
+                            debug_info.sample_rate,
+                            debug_info.channels,
+                            debug_info.format,
+                        );
+                        debug!(
+                            "first 16 bytes: {:02x?}, last 16 bytes: {:02x?}",
+                            debug_info.first_bytes, debug_info.last_bytes,
+                        );
+                        debug!("data stats: {:?}", debug_info.data_stats);
+                        debug!(
+                            "total packets: {total_packets}, total bytes: {total_bytes}, \
+                             average packet size: {:.2} bytes",
+                            total_bytes as f64 / total_packets as f64
+                        );
                         
                         // Calculate expected vs actual sample rate
                         let bytes_per_sample = match debug_info.format.as_str() {
@@ -203,50 +230,52 @@ fn main() -> eyre::Result<()> {
                             expected_sample_rate
                         };
                         
-                        println!("Samples per packet: {}", samples_per_packet);
-                        println!("Expected sample rate: {:.0}Hz", expected_sample_rate);
-                        println!("Calculated sample rate: {:.0}Hz", actual_sample_rate);
-                        println!("Sample rate difference: {:.0}Hz", (actual_sample_rate - expected_sample_rate).abs());
-                        println!("");
+                        debug!(
+                            "samples per packet: {samples_per_packet}, expected sample rate: \
+                             {expected_sample_rate:.0}Hz, calculated sample rate: \
+                             {actual_sample_rate:.0}Hz, difference: {:.0}Hz",
+                            (actual_sample_rate - expected_sample_rate).abs()
+                        );
                         
                         // Save debug info to file periodically
                         if total_packets % 10 == 0 {
                             if let Ok(json) = serde_json::to_string_pretty(&debug_history) {
                                 if let Err(e) = std::fs::write(&debug_file, json) {
-                                    eprintln!("Failed to write debug file: {}", e);
+                                    warn!("failed to write debug file: {e}");
+                                    audio_diagnostics.error(format!("failed to write debug file: {e}"));
                                 } else {
-                                    println!("Debug info saved to {}", debug_file);
+                                    debug!("debug info saved to {debug_file}");
                                 }
                             }
                         }
                     } else {
                         // Simple status output for production
                         if total_packets % 100 == 0 {
-                            println!("Audio sink: Received {} packets, {} total bytes", total_packets, total_bytes);
+                            info!("received {total_packets} packets, {total_bytes} total bytes");
                         }
                     }
                 }
-                other => eprintln!("Ignoring unexpected input `{other}`"),
+                other => warn!("Ignoring unexpected input `{other}`"),
             },
             Event::Stop(_) => {
-                println!("Received stop signal");
+                info!("received stop signal");
                 break;
             }
-            other => eprintln!("Received unexpected input: {other:?}"),
+            other => warn!("Received unexpected input: {other:?}"),
         }
     }
 
     // Cleanup
-    println!("Cleaning up Dora audio sink...");
+    info!("cleaning up Dora audio sink");
     
     // Signal audio playback thread to stop
     shutdown_signal.store(true, Ordering::Relaxed);
     
     // Wait for audio playback thread to finish
     if let Some(handle) = audio_thread_handle {
-        println!("Waiting for audio playback thread to finish...");
+        info!("waiting for audio playback thread to finish");
         if let Err(e) = handle.join() {
-            eprintln!("Error joining audio playback thread: {:?}", e);
+            error!("error joining audio playback thread: {e:?}");
         }
     }
 
@@ -254,14 +283,15 @@ fn main() -> eyre::Result<()> {
     if enable_debug && !debug_history.is_empty() {
         if let Ok(json) = serde_json::to_string_pretty(&debug_history) {
             if let Err(e) = std::fs::write(&debug_file, json) {
-                eprintln!("Failed to write final debug file: {}", e);
+                warn!("failed to write final debug file: {e}");
             } else {
-                println!("Final debug info saved to {}", debug_file);
+                info!("final debug info saved to {debug_file}");
             }
         }
     }
 
-    println!("Dora audio sink debug node stopped");
+    drop(ros_node);
+    info!("Dora audio sink debug node stopped");
     Ok(())
 }
 
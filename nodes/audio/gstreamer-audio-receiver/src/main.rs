@@ -10,9 +10,21 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use signal_hook::{consts::SIGTERM, iterator::Signals};
 use common_audio_playback::run_audio_playback_thread;
+use dora_ros2_bridge::{
+    BridgeNode, Diagnostics, DiagnosticsConfig,
+    tracing::{debug, error, info, warn},
+};
+
+/// Max buffers kept between ticks
+const PENDING_AUDIO_CAP: usize = 200;
+/// Max buffers kept for local playback
+const PLAYBACK_QUEUE_CAP: usize = 10;
 
 fn main() -> eyre::Result<()> {
-    println!("starting GStreamer audio receiver node");
+    // --- ROS 2 setup: only used for /rosout and /diagnostics -------------------------------
+    let mut ros_node = BridgeNode::builder("gstreamer_audio_receiver").build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting GStreamer audio receiver node");
 
     // Initialize GStreamer
     gst::init()?;
@@ -35,8 +47,18 @@ fn main() -> eyre::Result<()> {
     let force_format = std::env::var("AUDIO_FORCE_FORMAT").unwrap_or_else(|_| "S16LE".to_string());
     let pipeline_override = std::env::var("AUDIO_PIPELINE_OVERRIDE").ok();
     
-    println!("Listening on UDP port: {}", udp_port);
-    println!("Audio format: {}Hz, {} channels, encoding {} payload {}", sample_rate, channels, encoding_name, payload);
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    let udp_diagnostics = diagnostics.channel("udp_audio");
+    udp_diagnostics.value("udp_port", &udp_port);
+    udp_diagnostics.value("appsink_max_buffers", &appsink_max_buffers);
+    let playback_diagnostics = diagnostics.channel("playback");
+    let output_diagnostics = diagnostics.channel("audio");
+
+    info!("listening on UDP port {udp_port}");
+    info!(
+        "audio format: {sample_rate}Hz, {channels} channels, encoding {encoding_name} \
+         payload {payload}"
+    );
 
     // Store pending audio buffers to avoid dropping between ticks
     let pending_audio: Arc<Mutex<VecDeque<Vec<u8>>>> = Arc::new(Mutex::new(VecDeque::new()));
@@ -60,14 +82,14 @@ fn main() -> eyre::Result<()> {
     };
     
     let audio_thread_handle = if enable_playback {
-        println!("Initializing audio playback...");
+        info!("initializing audio playback");
         let queue_clone = audio_queue_for_playback.as_ref().unwrap().clone();
         let input_rate_hz: u32 = sample_rate.parse().unwrap_or(48000);
         
         // Start audio playback thread using common component
         Some(thread::spawn(move || {
             if let Err(e) = run_audio_playback_thread(queue_clone, shutdown_signal_clone, input_rate_hz) {
-                eprintln!("Audio playback error: {}", e);
+                error!("audio playback error: {e:?}");
             }
         }))
     } else {
@@ -76,7 +98,7 @@ fn main() -> eyre::Result<()> {
 
     // Create GStreamer pipeline for receiving audio
     let pipeline_str = if let Some(override_str) = pipeline_override.clone() {
-        println!("Using AUDIO_PIPELINE_OVERRIDE");
+        info!("using AUDIO_PIPELINE_OVERRIDE");
         override_str
     } else {
         // Choose depay/decode chain based on encoding name (support common cases)
@@ -89,7 +111,7 @@ fn main() -> eyre::Result<()> {
             "PCMA" => "rtppcmadepay ! alawdec".to_string(),
             "PCMU" => "rtppcmudepay ! mulawdec".to_string(),
             other => {
-                eprintln!("Unknown AUDIO_ENCODING_NAME '{}', defaulting to rtpL16depay", other);
+                warn!("unknown AUDIO_ENCODING_NAME '{other}', defaulting to rtpL16depay");
                 "rtpL16depay".to_string()
             }
         };
@@ -112,7 +134,7 @@ fn main() -> eyre::Result<()> {
         )
     };
 
-    println!("Creating GStreamer pipeline: {}", pipeline_str);
+    info!("creating GStreamer pipeline: {pipeline_str}");
 
     let pipeline = gst::parse_launch(&pipeline_str)
         .context("Failed to create GStreamer pipeline")?;
@@ -133,6 +155,7 @@ fn main() -> eyre::Result<()> {
 
     // Clone audio queue for callback - use the same queue as the playback thread
     let audio_queue_clone = audio_queue_for_playback.clone();
+    let udp_diagnostics_clone = udp_diagnostics.clone();
 
     // Set up appsink callbacks
     let appsink = appsink.dynamic_cast::<gst_app::AppSink>().unwrap();
@@ -149,7 +172,7 @@ fn main() -> eyre::Result<()> {
                             if let Ok(mut guard) = actual_sample_rate_clone.lock() {
                                 *guard = Some(rate.to_string());
                             }
-                            println!("GStreamer detected sample rate: {}Hz", rate);
+                            debug!("GStreamer detected sample rate: {rate}Hz");
                         }
                     }
                 }
@@ -157,28 +180,34 @@ fn main() -> eyre::Result<()> {
                 let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
                 let audio_data = map.as_slice().to_vec();
                 let audio_len = audio_data.len();
+                udp_diagnostics_clone.message(audio_len);
                 
                 // Queue the audio data
                 if let Ok(mut guard) = pending_audio_clone.lock() {
                     guard.push_back(audio_data.clone());
                     // Cap queue size to avoid unbounded growth (keep ~200 buffers)
-                    while guard.len() > 200 {
+                    while guard.len() > PENDING_AUDIO_CAP {
                         guard.pop_front();
+                        udp_diagnostics_clone.dropped(1);
                     }
+                    udp_diagnostics_clone.queue(guard.len(), PENDING_AUDIO_CAP);
                 }
                 
                 // Send audio data to playback queue
                 if let Some(ref queue) = audio_queue_clone {
                     if let Ok(mut guard) = queue.lock() {
                         guard.push_back(audio_data);
+                        playback_diagnostics.message(audio_len);
                         // Keep queue size reasonable (max 10 packets)
-                        while guard.len() > 10 {
+                        while guard.len() > PLAYBACK_QUEUE_CAP {
                             guard.pop_front();
+                            playback_diagnostics.dropped(1);
                         }
+                        playback_diagnostics.queue(guard.len(), PLAYBACK_QUEUE_CAP);
                     }
                 }
                 
-                println!("Received audio data: {} bytes", audio_len);
+                debug!("received audio data: {audio_len} bytes");
                 Ok(gst::FlowSuccess::Ok)
             })
             .build(),
@@ -194,12 +223,13 @@ fn main() -> eyre::Result<()> {
     // Background task to handle GStreamer messages
     let pipeline_clone = pipeline.clone();
     let shutdown_signal_for_gst = shutdown_signal.clone();
+    let gst_diagnostics = udp_diagnostics.clone();
     pool.spawn(async move {
         let bus = pipeline_clone.bus().unwrap();
         loop {
             // Check shutdown signal before processing messages
             if shutdown_signal_for_gst.load(Ordering::Relaxed) {
-                println!("GStreamer message handler shutting down");
+                debug!("GStreamer message handler shutting down");
                 break;
             }
             
@@ -207,21 +237,22 @@ fn main() -> eyre::Result<()> {
             if let Some(msg) = bus.timed_pop(gst::ClockTime::from_mseconds(100)) {
                 match msg.view() {
                     gst::MessageView::Error(err) => {
-                        eprintln!("GStreamer error: {:?}", err);
+                        error!("GStreamer error: {err:?}");
+                        gst_diagnostics.error(format!("GStreamer error: {:?}", err.error()));
                         break;
                     }
                     gst::MessageView::Eos(..) => {
-                        println!("GStreamer pipeline reached end of stream");
+                        info!("GStreamer pipeline reached end of stream");
                         break;
                     }
                     gst::MessageView::StateChanged(state) => {
-                        println!("GStreamer state changed: {:?}", state);
+                        debug!("GStreamer state changed: {state:?}");
                     }
                     _ => {}
                 }
             }
         }
-        println!("GStreamer message handler thread exiting");
+        debug!("GStreamer message handler thread exiting");
     }).context("failed to spawn GStreamer message handler")?;
 
     // Set up signal handlers for graceful shutdown (SIGINT and SIGTERM)
@@ -229,7 +260,7 @@ fn main() -> eyre::Result<()> {
     
     // Handle SIGINT (Ctrl+C)
     ctrlc::set_handler(move || {
-        println!("Received SIGINT (Ctrl+C), shutting down gracefully...");
+        info!("received SIGINT (Ctrl+C), shutting down gracefully");
         shutdown_signal_for_handler.store(true, Ordering::Relaxed);
     }).expect("Error setting SIGINT handler");
     
@@ -242,7 +273,7 @@ fn main() -> eyre::Result<()> {
         loop {
             // Check if we should exit due to other shutdown signal (like SIGINT)
             if shutdown_signal_check.load(Ordering::Relaxed) {
-                println!("Signal handler thread detected shutdown signal, exiting...");
+                debug!("signal handler thread detected shutdown signal, exiting");
                 break;
             }
             
@@ -250,7 +281,7 @@ fn main() -> eyre::Result<()> {
             if let Some(sig) = signals.pending().next() {
                 match sig {
                     SIGTERM => {
-                        println!("Received SIGTERM, shutting down gracefully...");
+                        info!("received SIGTERM, shutting down gracefully");
                         shutdown_signal_for_sigterm.store(true, Ordering::Relaxed);
                         break;
                     }
@@ -261,7 +292,7 @@ fn main() -> eyre::Result<()> {
             // Small delay to avoid busy waiting
             std::thread::sleep(std::time::Duration::from_millis(100));
         }
-        println!("Signal handler thread exiting");
+        debug!("signal handler thread exiting");
     });
 
     // --- Dora: init and process events ------------------------------------------------------
@@ -272,7 +303,7 @@ fn main() -> eyre::Result<()> {
     while let Some(event) = events.next() {
         // Check if shutdown signal is set
         if shutdown_signal.load(Ordering::Relaxed) {
-            println!("Shutdown signal received, stopping...");
+            info!("shutdown signal received, stopping");
             break;
         }
 
@@ -283,6 +314,7 @@ fn main() -> eyre::Result<()> {
                     let mut combined: Option<Vec<u8>> = None;
                     {
                         if let Ok(mut q) = pending_audio.lock() {
+                            udp_diagnostics.queue(0, PENDING_AUDIO_CAP);
                             if !q.is_empty() {
                                 let total_len: usize = q.iter().map(|b| b.len()).sum();
                                 let mut buf = Vec::with_capacity(total_len);
@@ -308,38 +340,42 @@ fn main() -> eyre::Result<()> {
                         params.insert("format".into(), Parameter::String(force_format.clone()));
 
                         // Send audio data as Arrow BinaryArray
-                        println!("sending audio data: {} bytes with sample rate: {}Hz", audio_data.len(), actual_rate);
+                        debug!(
+                            "sending audio data: {} bytes with sample rate: {actual_rate}Hz",
+                            audio_data.len()
+                        );
+                        output_diagnostics.message(audio_data.len());
                         node.send_output(output.clone(), params, audio_data.into_arrow())?;
                     } else {
                         // No audio received yet; ignore this tick
                     }
                 }
-                other => eprintln!("Ignoring unexpected input `{other}`"),
+                other => warn!("Ignoring unexpected input `{other}`"),
             },
             Event::Stop(_) => {
-                println!("Received stop");
+                info!("received stop");
                 break;
             }
-            other => eprintln!("Received unexpected input: {other:?}"),
+            other => warn!("Received unexpected input: {other:?}"),
         }
     }
 
     // Cleanup
-    println!("Cleaning up GStreamer audio receiver...");
+    info!("cleaning up GStreamer audio receiver");
     
     // Signal audio playback thread to stop
     shutdown_signal.store(true, Ordering::Relaxed);
     
     // Wait for audio playback thread to finish
     if let Some(handle) = audio_thread_handle {
-        println!("Waiting for audio playback thread to finish...");
+        debug!("waiting for audio playback thread to finish");
         if let Err(e) = handle.join() {
-            eprintln!("Error joining audio playback thread: {:?}", e);
+            error!("error joining audio playback thread: {e:?}");
         }
     }
     
     // Stop GStreamer pipeline
-    println!("Stopping GStreamer pipeline...");
+    debug!("stopping GStreamer pipeline");
     pipeline.set_state(gst::State::Null)
         .context("Failed to stop GStreamer pipeline")?;
     
@@ -347,17 +383,18 @@ fn main() -> eyre::Result<()> {
     let _ = pipeline.state(gst::ClockTime::from_seconds(5));
     
     // Explicitly shutdown the ThreadPool
-    println!("Shutting down ThreadPool...");
+    debug!("shutting down ThreadPool");
     drop(pool);
     
     // Wait for signal handler thread to finish (with timeout)
-    println!("Waiting for signal handler thread to finish...");
+    debug!("waiting for signal handler thread to finish");
     let signal_join_result = signal_thread_handle.join();
     if let Err(e) = signal_join_result {
-        eprintln!("Error joining signal handler thread: {:?}", e);
+        error!("error joining signal handler thread: {e:?}");
     }
     
-    println!("GStreamer audio receiver cleanup complete");
+    drop(ros_node);
+    info!("GStreamer audio receiver cleanup complete");
 
     Ok(())
 }
@@ -1,46 +1,88 @@
-use dora_node_api::{self, DoraNode, Event, MetadataParameters, Parameter};
+use std::sync::Arc;
+
+use dora_node_api::{
+    self, Event, Parameter,
+    arrow::{array::Array, datatypes::DataType},
+};
 use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, Diagnostics, DiagnosticsConfig, HeaderPolicy, LifecyclePublisher,
+    ParameterValue, QosConfig, QosEventMonitor,
+    header::receive_time_from_metadata,
     messages::audio_common_msgs::msg::AudioStamped as Ros2AudioStamped,
     messages::audio_common_msgs::msg::{Audio as Ros2Audio, AudioData as Ros2AudioData, AudioInfo as Ros2AudioInfo},
-    messages::std_msgs::msg::Header as Ros2Header,
-    ros2_client::{self, NodeOptions, ros2},
-    rustdds::{self, policy},
+    ros2_client,
+    tracing::{debug, info, warn},
 };
-use eyre::{Context, eyre};
-use futures::{StreamExt, task::SpawnExt};
+use eyre::Context;
 
 fn main() -> eyre::Result<()> {
-    println!("starting ROS2 audio publisher node with audio_common_msgs/AudioStamped");
-
     // Get audio topic from environment variable
     let audio_topic = std::env::var("ROS2_AUDIO_TOPIC").unwrap_or_else(|_| "/robot/audio".to_string());
-    println!("Publishing to audio topic: {}", audio_topic);
-
-    // --- ROS 2 setup: node + publisher --------------------------------------------
-    let mut ros_node = init_ros_node()?;
-    let audio_publisher = create_audio_publisher(&mut ros_node, &audio_topic)?;
+    let frame_id = std::env::var("ROS2_FRAME_ID").unwrap_or_else(|_| "robot_microphone".to_string());
+    let header_policy = HeaderPolicy::from_env("ROS2_AUDIO_HEADER")?;
+    let volume: f64 = match std::env::var("AUDIO_VOLUME") {
+        Ok(volume) => volume.parse().context("invalid AUDIO_VOLUME")?,
+        Err(_) => 1.0,
+    };
+    let topic_qos = QosConfig::from_env_or("ROS2_AUDIO_QOS", QosConfig::default())?;
 
-    // background spinner (service discovery, executor, etc.)
-    let pool = futures::executor::ThreadPool::new()?;
-    let spinner = ros_node
-        .spinner()
-        .map_err(|e| eyre::eyre!("failed to create spinner: {e:?}"))?;
-    pool.spawn(async {
-        if let Err(err) = spinner.spin().await {
-            eprintln!("ros2 spinner failed: {err:?}");
-        }
-    })
-        .context("failed to spawn ros2 spinner")?;
+    // --- ROS 2 setup: node + publisher + spinner --------------------------------------------
+    let mut ros_node = BridgeNode::builder("ros2_audio_publisher")
+        .read_only_parameter("topic", ParameterValue::String(audio_topic.clone()))
+        .read_only_parameter(
+            "qos_depth",
+            ParameterValue::Integer(topic_qos.depth.unwrap_or(10).into()),
+        )
+        .parameter("frame_id", ParameterValue::String(frame_id.clone()))
+        .parameter("volume", ParameterValue::Double(volume))
+        .build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting ROS2 audio publisher node with audio_common_msgs/AudioStamped");
+    info!("publishing to audio topic {audio_topic}");
+    let parameters = ros_node.parameters().clone();
+    let clock = ros_node.clock().clone();
+    let topic_name = ros_node.resolve_name(&audio_topic)?;
+    let audio_publisher =
+        Arc::new(create_audio_publisher(ros_node.node_mut(), &topic_name, &topic_qos)?);
+    // logs QoS problems, and sends them on ROS2_QOS_EVENTS_OUTPUT if set
+    let qos_events = QosEventMonitor::from_env(&ros_node)?;
+    qos_events.watch_publisher(&topic_name.to_string(), audio_publisher.clone())?;
+    // only publishes while the node is active, see ROS2_LIFECYCLE
+    let audio_publisher = LifecyclePublisher::new(audio_publisher, ros_node.lifecycle());
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    let audio_diagnostics = diagnostics.channel("audio");
 
     // --- Dora: init and process events ------------------------------------------------------
-    let (mut node, dora_events) = DoraNode::init_from_env()?;
-    let mut events = futures::executor::block_on_stream(dora_events);
+    let (mut node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
 
     // Process audio data from Dora and publish to ROS2
-    while let Some(event) = events.next() {
+    ros_node.run_with(dora_events, qos_events.events(), |event| {
         match event {
-            Event::Input { id, metadata, data } => match id.as_str() {
+            BridgeEvent::Dora(Event::Input { id, metadata, data }) => match id.as_str() {
                 "audio" => {
+                    // Structured `audio_common_msgs/AudioStamped` struct arrays keep their own header
+                    if matches!(data.data_type(), DataType::Struct(_)) {
+                        let mut audio_stamped_msg = Ros2AudioStamped::try_from(&data)
+                            .context("failed to convert AudioStamped struct")?;
+                        let default_frame_id =
+                            parameters.get_string("frame_id").unwrap_or_else(|| frame_id.clone());
+                        audio_stamped_msg.header = header_policy.apply(
+                            Some(&audio_stamped_msg.header),
+                            receive_time_from_metadata(&metadata.parameters),
+                            &clock,
+                            &default_frame_id,
+                        );
+                        debug!("Publishing structured audio_common_msgs/AudioStamped to topic {}", audio_topic);
+                        let published = audio_publisher
+                            .publish(audio_stamped_msg)
+                            .context("failed to publish audio")
+                            .inspect_err(|e| audio_diagnostics.error(e))?;
+                        if published {
+                            audio_diagnostics.message(data.get_array_memory_size());
+                        }
+                        return Ok(());
+                    }
+
                     // Extract audio data from Arrow format
                     let audio_data: Vec<u8> = (&data).try_into()
                         .map_err(|e| eyre::eyre!("Failed to convert data to bytes: {e}"))?;
@@ -181,98 +223,80 @@ fn main() -> eyre::Result<()> {
                         }
                     };
                     
+                    let volume = parameters.get_double("volume").unwrap_or(volume);
                     let audio_msg = Ros2Audio {
-                        audio_data: audio_data_msg,
+                        audio_data: scale_audio(audio_data_msg, volume),
                         info: audio_info,
                     };
                     
-                    // Create header with current timestamp (matching audio_capturer_node)
-                    let now = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default();
-                    
-                    let header = Ros2Header {
-                        stamp: dora_ros2_bridge::messages::builtin_interfaces::msg::Time {
-                            sec: now.as_secs() as i32,
-                            nanosec: now.subsec_nanos(),
-                        },
-                        frame_id: "robot_microphone".to_string(),
-                    };
+                    // Header from the input metadata, or the current ROS time (matching audio_capturer_node)
+                    let default_frame_id =
+                        parameters.get_string("frame_id").unwrap_or_else(|| frame_id.clone());
+                    let header =
+                        header_policy.apply_metadata(&metadata.parameters, &clock, &default_frame_id);
                     
                     let audio_stamped_msg = Ros2AudioStamped {
                         header,
                         audio: audio_msg,
                     };
                     
-                    println!("Audio metadata - Rate: {}Hz, Channels: {}, Format: {} ({}), Chunk: {}, Length: {} bytes", 
+                    debug!("Audio metadata - Rate: {}Hz, Channels: {}, Format: {} ({}), Chunk: {}, Length: {} bytes", 
                              sample_rate, channels, format_str, format_code, chunk, audio_len);
 
                     // Publish to ROS2
-                    println!("Publishing audio_common_msgs/AudioStamped: {} bytes to topic {}", audio_len, audio_topic);
-                    audio_publisher
+                    debug!("Publishing audio_common_msgs/AudioStamped: {} bytes to topic {}", audio_len, audio_topic);
+                    let published = audio_publisher
                         .publish(audio_stamped_msg)
-                        .map_err(|e| eyre::eyre!("failed to publish audio: {e:?}"))?;
+                        .context("failed to publish audio")
+                        .inspect_err(|e| audio_diagnostics.error(e))?;
+                    if published {
+                        audio_diagnostics.message(audio_len);
+                    }
                 }
-                other => eprintln!("Ignoring unexpected input `{other}`"),
+                other => warn!("Ignoring unexpected input `{other}`"),
             },
-            Event::Stop(_) => {
-                println!("Received stop");
-                break;
-            }
-            other => eprintln!("Received unexpected input: {other:?}"),
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            BridgeEvent::External(qos_event) => qos_events.send_output(&mut node, &qos_event)?,
+            BridgeEvent::Parameter(_) | BridgeEvent::Lifecycle(_) => {}
         }
-    }
-
-    Ok(())
+        Ok(())
+    })
 }
 
-fn init_ros_node() -> eyre::Result<ros2_client::Node> {
-    let ros_context = dora_ros2_bridge::create_ros2_context().unwrap();
-
-    ros_context
-        .new_node(
-            ros2_client::NodeName::new("/dora", "ros2_audio_publisher")
-                .map_err(|e| eyre!("failed to create ROS2 node name: {e}"))?,
-            NodeOptions::new().enable_rosout(true),
-        )
-        .map_err(|e| eyre::eyre!("failed to create ros2 node: {e:?}"))
+/// Apply the `volume` parameter as linear gain, clamping to the sample range.
+fn scale_audio(mut data: Ros2AudioData, volume: f64) -> Ros2AudioData {
+    if volume == 1.0 {
+        return data;
+    }
+    for s in &mut data.int16_data {
+        *s = (*s as f64 * volume).clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+    }
+    for s in &mut data.int32_data {
+        *s = (*s as f64 * volume).clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+    }
+    for s in &mut data.float32_data {
+        *s = (*s as f64 * volume) as f32;
+    }
+    for s in &mut data.int8_data {
+        *s = (*s as f64 * volume).clamp(i8::MIN as f64, i8::MAX as f64) as i8;
+    }
+    for s in &mut data.uint8_data {
+        // unsigned 8 bit audio is centered around 128
+        *s = (128.0 + (*s as f64 - 128.0) * volume).clamp(0.0, 255.0) as u8;
+    }
+    data
 }
 
 fn create_audio_publisher(
     ros_node: &mut ros2_client::Node,
-    topic_name: &str,
+    topic_name: &ros2_client::Name,
+    topic_qos: &QosConfig,
 ) -> eyre::Result<ros2_client::Publisher<Ros2AudioStamped>> {
-    let topic_qos: rustdds::QosPolicies = {
-        rustdds::QosPolicyBuilder::new()
-            .durability(policy::Durability::Volatile)
-            .liveliness(policy::Liveliness::Automatic {
-                lease_duration: ros2::Duration::INFINITE,
-            })
-            .reliability(policy::Reliability::Reliable {
-                max_blocking_time: ros2::Duration::from_millis(100),
-            })
-            .history(policy::History::KeepLast { depth: 10 })
-            .build()
-    };
-
-    // Parse topic name (e.g., "/robot/audio" -> namespace: "/robot", name: "audio")
-    let (namespace, name) = if topic_name.starts_with('/') {
-        let parts: Vec<&str> = topic_name.split('/').filter(|s| !s.is_empty()).collect();
-        if parts.len() >= 2 {
-            (format!("/{}", parts[0]), parts[1..].join("/"))
-        } else {
-            ("/".to_string(), "audio".to_string())
-        }
-    } else {
-        ("/".to_string(), topic_name.to_string())
-    };
-
     let audio_topic = ros_node
         .create_topic(
-            &ros2_client::Name::new(&namespace, &name)
-                .map_err(|e| eyre!("failed to create ROS2 name: {e}"))?,
+            topic_name,
             ros2_client::MessageTypeName::new("audio_common_msgs", "AudioStamped"),
-            &topic_qos,
+            &topic_qos.to_policies(),
         )
         .context("failed to create topic")?;
 
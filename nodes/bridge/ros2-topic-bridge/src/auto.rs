@@ -0,0 +1,102 @@
+//! Auto-bridging of discovered ROS2 topics that match a pattern.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use dora_ros2_bridge::{
+    BridgeNode, GraphEvent, GraphMonitor,
+    config::writer_compatible_qos,
+    graph::TopicInfo,
+    tracing::{info, warn},
+};
+use futures::future::AbortHandle;
+
+use crate::{
+    BridgeContext,
+    config::{AutoConfig, TopicConfig},
+    subscribe,
+};
+
+/// Creates and tears down subscriptions as matching topics appear and disappear.
+pub struct AutoBridge {
+    rules: Vec<AutoConfig>,
+    /// Topics bridged explicitly in the config, never auto-bridged.
+    configured: HashSet<String>,
+    active: HashMap<String, AbortHandle>,
+    monitor: GraphMonitor,
+    bridge: BridgeContext,
+}
+
+impl AutoBridge {
+    pub fn new(
+        rules: Vec<AutoConfig>,
+        topics: &[TopicConfig],
+        ros_node: &BridgeNode,
+        monitor: GraphMonitor,
+        bridge: BridgeContext,
+    ) -> eyre::Result<Self> {
+        let configured = topics
+            .iter()
+            .map(|t| Ok(ros_node.resolve_name(&t.topic)?.to_string()))
+            .collect::<eyre::Result<_>>()?;
+        for rule in &rules {
+            info!("auto-bridging ROS2 topics matching `{}`", rule.pattern.as_str());
+        }
+        Ok(Self {
+            rules,
+            configured,
+            active: HashMap::new(),
+            monitor,
+            bridge,
+        })
+    }
+
+    pub fn handle(&mut self, ros_node: &mut BridgeNode, event: GraphEvent) -> eyre::Result<()> {
+        match event {
+            GraphEvent::TopicAppeared(topic) | GraphEvent::TopicChanged(topic) => {
+                if topic.publishers == 0 {
+                    self.remove(&topic.name);
+                } else if !self.active.contains_key(&topic.name) {
+                    self.add(ros_node, &topic);
+                }
+            }
+            GraphEvent::TopicDisappeared { name } => self.remove(&name),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, ros_node: &mut BridgeNode, topic: &TopicInfo) {
+        if self.configured.contains(&topic.name) {
+            return;
+        }
+        let Some(rule) = self.rules.iter().find(|r| r.pattern.matches(&topic.name)) else {
+            return;
+        };
+        let [message_type] = topic.type_names.as_slice() else {
+            warn!(
+                "not auto-bridging `{}`: conflicting types {:?}",
+                topic.name, topic.type_names
+            );
+            return;
+        };
+        let qos = writer_compatible_qos(&self.monitor, &topic.name);
+        let topic_config = Arc::new(rule.topic_config(&topic.name, message_type, qos));
+
+        match subscribe(ros_node, topic_config, &self.bridge) {
+            Ok(handle) => {
+                self.active.insert(topic.name.clone(), handle);
+            }
+            Err(err) => warn!("not auto-bridging `{}`: {err:?}", topic.name),
+        }
+    }
+
+    fn remove(&mut self, topic: &str) {
+        if let Some(handle) = self.active.remove(topic) {
+            info!("topic `{topic}` disappeared, stopping auto-bridge");
+            handle.abort();
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use dora_ros2_bridge::{ContentFilter, HeaderPolicy, QosConfig, ThrottleConfig, TopicPattern};
+use eyre::bail;
+use serde::Deserialize;
+
+/// Env variable pointing to a YAML file with the list of bridged topics.
+pub const CONFIG_FILE_ENV: &str = "BRIDGE_CONFIG";
+/// Env variable holding the list of bridged topics as inline YAML.
+pub const CONFIG_INLINE_ENV: &str = "BRIDGE_TOPICS";
+
+/// A single bridged topic.
+///
+/// ```yaml
+/// - topic: /camera/image_raw
+///   type: sensor_msgs/Image
+///   direction: ros2_to_dora
+///   qos: sensor_data,depth=1
+///   id: image
+///   filter: header.frame_id = 'camera_frame'
+///   throttle: max_rate=10,latest_only
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopicConfig {
+    /// ROS2 topic name, resolved against the bridge node namespace.
+    pub topic: String,
+    /// Message type in the `package/Message` format.
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub direction: Direction,
+    #[serde(default)]
+    pub qos: QosConfig,
+    /// Dora input/output id, defaults to the topic name without leading `/`
+    /// and with the remaining `/` replaced by `_`.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Stamp and frame_id policy for messages published on ROS2. Without a
+    /// policy the header of the dora input is published unchanged.
+    #[serde(default)]
+    pub header: Option<HeaderPolicy>,
+    /// Only messages matching this filter are forwarded, in either direction.
+    #[serde(default)]
+    pub filter: Option<ContentFilter>,
+    /// Rate limit, decimation or bandwidth limit, applied after the filter.
+    #[serde(default)]
+    pub throttle: Option<ThrottleConfig>,
+}
+
+impl TopicConfig {
+    pub fn dora_id(&self) -> String {
+        self.id.clone().unwrap_or_else(|| {
+            self.topic
+                .trim_start_matches(['/', '~'])
+                .replace('/', "_")
+        })
+    }
+
+    /// Split the message type into package and message name.
+    pub fn package_and_name(&self) -> eyre::Result<(&str, &str)> {
+        match (
+            self.message_type.split_once('/'),
+            self.message_type.split_once("::"),
+        ) {
+            (Some((package, name)), None) | (None, Some((package, name))) => {
+                // accept `package/msg/Message` as used by `ros2 topic info`
+                let name = name.strip_prefix("msg/").unwrap_or(name);
+                Ok((package, name))
+            }
+            _ => bail!(
+                "expected message type in the format `package/Message`, such as `std_msgs/String`, but got `{}`",
+                self.message_type
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Subscribe on ROS2 and send the messages as dora outputs.
+    #[serde(alias = "to_dora")]
+    Ros2ToDora,
+    /// Receive dora inputs and publish them on ROS2.
+    #[serde(alias = "to_ros2")]
+    DoraToRos2,
+}
+
+/// Bridge every discovered ROS2 topic whose name matches `pattern`.
+///
+/// ```yaml
+/// - pattern: /robot/**
+/// - pattern: "*/image_raw"
+///   qos: sensor_data
+///   id: images
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoConfig {
+    pub pattern: TopicPattern,
+    /// QoS override, by default the QoS is chosen to be compatible with the writer.
+    #[serde(default)]
+    pub qos: Option<QosConfig>,
+    /// Dora output used for all matching topics. By default each topic is sent
+    /// on an output named like the topic (see [`TopicConfig::dora_id`]).
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Content filter applied to all matching topics.
+    #[serde(default)]
+    pub filter: Option<ContentFilter>,
+    /// Throttle applied to each matching topic separately.
+    #[serde(default)]
+    pub throttle: Option<ThrottleConfig>,
+}
+
+impl AutoConfig {
+    /// Config for bridging a discovered `topic` of type `message_type`.
+    pub fn topic_config(&self, topic: &str, message_type: &str, qos: QosConfig) -> TopicConfig {
+        TopicConfig {
+            topic: topic.to_owned(),
+            message_type: message_type.to_owned(),
+            direction: Direction::Ros2ToDora,
+            qos: self.qos.clone().unwrap_or(qos),
+            id: self.id.clone(),
+            header: None,
+            filter: self.filter.clone(),
+            throttle: self.throttle.clone(),
+        }
+    }
+}
+
+/// An entry of the bridge config: a single topic or a pattern for auto-bridging.
+#[derive(Debug, Clone)]
+enum Entry {
+    Topic(TopicConfig),
+    Auto(AutoConfig),
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    /// Picks the entry kind by its `pattern` or `topic` key first, so errors
+    /// name the offending field instead of failing to match either kind.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+        let entry = if value.get("pattern").is_some() {
+            AutoConfig::deserialize(value).map(Entry::Auto)
+        } else if value.get("topic").is_some() {
+            TopicConfig::deserialize(value).map(Entry::Topic)
+        } else {
+            return Err(serde::de::Error::custom(
+                "expected a `topic` entry or a `pattern` entry",
+            ));
+        };
+        entry.map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BridgeConfig {
+    pub topics: Vec<TopicConfig>,
+    pub auto: Vec<AutoConfig>,
+}
+
+/// Load the topic list from `BRIDGE_CONFIG` (file) or `BRIDGE_TOPICS` (inline YAML).
+pub fn load() -> eyre::Result<BridgeConfig> {
+    let entries: Vec<Entry> =
+        dora_ros2_bridge::config::load_yaml(CONFIG_FILE_ENV, CONFIG_INLINE_ENV, "bridge")?;
+    let mut config = BridgeConfig::default();
+    for entry in entries {
+        match entry {
+            Entry::Topic(topic) => config.topics.push(topic),
+            Entry::Auto(auto) => config.auto.push(auto),
+        }
+    }
+
+    let mut ids = std::collections::HashSet::new();
+    for topic in &config.topics {
+        topic.package_and_name()?;
+        if !ids.insert((topic.direction, topic.dora_id())) {
+            bail!("duplicate dora id `{}` in bridge config", topic.dora_id());
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(yaml: &str) -> TopicConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn dora_ids() {
+        let cases = [
+            ("/chatter", None, "chatter"),
+            ("/camera/image_raw", None, "camera_image_raw"),
+            ("~/status", None, "status"),
+            ("relative/topic", None, "relative_topic"),
+            ("/camera/image_raw", Some("image"), "image"),
+        ];
+        for (name, id, expected) in cases {
+            let mut config = topic(&format!(
+                "{{topic: {name}, type: std_msgs/String, direction: ros2_to_dora}}"
+            ));
+            config.id = id.map(ToOwned::to_owned);
+            assert_eq!(config.dora_id(), expected, "`{name}`");
+        }
+    }
+
+    #[test]
+    fn message_types() {
+        let cases = [
+            ("std_msgs/String", Some(("std_msgs", "String"))),
+            ("sensor_msgs/msg/Image", Some(("sensor_msgs", "Image"))),
+            ("geometry_msgs::Twist", Some(("geometry_msgs", "Twist"))),
+            ("String", None),
+            ("std_msgs/String::Bad", None),
+        ];
+        for (message_type, expected) in cases {
+            let config = topic(&format!(
+                "{{topic: /t, type: \"{message_type}\", direction: dora_to_ros2}}"
+            ));
+            assert_eq!(config.package_and_name().ok(), expected, "`{message_type}`");
+        }
+    }
+
+    #[test]
+    fn entry_errors_name_the_field() {
+        let parse = |yaml: &str| {
+            serde_yaml::from_str::<Vec<Entry>>(yaml)
+                .map(|_| ())
+                .unwrap_err()
+                .to_string()
+        };
+        let err = parse("- {topic: /t, type: std_msgs/String, direction: ros2_to_dora, qso: 1}");
+        assert!(err.contains("unknown field `qso`"), "{err}");
+        let err = parse("- {topic: /t, type: std_msgs/String}");
+        assert!(err.contains("missing field `direction`"), "{err}");
+        let err = parse("- {pattern: /robot/**, direction: ros2_to_dora}");
+        assert!(err.contains("unknown field `direction`"), "{err}");
+        let err = parse("- {type: std_msgs/String}");
+        assert!(err.contains("`topic` entry or a `pattern` entry"), "{err}");
+
+        let entries: Vec<Entry> = serde_yaml::from_str(
+            "[{topic: /t, type: std_msgs/String, direction: to_dora}, {pattern: /robot/**}]",
+        )
+        .unwrap();
+        assert!(matches!(entries[..], [Entry::Topic(_), Entry::Auto(_)]));
+    }
+}
@@ -0,0 +1,412 @@
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use dora_node_api::{
+    self, Event, MetadataParameters, Parameter,
+    arrow::array::{Array, ArrayData, make_array},
+    dora_core::config::DataId,
+};
+use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, Diagnostics, DiagnosticsConfig, GraphEvent, GraphMonitor, QosEvent,
+    QosEventMonitor, Throttle,
+    config::{Messages, load_messages},
+    diagnostics::Channel,
+    flume,
+    header::{
+        header_from_arrow, header_from_metadata, header_to_metadata, receive_time_from_metadata,
+        receive_time_to_metadata, replace_header,
+    },
+    ros2_client, rustdds,
+    tracing::{error, info, warn},
+};
+use dora_ros2_bridge_python::typed::{TypeInfo, TypedValue, deserialize::StructDeserializer};
+use eyre::{Context, ContextCompat};
+use futures::{
+    StreamExt,
+    future::{self, AbortHandle, Abortable},
+    stream::{self, BoxStream},
+};
+
+use crate::{
+    auto::AutoBridge,
+    config::{Direction, TopicConfig},
+};
+
+mod auto;
+mod config;
+
+type Incoming = (
+    DataId,
+    Arc<TopicConfig>,
+    Result<(ArrayData, ros2_client::MessageInfo), rustdds::dds::ReadError>,
+);
+
+enum External {
+    Message(Incoming),
+    Graph(GraphEvent),
+    Qos(QosEvent),
+}
+
+/// State shared by all bridged topics, including auto-bridged ones.
+#[derive(Clone)]
+struct BridgeContext {
+    messages: Arc<Messages>,
+    /// Messages received on subscribed topics, forwarded to dora by the event loop.
+    incoming_tx: flume::Sender<Incoming>,
+    /// Watches the QoS of all bridged topics.
+    qos_events: QosEventMonitor,
+    /// One diagnostics status per bridged topic.
+    diagnostics: Diagnostics,
+}
+
+/// A dora input that is published on ROS2.
+struct Output {
+    publisher: Arc<ros2_client::Publisher<TypedValue<'static>>>,
+    type_info: TypeInfo<'static>,
+    topic_config: Arc<TopicConfig>,
+    channel: Channel,
+    throttle: Option<Throttle>,
+    /// Number of inputs dropped by the content filter.
+    filtered: u64,
+}
+
+fn main() -> eyre::Result<()> {
+    let config = config::load()?;
+    let messages = Arc::new(load_messages()?);
+
+    // --- ROS 2 setup: one node (and DDS participant) for all topics ----------------------------
+    let node_name = std::env::var("ROS2_NODE_NAME").unwrap_or_else(|_| "ros2_topic_bridge".to_string());
+    let mut ros_node = BridgeNode::builder(node_name).build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting generic ROS2 topic bridge node");
+    // one diagnostics status per bridged topic
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    // logs QoS problems of all bridged topics, and sends them on ROS2_QOS_EVENTS_OUTPUT if set
+    let qos_events = QosEventMonitor::from_env(&ros_node)?;
+
+    let (incoming_tx, incoming_rx) = flume::unbounded::<Incoming>();
+    let bridge = BridgeContext {
+        messages,
+        incoming_tx,
+        qos_events,
+        diagnostics,
+    };
+    let mut publishers = HashMap::new();
+
+    for topic_config in &config.topics {
+        let topic_config = Arc::new(topic_config.clone());
+        match topic_config.direction {
+            Direction::Ros2ToDora => {
+                subscribe(&mut ros_node, topic_config, &bridge)?;
+            }
+            Direction::DoraToRos2 => {
+                let (topic, type_info) =
+                    create_topic(&mut ros_node, &topic_config, &bridge.messages)?;
+                let publisher = ros_node
+                    .node_mut()
+                    .create_publisher::<TypedValue<'static>>(
+                        &topic,
+                        Some(topic_config.qos.to_policies()),
+                    )
+                    .context("failed to create publisher")?;
+                let publisher = Arc::new(publisher);
+                bridge
+                    .qos_events
+                    .watch_publisher(&topic_config.topic, publisher.clone())?;
+                let id = DataId::from(topic_config.dora_id());
+                info!("bridging dora input `{id}` -> ROS2 `{}`", topic_config.topic);
+                if let Some(filter) = &topic_config.filter {
+                    info!("filtering `{}` with `{filter}`", topic_config.topic);
+                }
+                let channel = bridge.diagnostics.channel(&topic_config.topic);
+                let throttle = topic_throttle(&topic_config, &channel);
+                publishers.insert(
+                    id,
+                    Output {
+                        publisher,
+                        type_info,
+                        topic_config,
+                        channel,
+                        throttle,
+                        filtered: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    // --- Auto-bridging of discovered topics -------------------------------------------------
+    let mut auto_bridge = None;
+    let graph_events: BoxStream<'static, External> = if config.auto.is_empty() {
+        stream::empty().boxed()
+    } else {
+        let monitor = GraphMonitor::new(&ros_node)?;
+        let events = monitor.events().map(External::Graph).boxed();
+        auto_bridge = Some(AutoBridge::new(
+            config.auto,
+            &config.topics,
+            &ros_node,
+            monitor,
+            bridge.clone(),
+        )?);
+        events
+    };
+
+    // --- Dora: init and process events ------------------------------------------------------
+    let (mut node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
+
+    let external = stream::select_all([
+        incoming_rx.into_stream().map(External::Message).boxed(),
+        graph_events,
+        bridge.qos_events.events().map(External::Qos).boxed(),
+    ]);
+    ros_node.run_with_node(dora_events, external, |ros_node, event| {
+        match event {
+            BridgeEvent::Dora(Event::Input { id, metadata, data }) => {
+                let Some(output) = publishers.get_mut(&id) else {
+                    warn!("Ignoring unexpected input `{id}`");
+                    return Ok(());
+                };
+                // publishers stay inactive until a managed node is activated
+                if !ros_node.is_active() {
+                    return Ok(());
+                }
+                let value = match &output.topic_config.header {
+                    Some(policy) => {
+                        let source = header_from_arrow(&data)
+                            .or_else(|| header_from_metadata(&metadata.parameters));
+                        let header = policy.apply(
+                            source.as_ref(),
+                            receive_time_from_metadata(&metadata.parameters),
+                            ros_node.clock(),
+                            "",
+                        );
+                        replace_header(&data, header).with_context(|| {
+                            format!("failed to apply header policy to input `{id}`")
+                        })?
+                    }
+                    None => data.0,
+                };
+                let channel = &output.channel;
+                if !passes_filter(&output.topic_config, &value, channel, &mut output.filtered) {
+                    return Ok(());
+                }
+                let bytes = value.get_array_memory_size();
+                if let Some(throttle) = &mut output.throttle {
+                    if !throttle.admit(bytes) {
+                        return Ok(());
+                    }
+                }
+                // type info ensures the arrow struct is serialized in ROS2 field order
+                let typed_value = TypedValue {
+                    value: &value,
+                    type_info: &output.type_info,
+                };
+                output
+                    .publisher
+                    .publish(typed_value)
+                    .map_err(|e| e.forget_data())
+                    .with_context(|| format!("failed to publish input `{id}`"))
+                    .inspect_err(|e| channel.error(e))?;
+                channel.message(bytes);
+            }
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            BridgeEvent::Parameter(change) => {
+                info!("Parameter `{}` changed to {:?}", change.name, change.value)
+            }
+            BridgeEvent::Lifecycle(transition) => info!(
+                "lifecycle `{}`: {} -> {}, bridging {}",
+                transition.transition,
+                transition.start,
+                transition.goal,
+                if ros_node.is_active() { "resumed" } else { "paused" }
+            ),
+            BridgeEvent::External(External::Message((output, topic_config, message))) => {
+                // incoming messages are dropped while the node is paused
+                if !ros_node.is_active() {
+                    return Ok(());
+                }
+                let channel = bridge.diagnostics.channel(&topic_config.topic);
+                match message {
+                    Ok((value, _info)) => {
+                        let value = make_array(value);
+                        channel.message(value.get_array_memory_size());
+                        let mut params = MetadataParameters::default();
+                        // original header, for sinks with a header policy
+                        if let Some(header) = header_from_arrow(&value) {
+                            header_to_metadata(&mut params, &header);
+                        }
+                        receive_time_to_metadata(&mut params, &ros_node.clock().now());
+                        params.insert(
+                            "topic".into(),
+                            Parameter::String(topic_config.topic.clone()),
+                        );
+                        params.insert(
+                            "type".into(),
+                            Parameter::String(topic_config.message_type.clone()),
+                        );
+                        node.send_output(output, params, value)?;
+                    }
+                    Err(err) => {
+                        error!(
+                            "failed to read message from `{}`: {err:?}",
+                            topic_config.topic
+                        );
+                        channel.error(format!("failed to read message: {err:?}"));
+                    }
+                }
+            }
+            BridgeEvent::External(External::Graph(graph_event)) => {
+                if let Some(auto_bridge) = &mut auto_bridge {
+                    auto_bridge.handle(ros_node, graph_event)?;
+                }
+            }
+            BridgeEvent::External(External::Qos(qos_event)) => {
+                bridge.qos_events.send_output(&mut node, &qos_event)?
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Evaluate the content filter of `topic_config` on a message.
+///
+/// Messages that don't match, or that the filter can't be evaluated on, are
+/// dropped and counted in `filtered` and the diagnostics of the topic.
+fn passes_filter(
+    topic_config: &TopicConfig,
+    value: &dyn Array,
+    channel: &Channel,
+    filtered: &mut u64,
+) -> bool {
+    let Some(filter) = &topic_config.filter else {
+        return true;
+    };
+    let passes = filter.matches(value).unwrap_or_else(|err| {
+        warn!("dropping message on `{}`: {err:?}", topic_config.topic);
+        channel.error(format!("{err:#}"));
+        false
+    });
+    if !passes {
+        *filtered += 1;
+        channel.value("filtered", *filtered);
+    }
+    passes
+}
+
+/// The throttle of `topic_config`, reporting dropped messages as `throttled`
+/// in the diagnostics of the topic.
+fn topic_throttle(topic_config: &TopicConfig, channel: &Channel) -> Option<Throttle> {
+    let config = topic_config.throttle.clone()?;
+    info!("throttling `{}` with `{config}`", topic_config.topic);
+    let channel = channel.clone();
+    Some(Throttle::new(config).on_drop(move |dropped| channel.value("throttled", dropped)))
+}
+
+/// Create the ROS2 topic for `topic_config` and look up its message definition.
+fn create_topic(
+    ros_node: &mut BridgeNode,
+    topic_config: &TopicConfig,
+    messages: &Arc<Messages>,
+) -> eyre::Result<(ros2_client::Topic, TypeInfo<'static>)> {
+    let (package_name, message_name) = topic_config.package_and_name()?;
+    messages
+        .get(package_name)
+        .and_then(|m| m.get(message_name))
+        .with_context(|| {
+            format!(
+                "unknown message type `{}`, is it in AMENT_PREFIX_PATH?",
+                topic_config.message_type
+            )
+        })?;
+
+    let type_info = TypeInfo {
+        package_name: package_name.to_owned().into(),
+        message_name: message_name.to_owned().into(),
+        messages: messages.clone(),
+    };
+    let topic_name = ros_node.resolve_name(&topic_config.topic)?;
+    let topic = ros_node
+        .node_mut()
+        .create_topic(
+            &topic_name,
+            ros2_client::MessageTypeName::new(package_name, message_name),
+            &topic_config.qos.to_policies(),
+        )
+        .with_context(|| format!("failed to create topic `{}`", topic_config.topic))?;
+    Ok((topic, type_info))
+}
+
+/// Subscribe to a ROS2 topic and forward its messages to the `incoming_tx` of `bridge`.
+///
+/// Messages are filtered and throttled before they are forwarded. The
+/// subscription is watched by the QoS event monitor and dropped when the returned
+/// handle is aborted.
+fn subscribe(
+    ros_node: &mut BridgeNode,
+    topic_config: Arc<TopicConfig>,
+    bridge: &BridgeContext,
+) -> eyre::Result<AbortHandle> {
+    let (topic, type_info) = create_topic(ros_node, &topic_config, &bridge.messages)?;
+    let subscription = ros_node
+        .node_mut()
+        .create_subscription::<ArrayData>(&topic, Some(topic_config.qos.to_policies()))
+        .context("failed to create subscription")?;
+    let subscription = Arc::new(subscription);
+    let qos_watch = bridge
+        .qos_events
+        .watch_subscription(&topic_config.topic, subscription.clone())?;
+    let deserializer = StructDeserializer::new(Cow::Owned(type_info));
+    let id = DataId::from(topic_config.dora_id());
+    info!("bridging ROS2 `{}` -> dora output `{id}`", topic_config.topic);
+    if let Some(filter) = &topic_config.filter {
+        info!("filtering `{}` with `{filter}`", topic_config.topic);
+    }
+    let channel = bridge.diagnostics.channel(&topic_config.topic);
+    let throttle = topic_throttle(&topic_config, &channel);
+    let incoming_tx = bridge.incoming_tx.clone();
+
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    let forward = async move {
+        let mut filtered = 0;
+        let stream = subscription
+            .async_stream_seed(deserializer)
+            .filter(|message| {
+                future::ready(match message {
+                    Ok((value, _info)) => passes_filter(
+                        &topic_config,
+                        &make_array(value.clone()),
+                        &channel,
+                        &mut filtered,
+                    ),
+                    Err(_) => true,
+                })
+            });
+        futures::pin_mut!(stream);
+        let stream = match throttle {
+            // read errors bypass the throttle
+            Some(throttle) => throttle
+                .stream(stream, |message| {
+                    message
+                        .as_ref()
+                        .ok()
+                        .map(|(value, _info)| value.get_array_memory_size())
+                })
+                .left_stream(),
+            None => stream.right_stream(),
+        };
+        futures::pin_mut!(stream);
+        while let Some(message) = stream.next().await {
+            if incoming_tx
+                .send((id.clone(), topic_config.clone(), message))
+                .is_err()
+            {
+                break;
+            }
+        }
+    };
+    ros_node.spawn(async move {
+        let _ = Abortable::new(forward, abort_registration).await;
+        qos_watch.abort();
+    })?;
+    Ok(abort_handle)
+}
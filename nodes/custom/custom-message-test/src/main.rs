@@ -1,46 +1,86 @@
-use dora_node_api::{self, DoraNode, Event};
+use dora_node_api::{self, Event, dora_core::config::DataId};
 use dora_ros2_bridge::{
+    ActionClientBridge, ActionClientConfig, ActionQosConfig, ActionServerBridge,
+    ActionServerConfig, BridgeEvent, BridgeNode, LifecyclePublisher, QosConfig,
+    ServiceClientBridge, ServiceClientConfig, ServiceServerBridge, ServiceServerConfig,
+    action_client::ActionClientEvent,
+    action_server::ActionServerEvent,
+    messages::custom_msgs::action::{
+        AudioProcessingFeedback, AudioProcessingGoal, AudioProcessingResult,
+    },
     messages::custom_msgs::msg::{CustomAudio, RobotStatus},
+    messages::custom_msgs::service::{ProcessAudioRequest, ProcessAudioResponse},
     messages::std_msgs::msg::Header,
-    ros2_client::{self, NodeOptions},
-    rustdds::{self, policy},
+    ros2_client,
+    service_client::ServiceCallResult,
+    service_server::ServiceRequest,
+    tracing::{info, warn},
 };
 use eyre::Result;
 use futures::StreamExt;
 
-fn main() -> Result<()> {
-    println!("Starting custom message test node");
+type ProcessAudio = ros2_client::AService<ProcessAudioRequest, ProcessAudioResponse>;
+type AudioProcessing = ros2_client::action::Action<
+    AudioProcessingGoal,
+    AudioProcessingResult,
+    AudioProcessingFeedback,
+>;
+
+enum ServiceEvent {
+    ClientResult(ServiceCallResult<ProcessAudioResponse>),
+    ServerRequest(ServiceRequest<ProcessAudioRequest>),
+    Action(ActionClientEvent<AudioProcessingFeedback, AudioProcessingResult>),
+    ActionServer(ActionServerEvent<AudioProcessingGoal>),
+}
 
+fn main() -> Result<()> {
     // Initialize ROS2 node
-    let ros_context = dora_ros2_bridge::create_ros2_context()?;
-    let mut ros_node = ros_context
-        .new_node(
-            ros2_client::NodeName::new("/dora", "custom_message_test")?,
-            NodeOptions::new().enable_rosout(true),
-        )?;
+    let mut ros_node = BridgeNode::builder("custom_message_test").build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting custom message test node");
 
-    // Create publishers for custom messages
-    let custom_audio_publisher = create_custom_audio_publisher(&mut ros_node)?;
-    let robot_status_publisher = create_robot_status_publisher(&mut ros_node)?;
+    // Create publishers for custom messages, silent until a managed node is activated
+    let audio_qos = QosConfig::from_env_or("ROS2_AUDIO_QOS", QosConfig::default())?;
+    let status_qos = QosConfig::from_env_or("ROS2_STATUS_QOS", QosConfig::default())?;
+    let custom_audio_publisher = LifecyclePublisher::new(
+        create_custom_audio_publisher(ros_node.node_mut(), &audio_qos)?,
+        ros_node.lifecycle(),
+    );
+    let robot_status_publisher = LifecyclePublisher::new(
+        create_robot_status_publisher(ros_node.node_mut(), &status_qos)?,
+        ros_node.lifecycle(),
+    );
+
+    // Create service client, driven by the `process_audio` input
+    let process_audio_client = create_process_audio_client(&mut ros_node)?;
+    // Create service server, answered by the `process_audio_result` input
+    let process_audio_server = create_process_audio_server(&mut ros_node)?;
+    // Create action client, driven by the `audio_processing_goal`/`_cancel` inputs
+    let audio_processing_client = create_audio_processing_client(&mut ros_node)?;
+    // Create action server, executed through the `audio_processing_server_*` inputs
+    let audio_processing_server = create_audio_processing_server(&mut ros_node)?;
 
     // Initialize Dora node
-    let (mut node, dora_events) = DoraNode::init_from_env()?;
-    let mut events = futures::executor::block_on_stream(dora_events);
+    let (mut node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
+    let clock = ros_node.clock().clone();
 
     // Process events
-    while let Some(event) = events.next() {
+    let service_events = futures::stream::select_all([
+        process_audio_client.results().map(ServiceEvent::ClientResult).boxed(),
+        process_audio_server.requests().map(ServiceEvent::ServerRequest).boxed(),
+        audio_processing_client.events().map(ServiceEvent::Action).boxed(),
+        audio_processing_server.events().map(ServiceEvent::ActionServer).boxed(),
+    ]);
+    ros_node.run_with(dora_events, service_events, |event| {
         match event {
-            Event::Input { id, metadata, data } => match id.as_str() {
+            BridgeEvent::Dora(Event::Input { id, metadata, data }) => match id.as_str() {
                 "custom_audio" => {
                     // Create custom audio message
                     let audio_data: Vec<u8> = (&data).try_into()?;
                     
                     let custom_audio = CustomAudio {
                         header: Header {
-                            stamp: dora_ros2_bridge::messages::builtin_interfaces::msg::Time {
-                                sec: 0,
-                                nanosec: 0,
-                            },
+                            stamp: clock.now(),
                             frame_id: "custom_audio".to_string(),
                         },
                         audio_data,
@@ -50,17 +90,15 @@ fn main() -> Result<()> {
                         chunk_size: 1024,
                     };
 
-                    println!("Publishing CustomAudio message");
-                    custom_audio_publisher.publish(custom_audio)?;
+                    if custom_audio_publisher.publish(custom_audio)? {
+                        info!("Published CustomAudio message");
+                    }
                 }
                 "robot_status" => {
                     // Create robot status message
                     let robot_status = RobotStatus {
                         header: Header {
-                            stamp: dora_ros2_bridge::messages::builtin_interfaces::msg::Time {
-                                sec: 0,
-                                nanosec: 0,
-                            },
+                            stamp: clock.now(),
                             frame_id: "robot_status".to_string(),
                         },
                         robot_id: "test_robot".to_string(),
@@ -69,43 +107,73 @@ fn main() -> Result<()> {
                         active_sensors: vec!["camera".to_string(), "microphone".to_string()],
                     };
 
-                    println!("Publishing RobotStatus message");
-                    robot_status_publisher.publish(robot_status)?;
+                    if robot_status_publisher.publish(robot_status)? {
+                        info!("Published RobotStatus message");
+                    }
+                }
+                "process_audio" => {
+                    let request_id =
+                        process_audio_client.handle_input(&metadata.parameters, &data)?;
+                    info!("Calling ProcessAudio service (request `{request_id}`)");
+                }
+                "process_audio_result" => {
+                    process_audio_server.handle_input(&metadata.parameters, &data)?;
+                }
+                "audio_processing_goal" => {
+                    let request_id =
+                        audio_processing_client.handle_goal_input(&metadata.parameters, &data)?;
+                    info!("Sending AudioProcessing goal (request `{request_id}`)");
                 }
-                other => eprintln!("Ignoring unexpected input `{other}`"),
+                "audio_processing_server_feedback" => {
+                    audio_processing_server.handle_feedback_input(&metadata.parameters, &data)?;
+                }
+                "audio_processing_server_result" => {
+                    audio_processing_server.handle_result_input(&metadata.parameters, &data)?;
+                }
+                "audio_processing_cancel" => {
+                    if let Err(err) =
+                        audio_processing_client.handle_cancel_input(&metadata.parameters, &data)
+                    {
+                        warn!("{err:?}");
+                    }
+                }
+                other => warn!("Ignoring unexpected input `{other}`"),
             },
-            Event::Stop(_) => {
-                println!("Received stop");
-                break;
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            BridgeEvent::Parameter(change) => {
+                info!("Parameter `{}` changed to {:?}", change.name, change.value)
+            }
+            BridgeEvent::Lifecycle(transition) => info!(
+                "Lifecycle `{}`: {} -> {}",
+                transition.transition, transition.start, transition.goal
+            ),
+            BridgeEvent::External(ServiceEvent::ClientResult(result)) => {
+                process_audio_client.send_output(&mut node, result)?
+            }
+            BridgeEvent::External(ServiceEvent::ServerRequest(request)) => {
+                info!("Received ProcessAudio request `{}`", request.request_id);
+                process_audio_server.send_output(&mut node, request)?
+            }
+            BridgeEvent::External(ServiceEvent::Action(event)) => {
+                audio_processing_client.send_output(&mut node, event)?
+            }
+            BridgeEvent::External(ServiceEvent::ActionServer(event)) => {
+                audio_processing_server.send_output(&mut node, event)?
             }
-            other => eprintln!("Received unexpected input: {other:?}"),
         }
-    }
-
-    Ok(())
+        Ok(())
+    })
 }
 
 fn create_custom_audio_publisher(
     ros_node: &mut ros2_client::Node,
+    topic_qos: &QosConfig,
 ) -> Result<ros2_client::Publisher<CustomAudio>> {
-    let topic_qos: rustdds::QosPolicies = {
-        rustdds::QosPolicyBuilder::new()
-            .durability(policy::Durability::Volatile)
-            .liveliness(policy::Liveliness::Automatic {
-                lease_duration: dora_ros2_bridge::ros2_client::ros2::Duration::INFINITE,
-            })
-            .reliability(policy::Reliability::Reliable {
-                max_blocking_time: dora_ros2_bridge::ros2_client::ros2::Duration::from_millis(100),
-            })
-            .history(policy::History::KeepLast { depth: 10 })
-            .build()
-    };
-
     let audio_topic = ros_node
         .create_topic(
             &ros2_client::Name::new("/custom", "audio")?,
             ros2_client::MessageTypeName::new("custom_msgs", "CustomAudio"),
-            &topic_qos,
+            &topic_qos.to_policies(),
         )?;
 
     let audio_publisher = ros_node
@@ -115,28 +183,101 @@ fn create_custom_audio_publisher(
 
 fn create_robot_status_publisher(
     ros_node: &mut ros2_client::Node,
+    topic_qos: &QosConfig,
 ) -> Result<ros2_client::Publisher<RobotStatus>> {
-    let topic_qos: rustdds::QosPolicies = {
-        rustdds::QosPolicyBuilder::new()
-            .durability(policy::Durability::Volatile)
-            .liveliness(policy::Liveliness::Automatic {
-                lease_duration: dora_ros2_bridge::ros2_client::ros2::Duration::INFINITE,
-            })
-            .reliability(policy::Reliability::Reliable {
-                max_blocking_time: dora_ros2_bridge::ros2_client::ros2::Duration::from_millis(100),
-            })
-            .history(policy::History::KeepLast { depth: 10 })
-            .build()
-    };
-
     let status_topic = ros_node
         .create_topic(
             &ros2_client::Name::new("/custom", "robot_status")?,
             ros2_client::MessageTypeName::new("custom_msgs", "RobotStatus"),
-            &topic_qos,
+            &topic_qos.to_policies(),
         )?;
 
     let status_publisher = ros_node
         .create_publisher::<RobotStatus>(&status_topic, None)?;
     Ok(status_publisher)
 }
+
+fn create_process_audio_client(
+    ros_node: &mut BridgeNode,
+) -> Result<ServiceClientBridge<ProcessAudio>> {
+    let service_name = std::env::var("ROS2_PROCESS_AUDIO_SERVICE")
+        .unwrap_or_else(|_| "/custom/process_audio".to_string());
+    let mut config = ServiceClientConfig::new(service_name);
+    config.qos = QosConfig::from_env_or("ROS2_SERVICE_QOS", config.qos)?;
+    if let Ok(timeout) = std::env::var("ROS2_SERVICE_TIMEOUT_MS") {
+        let timeout: u64 = timeout.parse()?;
+        config.timeout = std::time::Duration::from_millis(timeout);
+    }
+    config.response_output = DataId::from("process_audio_response".to_owned());
+    config.error_output = DataId::from("process_audio_error".to_owned());
+
+    ServiceClientBridge::new(
+        ros_node,
+        ros2_client::ServiceTypeName::new("custom_msgs", "ProcessAudio"),
+        config,
+    )
+}
+
+fn create_process_audio_server(
+    ros_node: &mut BridgeNode,
+) -> Result<ServiceServerBridge<ProcessAudio>> {
+    let service_name = std::env::var("ROS2_PROCESS_AUDIO_SERVER")
+        .unwrap_or_else(|_| "/custom/dora/process_audio".to_string());
+    let mut config = ServiceServerConfig::new(service_name);
+    config.qos = QosConfig::from_env_or("ROS2_SERVICE_QOS", config.qos)?;
+    if let Ok(timeout) = std::env::var("ROS2_SERVICE_TIMEOUT_MS") {
+        let timeout: u64 = timeout.parse()?;
+        config.timeout = std::time::Duration::from_millis(timeout);
+    }
+    config.request_output = DataId::from("process_audio_request".to_owned());
+
+    ServiceServerBridge::new(
+        ros_node,
+        ros2_client::ServiceTypeName::new("custom_msgs", "ProcessAudio"),
+        config,
+        |error| ProcessAudioResponse {
+            success: false,
+            message: error.to_string(),
+            processed_samples: 0,
+        },
+    )
+}
+
+fn create_audio_processing_client(
+    ros_node: &mut BridgeNode,
+) -> Result<ActionClientBridge<AudioProcessing>> {
+    let action_name = std::env::var("ROS2_AUDIO_PROCESSING_ACTION")
+        .unwrap_or_else(|_| "/custom/audio_processing".to_string());
+    let mut config = ActionClientConfig::new(action_name);
+    config.qos = ActionQosConfig::from_env("ROS2_ACTION")?;
+    config.feedback_output = DataId::from("audio_processing_feedback".to_owned());
+    config.status_output = DataId::from("audio_processing_status".to_owned());
+    config.result_output = DataId::from("audio_processing_result".to_owned());
+    config.error_output = DataId::from("audio_processing_error".to_owned());
+
+    ActionClientBridge::new(
+        ros_node,
+        ros2_client::ActionTypeName::new("custom_msgs", "AudioProcessing"),
+        config,
+    )
+}
+
+fn create_audio_processing_server(
+    ros_node: &mut BridgeNode,
+) -> Result<ActionServerBridge<AudioProcessing>> {
+    let action_name = std::env::var("ROS2_AUDIO_PROCESSING_SERVER")
+        .unwrap_or_else(|_| "/custom/dora/audio_processing".to_string());
+    let mut config = ActionServerConfig::new(action_name);
+    if let Ok(policy) = std::env::var("ROS2_GOAL_POLICY") {
+        config.policy = policy.parse()?;
+    }
+    config.qos = ActionQosConfig::from_env("ROS2_ACTION")?;
+    config.goal_output = DataId::from("audio_processing_server_goal".to_owned());
+    config.cancel_output = DataId::from("audio_processing_server_cancel".to_owned());
+
+    ActionServerBridge::new(
+        ros_node,
+        ros2_client::ActionTypeName::new("custom_msgs", "AudioProcessing"),
+        config,
+    )
+}
@@ -0,0 +1,47 @@
+use dora_node_api::{self, Event, MetadataParameters, Parameter, dora_core::config::DataId};
+use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, GraphMonitor,
+    graph::topics_to_arrow,
+    tracing::{info, warn},
+};
+
+fn main() -> eyre::Result<()> {
+    // Dora output ids for graph changes and topic snapshots
+    let graph_output = DataId::from("graph".to_owned());
+    let topics_output = DataId::from("topics".to_owned());
+
+    // --- ROS 2 setup: node + discovery ------------------------------------------------------
+    let mut ros_node = BridgeNode::builder("ros2_graph_monitor").build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting ROS2 graph monitor node");
+    let monitor = GraphMonitor::new(&ros_node)?;
+
+    // --- Dora: init and process events ------------------------------------------------------
+    let (mut node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
+
+    ros_node.run_with(dora_events, monitor.events(), |event| {
+        match event {
+            BridgeEvent::Dora(Event::Input { id, metadata, data: _ }) => match id.as_str() {
+                // send the full topic list on every tick
+                "tick" => {
+                    let topics = monitor.topics();
+                    let mut params: MetadataParameters = metadata.parameters;
+                    params.insert("count".into(), Parameter::Integer(topics.len() as i64));
+                    node.send_output(topics_output.clone(), params, topics_to_arrow(&topics))?;
+                }
+                other => warn!("Ignoring unexpected input `{other}`"),
+            },
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            BridgeEvent::Parameter(_) | BridgeEvent::Lifecycle(_) => {}
+            BridgeEvent::External(graph_event) => {
+                info!("graph change: {graph_event:?}");
+                node.send_output(
+                    graph_output.clone(),
+                    MetadataParameters::default(),
+                    graph_event.to_arrow(),
+                )?;
+            }
+        }
+        Ok(())
+    })
+}
@@ -16,11 +16,12 @@ fn main() -> eyre::Result<()> {
     rosrust::init("dora_ros1_image_node");
     let image_topic = std::env::var("ROS_IMAGE_TOPIC").unwrap_or_else(|_| "/camera/image_raw".to_string());
 
-    let latest_image: Arc<Mutex<Option<RosImage>>> = Arc::new(Mutex::new(None));
+    // Latest image together with the time it was received
+    let latest_image: Arc<Mutex<Option<(RosImage, rosrust::Time)>>> = Arc::new(Mutex::new(None));
     let latest_image_cb = Arc::clone(&latest_image);
     let _sub = rosrust::subscribe(&image_topic, 1, move |msg: RosImage| {
         if let Ok(mut slot) = latest_image_cb.lock() {
-            *slot = Some(msg);
+            *slot = Some((msg, rosrust::now()));
         }
     }).map_err(|e| eyre::eyre!("ros subscribe error: {e}"))?;
 
@@ -35,8 +36,14 @@ fn main() -> eyre::Result<()> {
                         guard.and_then(|mut g| g.take())
                     };
 
-                    if let Some(img) = maybe_image {
+                    if let Some((img, received)) = maybe_image {
                         let mut params: MetadataParameters = metadata.parameters;
+                        // Original header, so the ROS2 side can keep stamp and frame_id
+                        params.insert("stamp_sec".into(), Parameter::Integer(img.header.stamp.sec as i64));
+                        params.insert("stamp_nanosec".into(), Parameter::Integer(img.header.stamp.nsec as i64));
+                        params.insert("frame_id".into(), Parameter::String(img.header.frame_id.clone()));
+                        params.insert("receive_stamp_sec".into(), Parameter::Integer(received.sec as i64));
+                        params.insert("receive_stamp_nanosec".into(), Parameter::Integer(received.nsec as i64));
                         params.insert("width".into(), Parameter::Integer(img.width as i64));
                         params.insert("height".into(), Parameter::Integer(img.height as i64));
                         params.insert("encoding".into(), Parameter::String(img.encoding.clone()));
@@ -1,44 +1,83 @@
-use dora_node_api::{self, DoraNode, Event, Parameter};
+use std::sync::Arc;
+
+use dora_node_api::{
+    self, Event, Parameter,
+    arrow::{array::Array, datatypes::DataType},
+};
 use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, Diagnostics, DiagnosticsConfig, HeaderPolicy, LifecyclePublisher,
+    ParameterValue, Parameters, QosConfig, QosEventMonitor, Throttle, ThrottleConfig,
+    diagnostics::Channel,
+    header::receive_time_from_metadata,
     messages::sensor_msgs::msg::Image as Ros2Image,
-    messages::builtin_interfaces::msg::Time,
-    ros2_client::{self, NodeOptions, ros2},
-    rustdds::{self, policy},
+    ros2_client,
+    tracing::{debug, info, warn},
 };
-use eyre::{Context, eyre};
-use futures::task::SpawnExt;
+use eyre::Context;
 
 fn main() -> eyre::Result<()> {
-    println!("starting ROS2 image sink node");
-
     // Get ROS2 topic from environment variable, default to /camera/image_raw
     let ros2_topic = std::env::var("ROS2_TOPIC").unwrap_or_else(|_| "/camera/image_raw".to_string());
-    println!("ROS2 topic: {}", ros2_topic);
+    let frame_id = std::env::var("ROS2_FRAME_ID").unwrap_or_else(|_| "camera_frame".to_string());
+    let header_policy = HeaderPolicy::from_env("ROS2_HEADER")?;
+    let topic_qos = QosConfig::from_env_or("ROS2_QOS", QosConfig::default().keep_last(1))?;
 
     // --- ROS 2 setup: node + publisher + spinner --------------------------------------------
-    let mut ros_node = init_ros_node()?;
-    let image_publisher = create_image_publisher(&mut ros_node, &ros2_topic)?;
-
-    // background spinner (service discovery, executor, etc.)
-    let pool = futures::executor::ThreadPool::new()?;
-    let spinner = ros_node
-        .spinner()
-        .map_err(|e| eyre::eyre!("failed to create spinner: {e:?}"))?;
-    pool.spawn(async {
-        if let Err(err) = spinner.spin().await {
-            eprintln!("ros2 spinner failed: {err:?}");
-        }
-    })
-        .context("failed to spawn ros2 spinner")?;
+    let mut ros_node = BridgeNode::builder("ros2_image_sink")
+        .read_only_parameter("topic", ParameterValue::String(ros2_topic.clone()))
+        .read_only_parameter(
+            "qos_depth",
+            ParameterValue::Integer(topic_qos.depth.unwrap_or(1).into()),
+        )
+        .parameter("frame_id", ParameterValue::String(frame_id.clone()))
+        .build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting ROS2 image sink node on {ros2_topic}");
+    let parameters = ros_node.parameters().clone();
+    let clock = ros_node.clock().clone();
+    let topic_name = ros_node.resolve_name(&ros2_topic)?;
+    let image_publisher =
+        Arc::new(create_image_publisher(ros_node.node_mut(), &topic_name, &topic_qos)?);
+    // logs QoS problems, and sends them on ROS2_QOS_EVENTS_OUTPUT if set
+    let qos_events = QosEventMonitor::from_env(&ros_node)?;
+    qos_events.watch_publisher(&topic_name.to_string(), image_publisher.clone())?;
+    // only publishes while the node is active, see ROS2_LIFECYCLE
+    let image_publisher = LifecyclePublisher::new(image_publisher, ros_node.lifecycle());
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    let image_diagnostics = diagnostics.channel("image");
+    // e.g. `max_rate=10` to cap the frame rate over a slow link, see ROS2_THROTTLE
+    let mut throttle = ThrottleConfig::from_env("ROS2_THROTTLE")?.map(|config| {
+        let channel = image_diagnostics.clone();
+        Throttle::new(config).on_drop(move |dropped| channel.value("throttled", dropped))
+    });
 
     // --- Dora: init and process events ------------------------------------------------------
-    let (_node, dora_events) = DoraNode::init_from_env()?;
-    let mut events = futures::executor::block_on_stream(dora_events);
+    let (mut node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
 
-    while let Some(event) = events.next() {
+    ros_node.run_with(dora_events, qos_events.events(), |event| {
         match event {
-            Event::Input { id, metadata, data } => match id.as_str() {
+            BridgeEvent::Dora(Event::Input { id, metadata, data }) => match id.as_str() {
                 "image" => {
+                    if let Some(throttle) = &mut throttle {
+                        if !throttle.admit(data.0.get_array_memory_size()) {
+                            return Ok(());
+                        }
+                    }
+                    // Structured `sensor_msgs/Image` struct arrays keep their own header
+                    if matches!(data.data_type(), DataType::Struct(_)) {
+                        let mut ros2_image = Ros2Image::try_from(&data)
+                            .context("failed to convert image struct")?;
+                        ros2_image.header = header_policy.apply(
+                            Some(&ros2_image.header),
+                            receive_time_from_metadata(&metadata.parameters),
+                            &clock,
+                            &default_frame_id(&parameters, &frame_id),
+                        );
+                        debug!("publishing ROS2 image: {} bytes, {}x{}, encoding: {}",
+                                 ros2_image.data.len(), ros2_image.width, ros2_image.height, ros2_image.encoding);
+                        return publish_image(&image_publisher, ros2_image, &image_diagnostics);
+                    }
+
                     // Extract image metadata from Dora parameters
                     let width = metadata.parameters.get("width")
                         .and_then(|p| match p {
@@ -79,20 +118,14 @@ fn main() -> eyre::Result<()> {
                     let image_data: Vec<u8> = (&data).try_into()
                         .context("failed to convert image data to bytes")?;
 
-                    // Get current time for timestamp
-                    let now = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default();
-
                     // Create ROS2 Image message
                     let ros2_image = Ros2Image {
-                        header: dora_ros2_bridge::messages::std_msgs::msg::Header {
-                            stamp: Time {
-                                sec: now.as_secs() as i32,
-                                nanosec: now.subsec_nanos(),
-                            },
-                            frame_id: "camera_frame".to_string(),
-                        },
+                        // Keeps the ROS1 stamp and frame_id unless configured otherwise
+                        header: header_policy.apply_metadata(
+                            &metadata.parameters,
+                            &clock,
+                            &default_frame_id(&parameters, &frame_id),
+                        ),
                         height,
                         width,
                         encoding: encoding.clone(),
@@ -101,72 +134,54 @@ fn main() -> eyre::Result<()> {
                         data: image_data,
                     };
 
-                    println!("publishing ROS2 image: {} bytes, {}x{}, encoding: {}", 
+                    debug!("publishing ROS2 image: {} bytes, {}x{}, encoding: {}", 
                              ros2_image.data.len(), width, height, encoding);
                     
-                    image_publisher.publish(ros2_image)
-                        .map_err(|e| eyre::eyre!("failed to publish image: {e:?}"))?;
+                    publish_image(&image_publisher, ros2_image, &image_diagnostics)?;
                 }
-                other => eprintln!("Ignoring unexpected input `{other}`"),
+                other => warn!("Ignoring unexpected input `{other}`"),
             },
-            Event::Stop(_) => {
-                println!("Received stop");
-                break;
-            }
-            other => eprintln!("Received unexpected input: {other:?}"),
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            BridgeEvent::External(qos_event) => qos_events.send_output(&mut node, &qos_event)?,
+            BridgeEvent::Parameter(_) | BridgeEvent::Lifecycle(_) => {}
         }
-    }
+        Ok(())
+    })
+}
 
+fn publish_image(
+    publisher: &LifecyclePublisher<Ros2Image>,
+    image: Ros2Image,
+    diagnostics: &Channel,
+) -> eyre::Result<()> {
+    let bytes = image.data.len();
+    let published = publisher
+        .publish(image)
+        .context("failed to publish image")
+        .inspect_err(|e| diagnostics.error(e))?;
+    if published {
+        diagnostics.message(bytes);
+    }
     Ok(())
 }
 
-fn init_ros_node() -> eyre::Result<ros2_client::Node> {
-    let ros_context = dora_ros2_bridge::create_ros2_context().unwrap();
-
-    ros_context
-        .new_node(
-            ros2_client::NodeName::new("/dora", "ros2_image_sink")
-                .map_err(|e| eyre!("failed to create ROS2 node name: {e}"))?,
-            NodeOptions::new().enable_rosout(true),
-        )
-        .map_err(|e| eyre::eyre!("failed to create ros2 node: {e:?}"))
+/// `frame_id` parameter, used for images that arrive without a frame_id.
+fn default_frame_id(parameters: &Parameters, frame_id: &str) -> String {
+    parameters
+        .get_string("frame_id")
+        .unwrap_or_else(|| frame_id.to_owned())
 }
 
 fn create_image_publisher(
     ros_node: &mut ros2_client::Node,
-    topic_name: &str,
+    topic_name: &ros2_client::Name,
+    topic_qos: &QosConfig,
 ) -> eyre::Result<ros2_client::Publisher<Ros2Image>> {
-    let topic_qos: rustdds::QosPolicies = {
-        rustdds::QosPolicyBuilder::new()
-            .durability(policy::Durability::Volatile)
-            .liveliness(policy::Liveliness::Automatic {
-                lease_duration: ros2::Duration::INFINITE,
-            })
-            .reliability(policy::Reliability::Reliable {
-                max_blocking_time: ros2::Duration::from_millis(100),
-            })
-            .history(policy::History::KeepLast { depth: 1 })
-            .build()
-    };
-
-    // Parse topic name (e.g., "/camera/image_raw" -> namespace: "/camera", name: "image_raw")
-    let (namespace, name) = if topic_name.starts_with('/') {
-        let parts: Vec<&str> = topic_name.split('/').filter(|s| !s.is_empty()).collect();
-        if parts.len() >= 2 {
-            (format!("/{}", parts[0]), parts[1..].join("/"))
-        } else {
-            ("/".to_string(), "image_raw".to_string())
-        }
-    } else {
-        ("/".to_string(), topic_name.to_string())
-    };
-
     let image_topic = ros_node
         .create_topic(
-            &ros2_client::Name::new(&namespace, &name)
-                .map_err(|e| eyre!("failed to create ROS2 name: {e}"))?,
+            topic_name,
             ros2_client::MessageTypeName::new("sensor_msgs", "Image"),
-            &topic_qos,
+            &topic_qos.to_policies(),
         )
         .context("failed to create topic")?;
 
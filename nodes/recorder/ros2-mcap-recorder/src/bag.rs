@@ -0,0 +1,425 @@
+//! A rosbag2 bag of MCAP files: a directory with the recordings and a
+//! `metadata.yaml` describing them, readable by `ros2 bag info` and `ros2 bag play`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use dora_ros2_bridge::{
+    rustdds::{self, QosPolicies, policy},
+    tracing::info,
+};
+use eyre::{Context, ContextCompat, bail};
+use serde::Serialize;
+
+use crate::config::{Compression, SplitConfig};
+
+/// Version of the rosbag2 metadata format written, supported by Humble and later.
+const METADATA_VERSION: u32 = 5;
+const METADATA_FILE: &str = "metadata.yaml";
+
+/// `rmw_time_t` of an infinite duration, as written by Humble.
+const INFINITE_TIME: RmwTime = RmwTime {
+    sec: 9_223_372_036,
+    nsec: 854_775_807,
+};
+
+/// A recorded topic, registered with [`BagWriter::add_topic`].
+struct Topic {
+    /// Type like `sensor_msgs/msg/Image`.
+    type_name: String,
+    /// `ros2msg` schema, if the message definition was found.
+    schema: Option<String>,
+    /// QoS of the writers, see [`offered_qos_profiles`].
+    offered_qos_profiles: String,
+    message_count: u64,
+}
+
+struct OpenFile {
+    writer: mcap::Writer<BufWriter<File>>,
+    /// Channel id of each topic, added when its first message is written to the file.
+    channels: HashMap<String, u16>,
+    opened: Instant,
+    /// Payload bytes written, an upper bound of the (compressed) file size.
+    bytes: u64,
+    info: FileInfo,
+}
+
+#[derive(Debug, Clone)]
+struct FileInfo {
+    path: String,
+    starting_time: u64,
+    end_time: u64,
+    message_count: u64,
+}
+
+pub struct BagWriter {
+    directory: PathBuf,
+    split: SplitConfig,
+    compression: Compression,
+    topics: BTreeMap<String, Topic>,
+    file: Option<OpenFile>,
+    files: Vec<FileInfo>,
+    sequence: u32,
+}
+
+impl BagWriter {
+    /// Create the bag directory; like `ros2 bag record`, existing bags are not overwritten.
+    pub fn create(
+        directory: impl Into<PathBuf>,
+        split: SplitConfig,
+        compression: Compression,
+    ) -> eyre::Result<Self> {
+        let directory = directory.into();
+        if directory.exists() {
+            bail!("bag directory `{}` already exists", directory.display());
+        }
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("failed to create bag directory `{}`", directory.display()))?;
+        Ok(Self {
+            directory,
+            split,
+            compression,
+            topics: BTreeMap::new(),
+            file: None,
+            files: Vec::new(),
+            sequence: 0,
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn message_count(&self) -> u64 {
+        self.topics.values().map(|t| t.message_count).sum()
+    }
+
+    /// Register a topic before writing its messages.
+    pub fn add_topic(
+        &mut self,
+        topic: &str,
+        type_name: &str,
+        schema: Option<String>,
+        offered_qos_profiles: String,
+    ) {
+        self.topics.insert(
+            topic.to_owned(),
+            Topic {
+                type_name: type_name.to_owned(),
+                schema,
+                offered_qos_profiles,
+                message_count: 0,
+            },
+        );
+    }
+
+    /// Write a serialized message (including its CDR encapsulation header),
+    /// received at `time` in nanoseconds since the UNIX epoch.
+    pub fn write(&mut self, topic: &str, message: &[u8], time: u64) -> eyre::Result<()> {
+        if self.file.as_ref().is_some_and(|file| self.split_due(file)) {
+            self.close_file()?;
+        }
+        if self.file.is_none() {
+            self.open_file(time)?;
+        }
+        let file = self.file.as_mut().context("no open bag file")?;
+        let entry = self
+            .topics
+            .get_mut(topic)
+            .with_context(|| format!("topic `{topic}` was not added to the bag"))?;
+        let channel_id = match file.channels.get(topic) {
+            Some(id) => *id,
+            None => {
+                let id = add_channel(&mut file.writer, topic, entry)?;
+                file.channels.insert(topic.to_owned(), id);
+                id
+            }
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        file.writer
+            .write_to_known_channel(
+                &mcap::records::MessageHeader {
+                    channel_id,
+                    sequence: self.sequence,
+                    log_time: time,
+                    publish_time: time,
+                },
+                message,
+            )
+            .with_context(|| format!("failed to write message of `{topic}`"))?;
+        file.bytes += message.len() as u64;
+        file.info.message_count += 1;
+        file.info.starting_time = file.info.starting_time.min(time);
+        file.info.end_time = file.info.end_time.max(time);
+        entry.message_count += 1;
+        Ok(())
+    }
+
+    /// Close the current file and write the final `metadata.yaml`.
+    pub fn finish(&mut self) -> eyre::Result<()> {
+        self.close_file()?;
+        self.write_metadata()
+    }
+
+    fn split_due(&self, file: &OpenFile) -> bool {
+        self.split.max_size.is_some_and(|max| file.bytes >= max)
+            || self
+                .split
+                .max_duration
+                .is_some_and(|max| file.opened.elapsed() >= max)
+    }
+
+    fn open_file(&mut self, time: u64) -> eyre::Result<()> {
+        let name = self
+            .directory
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "rosbag2".to_owned());
+        let path = format!("{name}_{}.mcap", self.files.len());
+        let full_path = self.directory.join(&path);
+        let out = File::create(&full_path)
+            .with_context(|| format!("failed to create `{}`", full_path.display()))?;
+        let writer = mcap::WriteOptions::new()
+            .profile("ros2")
+            .compression(self.compression.to_mcap())
+            .create(BufWriter::new(out))
+            .with_context(|| format!("failed to start MCAP file `{}`", full_path.display()))?;
+        info!("recording to `{}`", full_path.display());
+        self.file = Some(OpenFile {
+            writer,
+            channels: HashMap::new(),
+            opened: Instant::now(),
+            bytes: 0,
+            info: FileInfo {
+                path,
+                starting_time: time,
+                end_time: time,
+                message_count: 0,
+            },
+        });
+        Ok(())
+    }
+
+    fn close_file(&mut self) -> eyre::Result<()> {
+        let Some(mut file) = self.file.take() else {
+            return Ok(());
+        };
+        file.writer
+            .finish()
+            .with_context(|| format!("failed to finish MCAP file `{}`", file.info.path))?;
+        self.files.push(file.info);
+        // keep the metadata current, so the bag stays usable if the recorder is killed
+        self.write_metadata()
+    }
+
+    fn write_metadata(&self) -> eyre::Result<()> {
+        let starting_time = self
+            .files
+            .iter()
+            .map(|f| f.starting_time)
+            .min()
+            .unwrap_or(0);
+        let end_time = self.files.iter().map(|f| f.end_time).max().unwrap_or(0);
+        let metadata = Metadata {
+            rosbag2_bagfile_information: BagInfo {
+                version: METADATA_VERSION,
+                storage_identifier: "mcap",
+                duration: Nanoseconds {
+                    nanoseconds: end_time - starting_time,
+                },
+                starting_time: NanosecondsSinceEpoch {
+                    nanoseconds_since_epoch: starting_time,
+                },
+                message_count: self.files.iter().map(|f| f.message_count).sum(),
+                topics_with_message_count: self
+                    .topics
+                    .iter()
+                    .map(|(name, topic)| TopicWithMessageCount {
+                        topic_metadata: TopicMetadata {
+                            name,
+                            r#type: &topic.type_name,
+                            serialization_format: "cdr",
+                            offered_qos_profiles: &topic.offered_qos_profiles,
+                        },
+                        message_count: topic.message_count,
+                    })
+                    .collect(),
+                compression_format: "",
+                compression_mode: "",
+                relative_file_paths: self.files.iter().map(|f| f.path.as_str()).collect(),
+                files: self
+                    .files
+                    .iter()
+                    .map(|f| FileMetadata {
+                        path: &f.path,
+                        starting_time: NanosecondsSinceEpoch {
+                            nanoseconds_since_epoch: f.starting_time,
+                        },
+                        duration: Nanoseconds {
+                            nanoseconds: f.end_time - f.starting_time,
+                        },
+                        message_count: f.message_count,
+                    })
+                    .collect(),
+            },
+        };
+        let path = self.directory.join(METADATA_FILE);
+        let yaml = serde_yaml::to_string(&metadata).context("failed to serialize bag metadata")?;
+        std::fs::write(&path, yaml).with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
+
+fn add_channel(
+    writer: &mut mcap::Writer<BufWriter<File>>,
+    topic: &str,
+    entry: &Topic,
+) -> eyre::Result<u16> {
+    // schema id 0 marks a channel without schema
+    let schema_id = match &entry.schema {
+        Some(schema) => writer
+            .add_schema(&entry.type_name, "ros2msg", schema.as_bytes())
+            .with_context(|| format!("failed to add schema of `{topic}`"))?,
+        None => 0,
+    };
+    let metadata = BTreeMap::from([(
+        "offered_qos_profiles".to_owned(),
+        entry.offered_qos_profiles.clone(),
+    )]);
+    writer
+        .add_channel(schema_id, topic, "cdr", &metadata)
+        .with_context(|| format!("failed to add channel for `{topic}`"))
+}
+
+/// The rosbag2 `offered_qos_profiles` of a topic whose writers announced `writer_qos`.
+///
+/// A YAML list of `rmw_qos_profile_t`s with the rmw enum values, which `ros2
+/// bag play` uses to publish with the recorded QoS. Policies missing from the
+/// announcement have their DDS default for writers; the history is not
+/// announced, so like in `ros2 bag record` it is unknown.
+pub fn offered_qos_profiles(writer_qos: Option<&QosPolicies>) -> String {
+    let Some(qos) = writer_qos else {
+        return String::new();
+    };
+    let (history, depth) = match qos.history() {
+        Some(policy::History::KeepLast { depth }) => (1, depth.max(0) as u32),
+        Some(policy::History::KeepAll) => (2, 0),
+        None => (3, 0),
+    };
+    let (liveliness, lease_duration) = match qos.liveliness() {
+        Some(policy::Liveliness::Automatic { lease_duration }) => (1, lease_duration),
+        Some(policy::Liveliness::ManualByParticipant { lease_duration }) => (2, lease_duration),
+        Some(policy::Liveliness::ManualByTopic { lease_duration }) => (3, lease_duration),
+        None => (1, rustdds::Duration::INFINITE),
+    };
+    let profile = QosProfile {
+        history,
+        depth,
+        reliability: match qos.reliability() {
+            Some(policy::Reliability::BestEffort) => 2,
+            Some(policy::Reliability::Reliable { .. }) | None => 1,
+        },
+        durability: match qos.durability() {
+            Some(policy::Durability::Volatile) | None => 2,
+            Some(_) => 1,
+        },
+        deadline: qos
+            .deadline()
+            .map_or(INFINITE_TIME, |policy::Deadline(d)| RmwTime::from(d)),
+        lifespan: qos
+            .lifespan()
+            .map_or(INFINITE_TIME, |l| RmwTime::from(l.duration)),
+        liveliness,
+        liveliness_lease_duration: RmwTime::from(lease_duration),
+        avoid_ros_namespace_conventions: false,
+    };
+    serde_yaml::to_string(&[profile]).unwrap_or_default()
+}
+
+/// `rmw_qos_profile_t` as written to `offered_qos_profiles`.
+#[derive(Serialize)]
+struct QosProfile {
+    history: u8,
+    depth: u32,
+    reliability: u8,
+    durability: u8,
+    deadline: RmwTime,
+    lifespan: RmwTime,
+    liveliness: u8,
+    liveliness_lease_duration: RmwTime,
+    avoid_ros_namespace_conventions: bool,
+}
+
+#[derive(Serialize)]
+struct RmwTime {
+    sec: u64,
+    nsec: u64,
+}
+
+impl From<rustdds::Duration> for RmwTime {
+    fn from(duration: rustdds::Duration) -> Self {
+        if duration == rustdds::Duration::INFINITE {
+            return INFINITE_TIME;
+        }
+        let nanos = duration.to_nanoseconds().max(0) as u64;
+        Self {
+            sec: nanos / 1_000_000_000,
+            nsec: nanos % 1_000_000_000,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    rosbag2_bagfile_information: BagInfo<'a>,
+}
+
+#[derive(Serialize)]
+struct BagInfo<'a> {
+    version: u32,
+    storage_identifier: &'a str,
+    duration: Nanoseconds,
+    starting_time: NanosecondsSinceEpoch,
+    message_count: u64,
+    topics_with_message_count: Vec<TopicWithMessageCount<'a>>,
+    compression_format: &'a str,
+    compression_mode: &'a str,
+    relative_file_paths: Vec<&'a str>,
+    files: Vec<FileMetadata<'a>>,
+}
+
+#[derive(Serialize)]
+struct Nanoseconds {
+    nanoseconds: u64,
+}
+
+#[derive(Serialize)]
+struct NanosecondsSinceEpoch {
+    nanoseconds_since_epoch: u64,
+}
+
+#[derive(Serialize)]
+struct TopicWithMessageCount<'a> {
+    topic_metadata: TopicMetadata<'a>,
+    message_count: u64,
+}
+
+#[derive(Serialize)]
+struct TopicMetadata<'a> {
+    name: &'a str,
+    r#type: &'a str,
+    serialization_format: &'a str,
+    offered_qos_profiles: &'a str,
+}
+
+#[derive(Serialize)]
+struct FileMetadata<'a> {
+    path: &'a str,
+    starting_time: NanosecondsSinceEpoch,
+    duration: Nanoseconds,
+    message_count: u64,
+}
@@ -0,0 +1,213 @@
+//! Recorder configuration, read from `RECORDER_CONFIG` (file) or `RECORDER` (inline YAML).
+
+use std::{path::PathBuf, time::Duration};
+
+use dora_ros2_bridge::{QosConfig, TopicPattern, qos::QosDuration, throttle::parse_size};
+use eyre::bail;
+use serde::Deserialize;
+
+/// Env variable pointing to a YAML file with the recorder config.
+const CONFIG_FILE_ENV: &str = "RECORDER_CONFIG";
+/// Env variable holding the recorder config as inline YAML.
+const CONFIG_INLINE_ENV: &str = "RECORDER";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecorderConfig {
+    /// Bag directory, `rosbag2_<date>-<time>` (UTC) in the working directory by default.
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    /// Topics to record, by name or pattern.
+    #[serde(default)]
+    pub topics: Vec<TopicSelector>,
+    /// Record all discovered topics, like `ros2 bag record -a`.
+    #[serde(default)]
+    pub all: bool,
+    /// Topics never recorded, by name or pattern.
+    #[serde(default)]
+    pub exclude: Vec<TopicPattern>,
+    #[serde(default)]
+    pub split: SplitConfig,
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+impl RecorderConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.topics.is_empty() && !self.all {
+            bail!("no topics to record, list them in `topics` or set `all: true`");
+        }
+        if self.split.max_size == Some(0) {
+            bail!("`split.max_size` must be greater than zero");
+        }
+        if self.split.max_duration.is_some_and(|d| d.is_zero()) {
+            bail!("`split.max_duration` must be greater than zero");
+        }
+        Ok(())
+    }
+
+    /// The selector matching `topic`, if it is to be recorded.
+    ///
+    /// With `all: true`, topics without a selector are recorded with the
+    /// default selector.
+    pub fn selector(&self, topic: &str) -> Option<TopicSelector> {
+        if self.exclude.iter().any(|p| p.matches(topic)) {
+            return None;
+        }
+        match self.topics.iter().find(|s| s.topic.matches(topic)) {
+            Some(selector) => Some(selector.clone()),
+            None if self.all => Some(TopicSelector::from(TopicPattern::any())),
+            None => None,
+        }
+    }
+
+    pub fn output(&self) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(default_bag_name()))
+    }
+}
+
+/// A topic to record, given as a name or pattern, or with its type and QoS.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "RawSelector")]
+pub struct TopicSelector {
+    pub topic: TopicPattern,
+    /// Expected message type; topics of other types are not recorded.
+    pub message_type: Option<String>,
+    /// QoS of the subscription, compatible with the discovered writers by default.
+    pub qos: Option<QosConfig>,
+}
+
+impl From<TopicPattern> for TopicSelector {
+    fn from(topic: TopicPattern) -> Self {
+        Self {
+            topic,
+            message_type: None,
+            qos: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSelector {
+    Pattern(TopicPattern),
+    Full {
+        topic: TopicPattern,
+        #[serde(default, rename = "type")]
+        message_type: Option<String>,
+        #[serde(default)]
+        qos: Option<QosConfig>,
+    },
+}
+
+impl From<RawSelector> for TopicSelector {
+    fn from(raw: RawSelector) -> Self {
+        match raw {
+            RawSelector::Pattern(topic) => topic.into(),
+            RawSelector::Full {
+                topic,
+                message_type,
+                qos,
+            } => Self {
+                topic,
+                message_type,
+                qos,
+            },
+        }
+    }
+}
+
+/// When to start a new file in the bag, like `ros2 bag record --max-bag-size`
+/// and `--max-bag-duration`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplitConfig {
+    /// Approximate file size, like `512MiB` or `2GB`.
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// Recording duration per file, like `300s`.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_duration: Option<Duration>,
+}
+
+fn deserialize_size<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Raw>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Raw::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Raw::Text(text)) => parse_size(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+fn deserialize_duration<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    match Option::<QosDuration>::deserialize(deserializer)? {
+        None | Some(QosDuration::Infinite) => Ok(None),
+        Some(QosDuration::Finite(duration)) => Ok(Some(duration)),
+    }
+}
+
+/// Chunk compression of the MCAP files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    #[default]
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    pub fn to_mcap(self) -> Option<mcap::Compression> {
+        match self {
+            Self::None => None,
+            Self::Zstd => Some(mcap::Compression::Zstd),
+            Self::Lz4 => Some(mcap::Compression::Lz4),
+        }
+    }
+}
+
+/// Load the recorder config from `RECORDER_CONFIG` (file) or `RECORDER` (inline YAML).
+pub fn load() -> eyre::Result<RecorderConfig> {
+    let config: RecorderConfig =
+        dora_ros2_bridge::config::load_yaml(CONFIG_FILE_ENV, CONFIG_INLINE_ENV, "recorder")?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// `rosbag2_YYYY_MM_DD-HH_MM_SS` for the current UTC time, like `ros2 bag record`.
+fn default_bag_name() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "rosbag2_{year:04}_{month:02}_{day:02}-{:02}_{:02}_{:02}",
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
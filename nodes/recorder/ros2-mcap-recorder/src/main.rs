@@ -0,0 +1,218 @@
+use std::{collections::HashSet, sync::Arc};
+
+use dora_node_api::{self, Event};
+use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, Diagnostics, DiagnosticsConfig, GraphEvent, GraphMonitor, QosConfig,
+    config::{Messages, load_messages, writer_compatible_qos},
+    flume,
+    graph::TopicInfo,
+    raw::{self, RawSample},
+    tracing::{info, warn},
+};
+use eyre::Context;
+use futures::{StreamExt, stream};
+
+use crate::{
+    bag::{BagWriter, offered_qos_profiles},
+    config::RecorderConfig,
+};
+
+mod bag;
+mod config;
+mod schema;
+
+/// A sample received on a recorded topic.
+struct Recorded {
+    topic: Arc<str>,
+    sample: RawSample,
+    /// Receive time on the node's ROS clock, in nanoseconds since the UNIX epoch.
+    time: u64,
+}
+
+enum External {
+    Sample(Recorded),
+    Graph(GraphEvent),
+}
+
+fn main() -> eyre::Result<()> {
+    let config = config::load()?;
+    let messages = load_messages()?;
+
+    // --- ROS 2 setup ------------------------------------------------------------------------
+    let mut ros_node = BridgeNode::builder("ros2_mcap_recorder").build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting ROS2 MCAP recorder node");
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    let monitor = GraphMonitor::new(&ros_node)?;
+
+    let mut bag = BagWriter::create(config.output(), config.split.clone(), config.compression)?;
+    info!("recording bag `{}`", bag.directory().display());
+
+    let (samples_tx, samples_rx) = flume::unbounded();
+    let external = stream::select(
+        samples_rx.into_stream().map(External::Sample),
+        monitor.events().map(External::Graph),
+    );
+    let mut recorder = Recorder {
+        config,
+        messages,
+        monitor,
+        recording: HashSet::new(),
+        samples_tx,
+        diagnostics,
+    };
+
+    // --- Dora: init and process events ------------------------------------------------------
+    let (_node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
+
+    let result = ros_node.run_with_node(dora_events, external, |ros_node, event| {
+        match event {
+            BridgeEvent::External(External::Sample(recorded)) => {
+                // samples are dropped while a managed node is paused
+                if ros_node.is_active() {
+                    let message = recorded.sample.to_serialized_message();
+                    bag.write(&recorded.topic, &message, recorded.time)?;
+                    recorder
+                        .diagnostics
+                        .channel(&recorded.topic)
+                        .message(message.len());
+                }
+            }
+            BridgeEvent::External(External::Graph(
+                GraphEvent::TopicAppeared(topic) | GraphEvent::TopicChanged(topic),
+            )) => recorder.discovered(ros_node, &topic, &mut bag),
+            BridgeEvent::External(External::Graph(_)) => {}
+            BridgeEvent::Lifecycle(transition) => info!(
+                "lifecycle `{}`: {} -> {}, recording {}",
+                transition.transition,
+                transition.start,
+                transition.goal,
+                if ros_node.is_active() {
+                    "resumed"
+                } else {
+                    "paused"
+                }
+            ),
+            BridgeEvent::Parameter(_) => {}
+            BridgeEvent::Dora(Event::Input { id, .. }) => warn!("Ignoring unexpected input `{id}`"),
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+        }
+        Ok(())
+    });
+
+    // finish the bag even if recording failed, so what was recorded stays readable
+    let finished = bag.finish().context("failed to finish bag");
+    info!(
+        "recorded {} messages to `{}`",
+        bag.message_count(),
+        bag.directory().display()
+    );
+    result.and(finished)
+}
+
+/// Subscribes to the topics selected in the config as they are discovered.
+struct Recorder {
+    config: RecorderConfig,
+    messages: Messages,
+    monitor: GraphMonitor,
+    recording: HashSet<String>,
+    samples_tx: flume::Sender<Recorded>,
+    diagnostics: Diagnostics,
+}
+
+impl Recorder {
+    fn discovered(&mut self, ros_node: &mut BridgeNode, topic: &TopicInfo, bag: &mut BagWriter) {
+        // like `ros2 bag record`, subscriptions are kept when publishers go away
+        if topic.publishers == 0 || self.recording.contains(&topic.name) {
+            return;
+        }
+        let Some(selector) = self.config.selector(&topic.name) else {
+            return;
+        };
+        let [type_name] = topic.type_names.as_slice() else {
+            warn!(
+                "not recording `{}`: conflicting types {:?}",
+                topic.name, topic.type_names
+            );
+            return;
+        };
+        let Ok((package, name)) = raw::split_type(type_name, "msg") else {
+            warn!(
+                "not recording `{}`: unsupported type `{type_name}`",
+                topic.name
+            );
+            return;
+        };
+        if let Some(expected) = &selector.message_type {
+            if raw::split_type(expected, "msg").ok() != Some((package, name)) {
+                warn!(
+                    "not recording `{}`: type `{type_name}` is not the configured `{expected}`",
+                    topic.name
+                );
+                return;
+            }
+        }
+        let qos = selector
+            .qos
+            .unwrap_or_else(|| writer_compatible_qos(&self.monitor, &topic.name));
+        let schema = match schema::ros2msg(&self.messages, package, name) {
+            Ok(schema) => Some(schema),
+            Err(err) => {
+                warn!("recording `{}` without schema: {err}", topic.name);
+                None
+            }
+        };
+
+        match self.subscribe(ros_node, &topic.name, type_name, &qos) {
+            Ok(()) => {
+                info!("recording `{}` ({package}/msg/{name})", topic.name);
+                // like the subscription QoS, ask again for the writer QoS
+                let offered_qos = self.monitor.topic(&topic.name).and_then(|t| t.qos);
+                bag.add_topic(
+                    &topic.name,
+                    &format!("{package}/msg/{name}"),
+                    schema,
+                    offered_qos_profiles(offered_qos.as_ref()),
+                );
+                self.recording.insert(topic.name.clone());
+            }
+            Err(err) => warn!("not recording `{}`: {err:?}", topic.name),
+        }
+    }
+
+    fn subscribe(
+        &self,
+        ros_node: &BridgeNode,
+        topic: &str,
+        type_name: &str,
+        qos: &QosConfig,
+    ) -> eyre::Result<()> {
+        let reader = raw::create_reader(ros_node, topic, type_name, &qos.to_policies())?;
+        let samples_tx = self.samples_tx.clone();
+        let channel = self.diagnostics.channel(topic);
+        let clock = ros_node.clock().clone();
+        let topic: Arc<str> = topic.into();
+        ros_node.spawn(async move {
+            let samples = reader.async_sample_stream();
+            futures::pin_mut!(samples);
+            while let Some(sample) = samples.next().await {
+                match sample {
+                    Ok(sample) => {
+                        let recorded = Recorded {
+                            topic: topic.clone(),
+                            sample: sample.into_value(),
+                            time: clock.since_epoch().as_nanos() as u64,
+                        };
+                        if samples_tx.send(recorded).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        warn!("failed to read `{topic}`: {err:?}");
+                        channel.error(format!("{err:?}"));
+                    }
+                }
+            }
+        })
+    }
+}
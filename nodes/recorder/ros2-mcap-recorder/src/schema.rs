@@ -0,0 +1,105 @@
+//! `ros2msg` schemas, built from the parsed `.msg` definitions.
+//!
+//! MCAP readers like Foxglove decode the recorded CDR payloads with these, in
+//! the same format as `rosbag2_storage_mcap`: the message definition followed
+//! by the definitions of all nested types, each after a separator line.
+
+use std::collections::{HashSet, VecDeque};
+
+use dora_ros2_bridge::config::Messages;
+use dora_ros2_bridge_msg_gen::types::{
+    MemberType, NestableType,
+    primitives::{BasicType, GenericString},
+};
+use eyre::ContextCompat;
+
+const SEPARATOR: &str =
+    "================================================================================";
+
+/// The `ros2msg` schema of `package/Name`.
+///
+/// Constants are left out, they are not needed to decode messages.
+pub fn ros2msg(messages: &Messages, package: &str, name: &str) -> eyre::Result<String> {
+    let mut schema = String::new();
+    let mut seen = HashSet::new();
+    let mut pending = VecDeque::from([(package.to_owned(), name.to_owned())]);
+    while let Some((package, name)) = pending.pop_front() {
+        if !seen.insert((package.clone(), name.clone())) {
+            continue;
+        }
+        let message = messages
+            .get(&package)
+            .and_then(|m| m.get(&name))
+            .with_context(|| format!("no definition found for `{package}/{name}`"))?;
+        if seen.len() > 1 {
+            schema.push_str(SEPARATOR);
+            schema.push_str(&format!("\nMSG: {package}/{name}\n"));
+        }
+        for member in &message.members {
+            let (type_name, nested) = member_type(&member.r#type, &package);
+            schema.push_str(&format!("{type_name} {}\n", member.name));
+            pending.extend(nested);
+        }
+    }
+    Ok(schema)
+}
+
+/// The `.msg` type of a member, and the nested message type it refers to.
+fn member_type(member: &MemberType, package: &str) -> (String, Option<(String, String)>) {
+    match member {
+        MemberType::NestableType(t) => nestable_type(t, package),
+        MemberType::Array(a) => {
+            let (name, nested) = nestable_type(&a.value_type, package);
+            (format!("{name}[{}]", a.size), nested)
+        }
+        MemberType::Sequence(s) => {
+            let (name, nested) = nestable_type(&s.value_type, package);
+            (format!("{name}[]"), nested)
+        }
+        MemberType::BoundedSequence(s) => {
+            let (name, nested) = nestable_type(&s.value_type, package);
+            (format!("{name}[<={}]", s.max_size), nested)
+        }
+    }
+}
+
+fn nestable_type(t: &NestableType, package: &str) -> (String, Option<(String, String)>) {
+    match t {
+        NestableType::BasicType(t) => (basic_type(t).to_owned(), None),
+        NestableType::NamedType(t) => (
+            format!("{package}/{}", t.0),
+            Some((package.to_owned(), t.0.clone())),
+        ),
+        NestableType::NamespacedType(t) => (
+            format!("{}/{}", t.package, t.name),
+            Some((t.package.clone(), t.name.clone())),
+        ),
+        NestableType::GenericString(s) => {
+            let name = match s {
+                GenericString::String => "string".to_owned(),
+                GenericString::BoundedString(n) => format!("string<={n}"),
+                GenericString::WString => "wstring".to_owned(),
+                GenericString::BoundedWString(n) => format!("wstring<={n}"),
+            };
+            (name, None)
+        }
+    }
+}
+
+fn basic_type(t: &BasicType) -> &'static str {
+    match t {
+        BasicType::Bool => "bool",
+        BasicType::Byte => "byte",
+        BasicType::Char => "char",
+        BasicType::I8 => "int8",
+        BasicType::U8 => "uint8",
+        BasicType::I16 => "int16",
+        BasicType::U16 => "uint16",
+        BasicType::I32 => "int32",
+        BasicType::U32 => "uint32",
+        BasicType::I64 => "int64",
+        BasicType::U64 => "uint64",
+        BasicType::F32 => "float32",
+        BasicType::F64 => "float64",
+    }
+}
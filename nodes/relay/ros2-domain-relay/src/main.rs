@@ -0,0 +1,52 @@
+use dora_node_api::{self, Event};
+use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, DomainRelay, RelayConfig, Ros2ContextConfig,
+    futures::stream,
+    tracing::{info, warn},
+};
+use eyre::Context;
+
+/// Env variable pointing to a YAML file with the relay config.
+const CONFIG_FILE_ENV: &str = "RELAY_CONFIG";
+/// Env variable holding the relay config as inline YAML.
+const CONFIG_INLINE_ENV: &str = "RELAY";
+
+fn main() -> eyre::Result<()> {
+    let config: RelayConfig =
+        dora_ros2_bridge::config::load_yaml(CONFIG_FILE_ENV, CONFIG_INLINE_ENV, "relay")?;
+    config.validate()?;
+    let a_context = match &config.a {
+        Some(a) => a.clone(),
+        None => Ros2ContextConfig::from_env().context("invalid ROS2 context configuration")?,
+    };
+
+    // --- ROS 2 setup: one node (and DDS participant) per domain -----------------------------
+    let mut a_node = BridgeNode::builder("ros2_domain_relay")
+        .context(a_context)
+        .build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut a_node)?;
+    info!("starting ROS2 domain relay node");
+    let b_node = BridgeNode::builder("ros2_domain_relay")
+        .context(config.b.clone())
+        .build()?;
+    let relay = DomainRelay::new(&a_node, &b_node, &config)?;
+    info!("relaying {} topics and services", relay.routes().len());
+
+    // --- Dora: init and process events ------------------------------------------------------
+    let (_node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
+
+    // relaying runs in the background until the dataflow is stopped; `run_with`
+    // watches the spinner of `a_node`, the one of `b_node` is merged in
+    let b_failed = stream::once(b_node.spinner_failure());
+    a_node.run_with(dora_events, b_failed, |event| {
+        match event {
+            BridgeEvent::External(err) => {
+                return Err(err.wrap_err("ROS2 node in the B domain failed"));
+            }
+            BridgeEvent::Dora(Event::Input { id, .. }) => warn!("Ignoring unexpected input `{id}`"),
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            BridgeEvent::Parameter(_) | BridgeEvent::Lifecycle(_) => {}
+        }
+        Ok(())
+    })
+}
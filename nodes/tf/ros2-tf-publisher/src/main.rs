@@ -0,0 +1,81 @@
+use dora_node_api::{self, Event, arrow::array::Array};
+use dora_ros2_bridge::{
+    BridgeEvent, BridgeNode, Diagnostics, DiagnosticsConfig, LifecycleState, StaticTransform,
+    TfPublisher,
+    config::load_yaml,
+    futures::stream,
+    tracing::{info, warn},
+};
+
+/// Env variable pointing to a YAML file with static transforms.
+const STATIC_TRANSFORMS_FILE_ENV: &str = "ROS2_STATIC_TRANSFORMS_FILE";
+/// Env variable holding static transforms as inline YAML.
+const STATIC_TRANSFORMS_ENV: &str = "ROS2_STATIC_TRANSFORMS";
+
+fn main() -> eyre::Result<()> {
+    let static_transforms = load_static_transforms()?;
+
+    // --- ROS 2 setup: node + /tf and /tf_static publishers ----------------------------------
+    let mut ros_node = BridgeNode::builder("ros2_tf_publisher").build()?;
+    dora_ros2_bridge::rosout::init_tracing(&mut ros_node)?;
+    info!("starting ROS2 TF publisher node");
+    let mut tf_publisher = TfPublisher::new(&mut ros_node)?;
+    for transform in &static_transforms {
+        info!("static transform {} -> {}", transform.parent, transform.child);
+    }
+    // a managed node publishes them on activation
+    tf_publisher.publish_static_config(&static_transforms)?;
+    let diagnostics = Diagnostics::new(&mut ros_node, DiagnosticsConfig::from_env()?)?;
+    let tf_diagnostics = diagnostics.channel("transforms");
+    let lifecycle = ros_node.lifecycle().clone();
+
+    // --- Dora: init and process events ------------------------------------------------------
+    let (_node, dora_events) = dora_ros2_bridge::rosout::init_dora_node()?;
+
+    ros_node.run_with(dora_events, stream::empty::<()>(), |event| {
+        match event {
+            BridgeEvent::Dora(Event::Input { id, metadata: _, data }) => match id.as_str() {
+                // dynamic transforms -> /tf
+                // /tf stays inactive until a managed node is activated
+                "transforms" if !lifecycle.is_active() => {}
+                "transforms" => match tf_publisher.handle_input(&data) {
+                    Ok(_) => tf_diagnostics.message(data.get_array_memory_size()),
+                    Err(err) => {
+                        warn!("dropping transforms: {err:?}");
+                        tf_diagnostics.error(format!("{err:#}"));
+                    }
+                },
+                // additional static transforms -> /tf_static
+                "static_transforms" => match tf_publisher.handle_static_input(&data) {
+                    Ok(count) => info!("added {count} static transforms"),
+                    Err(err) => {
+                        warn!("dropping static transforms: {err:?}");
+                        tf_diagnostics.error(format!("{err:#}"));
+                    }
+                },
+                other => warn!("Ignoring unexpected input `{other}`"),
+            },
+            BridgeEvent::Dora(other) => warn!("Received unexpected input: {other:?}"),
+            // /tf_static is latched, publish the transforms added while inactive
+            BridgeEvent::Lifecycle(transition) if transition.goal == LifecycleState::Active => {
+                if tf_publisher.publish_pending_static()? {
+                    info!("published static transforms on activation");
+                }
+            }
+            BridgeEvent::Parameter(_) | BridgeEvent::Lifecycle(_) | BridgeEvent::External(()) => {}
+        }
+        Ok(())
+    })
+}
+
+/// Load static transforms from `ROS2_STATIC_TRANSFORMS_FILE` or `ROS2_STATIC_TRANSFORMS`.
+///
+/// Static transforms are optional, no transforms are published if neither is set.
+fn load_static_transforms() -> eyre::Result<Vec<StaticTransform>> {
+    if std::env::var_os(STATIC_TRANSFORMS_FILE_ENV).is_none()
+        && std::env::var_os(STATIC_TRANSFORMS_ENV).is_none()
+    {
+        return Ok(Vec::new());
+    }
+    load_yaml(STATIC_TRANSFORMS_FILE_ENV, STATIC_TRANSFORMS_ENV, "static transforms")
+}